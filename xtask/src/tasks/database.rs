@@ -120,7 +120,7 @@ fn wait_for_postgres() -> Result<(), anyhow::Error> {
     let mut check_online = Command::new("psql");
     let check_online = check_online
         .current_dir(project_root())
-        .env("PGPASSWORD", &db_config.password())
+        .env("PGPASSWORD", db_config.password())
         .args([
             "-h",
             "localhost",
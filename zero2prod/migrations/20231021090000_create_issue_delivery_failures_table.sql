@@ -0,0 +1,15 @@
+-- Remember why a delivery last failed so it survives into the dead-letter table
+ALTER TABLE issue_delivery_queue
+ADD COLUMN last_error TEXT,
+ADD COLUMN queued_at timestamptz NOT NULL DEFAULT now();
+
+CREATE TABLE issue_delivery_failures (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    retries INTEGER NOT NULL,
+    last_error TEXT NOT NULL,
+    queued_at timestamptz NOT NULL,
+    failed_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (newsletter_issue_id, subscriber_email)
+);
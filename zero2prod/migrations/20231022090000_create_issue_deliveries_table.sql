@@ -0,0 +1,13 @@
+-- One row per subscriber and issue, holding how their delivery ended
+CREATE TABLE issue_deliveries (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    -- 'sent', 'failed' or 'skipped'
+    outcome TEXT NOT NULL,
+    provider_message_id TEXT,
+    error TEXT,
+    sent_at timestamptz,
+    recorded_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (newsletter_issue_id, subscriber_email)
+);
@@ -0,0 +1,22 @@
+-- Events the email provider reports back about the emails we sent
+CREATE TABLE email_events (
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    -- Empty when the recipient is not, or no longer, a subscriber
+    subscriber_id uuid REFERENCES subscriptions (id),
+    recipient TEXT NOT NULL,
+    provider_message_id TEXT,
+    -- 'delivery', 'bounce' or 'spam_complaint'
+    event_type TEXT NOT NULL,
+    description TEXT NOT NULL,
+    occurred_at timestamptz NOT NULL,
+    received_at timestamptz NOT NULL DEFAULT now()
+);
+
+-- The latest event reported for each entry of the delivery log
+ALTER TABLE issue_deliveries
+ADD COLUMN provider_status TEXT,
+ADD COLUMN provider_status_at timestamptz;
+
+CREATE INDEX issue_deliveries_provider_message_id_idx
+    ON issue_deliveries (provider_message_id);
@@ -0,0 +1,14 @@
+-- Issues start out as drafts and only go out once they are published
+ALTER TABLE newsletter_issues
+ADD COLUMN status TEXT NOT NULL DEFAULT 'draft',
+ADD COLUMN created_at timestamptz NOT NULL DEFAULT now(),
+ADD COLUMN updated_at timestamptz NOT NULL DEFAULT now(),
+ALTER COLUMN published_at DROP NOT NULL,
+ALTER COLUMN published_at TYPE timestamptz USING published_at::timestamptz;
+
+-- Every issue stored so far was published as soon as it was written
+UPDATE newsletter_issues
+SET
+    status = 'published',
+    created_at = published_at,
+    updated_at = published_at;
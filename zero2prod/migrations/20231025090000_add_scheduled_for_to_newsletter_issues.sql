@@ -0,0 +1,6 @@
+-- When a scheduled issue should go out
+ALTER TABLE newsletter_issues ADD COLUMN scheduled_for timestamptz;
+
+CREATE INDEX newsletter_issues_scheduled_for_idx
+    ON newsletter_issues (scheduled_for)
+    WHERE status = 'scheduled';
@@ -0,0 +1,4 @@
+-- Serves the public archive, newest first
+CREATE INDEX newsletter_issues_published_at_idx
+    ON newsletter_issues (published_at DESC)
+    WHERE status = 'published';
@@ -0,0 +1,19 @@
+ALTER TABLE users
+    ADD COLUMN role TEXT NOT NULL DEFAULT 'editor'
+        CHECK (role IN ('owner', 'editor', 'viewer')),
+    ADD COLUMN email TEXT NULL UNIQUE,
+    ADD COLUMN disabled_at timestamptz NULL,
+    ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
+
+-- Whoever could already log in had full control, so they keep it
+UPDATE users SET role = 'owner';
+
+CREATE TABLE user_invitations (
+    invitation_token TEXT PRIMARY KEY,
+    username TEXT NOT NULL,
+    email TEXT NOT NULL,
+    role TEXT NOT NULL CHECK (role IN ('owner', 'editor', 'viewer')),
+    invited_by uuid NULL REFERENCES users (user_id) ON DELETE SET NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    expires_at timestamptz NOT NULL
+);
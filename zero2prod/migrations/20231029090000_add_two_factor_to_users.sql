@@ -0,0 +1,13 @@
+-- A secret without `totp_enabled_at` belongs to an enrollment that hasn't been verified yet
+ALTER TABLE users
+    ADD COLUMN totp_secret TEXT NULL,
+    ADD COLUMN totp_enabled_at timestamptz NULL,
+    ADD COLUMN totp_last_used_step BIGINT NULL;
+
+CREATE TABLE recovery_codes (
+    recovery_code_id uuid PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    used_at timestamptz NULL
+);
+CREATE INDEX recovery_codes_user_id_idx ON recovery_codes (user_id);
@@ -0,0 +1,12 @@
+-- Bumped to end every session of a user at once, e.g. after their password was reset
+ALTER TABLE users ADD COLUMN session_generation INTEGER NOT NULL DEFAULT 0;
+
+-- Only a hash of each token is kept, so a leaked table can't be used to reset passwords
+CREATE TABLE password_reset_tokens (
+    token_hash TEXT PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    expires_at timestamptz NOT NULL,
+    used_at timestamptz NULL
+);
+CREATE INDEX password_reset_tokens_user_id_idx ON password_reset_tokens (user_id);
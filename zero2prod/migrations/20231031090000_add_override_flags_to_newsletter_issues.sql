@@ -0,0 +1,8 @@
+-- Which bodies of an issue were written by hand instead of being generated from its Markdown.
+-- Existing issues keep whatever they store, so they count as written by hand
+ALTER TABLE newsletter_issues
+    ADD COLUMN html_overridden BOOLEAN NOT NULL DEFAULT TRUE,
+    ADD COLUMN text_overridden BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE newsletter_issues
+    ALTER COLUMN html_overridden SET DEFAULT FALSE,
+    ALTER COLUMN text_overridden SET DEFAULT FALSE;
@@ -0,0 +1,5 @@
+-- Removing a subscriber keeps the events reported for them, they still carry the recipient
+ALTER TABLE email_events
+DROP CONSTRAINT email_events_subscriber_id_fkey,
+ADD CONSTRAINT email_events_subscriber_id_fkey
+    FOREIGN KEY (subscriber_id) REFERENCES subscriptions (id) ON DELETE SET NULL;
@@ -1,7 +1,24 @@
+mod basic;
 mod middleware;
 mod password;
+mod role;
+mod throttle;
+mod totp;
+mod two_factor;
 mod user;
 
-pub use middleware::{reject_anonymous_users, UserId};
-pub use password::{change_password, validate_credentials, AuthError, Credentials};
-pub use user::get_username;
+pub use basic::basic_authentication;
+pub use middleware::{reject_anonymous_users, reject_non_owners, reject_viewers, UserId};
+pub use password::{
+    change_password, compute_password_hash, validate_credentials, validate_new_password, AuthError,
+    Credentials, PasswordHashing,
+};
+pub use role::Role;
+pub use throttle::{ClientIp, Lockout, LoginLock, LoginThrottle};
+pub use totp::{qr_code_svg, TotpSecret};
+pub use two_factor::{
+    confirm_two_factor_enrollment, disable_two_factor, get_two_factor_status,
+    remaining_recovery_codes, start_two_factor_enrollment, verify_second_factor, TwoFactorStatus,
+    RECOVERY_CODE_COUNT,
+};
+pub use user::{get_session_generation, get_username};
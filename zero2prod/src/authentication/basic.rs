@@ -0,0 +1,62 @@
+use anyhow::Context;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use http::HeaderMap;
+use secrecy::Secret;
+
+use super::Credentials;
+
+/// Extract the credentials of an `Authorization: Basic` header.
+pub fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+    let header_value = headers
+        .get("Authorization")
+        .context("The 'Authorization' header was missing.")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes = STANDARD
+        .decode(base64encoded_segment)
+        .context("Failed to base64-decode 'Basic' credentials.")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .context("The decoded credential string is not valid UTF8.")?;
+
+    let (username, password) = decoded_credentials
+        .split_once(':')
+        .context("A password must be provided in 'Basic' auth.")?;
+    Ok(Credentials {
+        username: username.to_string(),
+        password: Secret::new(password.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+    use http::{HeaderMap, HeaderValue};
+    use secrecy::ExposeSecret;
+
+    use super::basic_authentication;
+
+    #[test]
+    fn basic_credentials_are_decoded() {
+        let mut headers = HeaderMap::new();
+        // "postmark:pass:word"
+        headers.insert(
+            "Authorization",
+            HeaderValue::from_static("Basic cG9zdG1hcms6cGFzczp3b3Jk"),
+        );
+
+        let credentials = assert_ok!(basic_authentication(&headers));
+        assert_eq!(credentials.username, "postmark");
+        assert_eq!(credentials.password.expose_secret(), "pass:word");
+    }
+
+    #[test]
+    fn other_authorization_schemes_are_rejected() {
+        let mut headers = HeaderMap::new();
+        headers.insert("Authorization", HeaderValue::from_static("Bearer token"));
+
+        assert_err!(basic_authentication(&headers));
+    }
+}
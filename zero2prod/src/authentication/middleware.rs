@@ -1,32 +1,119 @@
 use std::ops::Deref;
 
+use anyhow::Context;
 use axum::{
+    extract::State,
     middleware::Next,
     response::{IntoResponse, Redirect, Response},
+    Extension,
 };
+use axum_extra::response::Html;
 use axum_session::SessionRedisPool;
-use http::Request;
+use http::{Request, StatusCode};
+use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::session_state::TypedSession;
+use crate::{e500, session_state::TypedSession};
+
+use super::Role;
 
 pub async fn reject_anonymous_users<B>(
+    State(pool): State<PgPool>,
     session: TypedSession<SessionRedisPool>,
     mut request: Request<B>,
     next: Next<B>,
-) -> Result<Response, impl IntoResponse> {
-    match session.get_user_id() {
-        Some(uid) => {
+) -> Result<Response, Response> {
+    let Some(uid) = session.get_user_id() else {
+        tracing::error!("User has not logged in.");
+        return Err(Redirect::to("/login").into_response());
+    };
+    // Checked on every request so disabling a user, or ending all their sessions,
+    // locks them out straight away
+    match get_active_user(uid, &pool)
+        .await
+        .map_err(|e| e500(e).into_response())?
+    {
+        Some((role, session_generation))
+            if session.get_session_generation() == session_generation =>
+        {
             request.extensions_mut().insert(UserId(uid));
+            request.extensions_mut().insert(role);
             Ok(next.run(request).await)
         }
-        None => {
-            tracing::error!("User has not logged in.");
-            Err(Redirect::to("/login"))
+        _ => {
+            tracing::warn!(user_id = %uid, "A session that is no longer valid was used.");
+            session.log_out();
+            Err(Redirect::to("/login").into_response())
         }
     }
 }
 
+/// Only let editors and owners through. Must run after `reject_anonymous_users`.
+pub async fn reject_viewers<B>(
+    Extension(role): Extension<Role>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if role < Role::Editor {
+        return forbidden(Role::Editor, role);
+    }
+    next.run(request).await
+}
+
+/// Only let owners through. Must run after `reject_anonymous_users`.
+pub async fn reject_non_owners<B>(
+    Extension(role): Extension<Role>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if role < Role::Owner {
+        return forbidden(Role::Owner, role);
+    }
+    next.run(request).await
+}
+
+fn forbidden(required: Role, role: Role) -> Response {
+    tracing::warn!(%role, %required, "User lacks the role for this page.");
+    let page = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Forbidden</title>
+</head>
+<body>
+    <p>Only users with the {required} role can do this. You are a {role}.</p>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#
+    );
+    (StatusCode::FORBIDDEN, Html(page)).into_response()
+}
+
+/// The role and session generation of a user who may still log in.
+#[tracing::instrument(name = "Get active user", skip(pool))]
+async fn get_active_user(
+    user_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<(Role, i32)>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT role, session_generation
+        FROM users
+        WHERE user_id = $1 AND disabled_at IS NULL
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch the role of the user.")?;
+    row.map(|row| {
+        let role = Role::try_from(row.role).map_err(anyhow::Error::msg)?;
+        Ok((role, row.session_generation))
+    })
+    .transpose()
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct UserId(Uuid);
 
@@ -5,6 +5,7 @@ use argon2::{
 };
 use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
+use tracing::Instrument;
 
 use crate::{error_chain_fmt, telemetry::spawn_blocking_with_tracing};
 
@@ -28,20 +29,53 @@ pub struct Credentials {
     pub(crate) password: Secret<String>,
 }
 
-#[tracing::instrument(name = "Validate credentials", skip(credentials, pool))]
+/// How passwords are hashed. Built from `PasswordHashingSettings`.
+#[derive(Clone)]
+pub struct PasswordHashing {
+    params: Params,
+    /// Checked against when the username is unknown, so that takes as long as a wrong password
+    fallback_hash: Secret<String>,
+}
+
+impl PasswordHashing {
+    pub fn new(params: Params) -> Result<Self, anyhow::Error> {
+        let fallback_password = Secret::new(uuid::Uuid::new_v4().to_string());
+        let fallback_hash = compute_password_hash(fallback_password, params.clone())
+            .context("Failed to compute the fallback password hash.")?;
+        Ok(Self {
+            params,
+            fallback_hash,
+        })
+    }
+
+    pub fn params(&self) -> Params {
+        self.params.clone()
+    }
+
+    /// Whether a stored hash was made with another algorithm or weaker parameters than
+    /// the ones we hash with now.
+    fn is_outdated(&self, password_hash: &PasswordHash) -> bool {
+        let Ok(params) = Params::try_from(password_hash) else {
+            return true;
+        };
+        password_hash.algorithm != argon2::Algorithm::Argon2id.ident()
+            || password_hash.version != Some(Version::V0x13.into())
+            || params.m_cost() != self.params.m_cost()
+            || params.t_cost() != self.params.t_cost()
+            || params.p_cost() != self.params.p_cost()
+    }
+}
+
+#[tracing::instrument(name = "Validate credentials", skip(credentials, hashing, pool))]
 pub async fn validate_credentials(
     credentials: Credentials,
+    hashing: &PasswordHashing,
     pool: &PgPool,
 ) -> Result<uuid::Uuid, AuthError> {
-    //Create a fallback password hash to enforce doing the same amount
+    //Use a fallback password hash to enforce doing the same amount
     //of work whether we have a user account in the db or not.
     let mut user_id = None;
-    let mut expected_password_hash = Secret::new(
-        "$argon2id$v=19$m=15000,t=2,p=1$\
-        gZiV/M1gPc22ElAH/Jh1Hw$\
-        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
-            .to_string(),
-    );
+    let mut expected_password_hash = hashing.fallback_hash.clone();
 
     if let Some((stored_user_id, stored_password_hash)) =
         get_stored_credentials(&credentials.username, pool)
@@ -52,14 +86,76 @@ pub async fn validate_credentials(
         expected_password_hash = stored_password_hash
     }
 
-    spawn_blocking_with_tracing(move || {
-        verify_password_hash(expected_password_hash, credentials.password)
+    let verifying_hash = expected_password_hash.clone();
+    let password = spawn_blocking_with_tracing(move || {
+        verify_password_hash(verifying_hash, credentials.password.clone())
+            .map(|_| credentials.password)
     })
     .await
     .context("Failed to spawn blocking task.")
     .map_err(AuthError::UnexpectedError)??;
 
-    user_id.ok_or_else(|| AuthError::InvalidCredentials(anyhow::anyhow!("Unknown username.")))
+    let user_id = user_id
+        .ok_or_else(|| AuthError::InvalidCredentials(anyhow::anyhow!("Unknown username.")))?;
+    rehash_if_outdated(user_id, expected_password_hash, password, hashing, pool);
+    Ok(user_id)
+}
+
+/// Upgrade a hash made with outdated parameters, now that we know the password.
+///
+/// This runs in the background, so raising the cost doesn't slow down the login that
+/// triggers it.
+fn rehash_if_outdated(
+    user_id: uuid::Uuid,
+    stored_password_hash: Secret<String>,
+    password: Secret<String>,
+    hashing: &PasswordHashing,
+    pool: &PgPool,
+) {
+    let outdated = PasswordHash::new(stored_password_hash.expose_secret())
+        .map(|password_hash| hashing.is_outdated(&password_hash))
+        .unwrap_or(true);
+    if !outdated {
+        return;
+    }
+
+    let params = hashing.params();
+    let pool = pool.clone();
+    tokio::spawn(
+        async move {
+            let result = async {
+                let password_hash =
+                    spawn_blocking_with_tracing(move || compute_password_hash(password, params))
+                        .await?
+                        .context("Failed to hash password")?;
+                // Leave the hash alone if the password changed in the meantime
+                sqlx::query!(
+                    r#"
+                    UPDATE users
+                    SET password_hash = $1
+                    WHERE user_id = $2 AND password_hash = $3
+                    "#,
+                    password_hash.expose_secret(),
+                    user_id,
+                    stored_password_hash.expose_secret()
+                )
+                .execute(&pool)
+                .await
+                .context("Failed to store the upgraded password hash.")?;
+                Ok::<_, anyhow::Error>(())
+            }
+            .await;
+            match result {
+                Ok(()) => tracing::info!("Upgraded an outdated password hash."),
+                Err(e) => tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to upgrade an outdated password hash."
+                ),
+            }
+        }
+        .instrument(tracing::info_span!("Rehash password", %user_id)),
+    );
 }
 
 #[tracing::instrument(
@@ -92,7 +188,7 @@ async fn get_stored_credentials(
         r#"
         SELECT user_id, password_hash
         FROM users
-        WHERE username = $1
+        WHERE username = $1 AND disabled_at IS NULL
         "#,
         username,
     )
@@ -104,15 +200,18 @@ async fn get_stored_credentials(
     Ok(row)
 }
 
-#[tracing::instrument(name = "Change password", skip(password, pool))]
+#[tracing::instrument(name = "Change password", skip(password, hashing, pool))]
 pub async fn change_password(
     user_id: uuid::Uuid,
     password: Secret<String>,
+    hashing: &PasswordHashing,
     pool: &PgPool,
 ) -> Result<(), anyhow::Error> {
-    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
-        .await?
-        .context("Failed to hash password")?;
+    let params = hashing.params();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, params))
+            .await?
+            .context("Failed to hash password")?;
 
     sqlx::query!(
         r#"
@@ -129,15 +228,60 @@ pub async fn change_password(
     Ok(())
 }
 
-fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
+/// Check a password someone chose for themselves against our length rule.
+pub fn validate_new_password(password: &Secret<String>) -> Result<(), &'static str> {
+    let length = password.expose_secret().chars().count();
+    if !(12..=128).contains(&length) {
+        return Err("The new password should be between 12 and 128 characters long.");
+    }
+    Ok(())
+}
+
+pub fn compute_password_hash(
+    password: Secret<String>,
+    params: Params,
+) -> Result<Secret<String>, anyhow::Error> {
     let salt = SaltString::generate(&mut rand::thread_rng());
-    let password_hash = Argon2::new(
-        argon2::Algorithm::Argon2id,
-        Version::V0x13,
-        Params::new(15000, 2, 1, None).unwrap(),
-    )
-    .hash_password(password.expose_secret().as_bytes(), &salt)?
-    .to_string();
+    let password_hash = Argon2::new(argon2::Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password(password.expose_secret().as_bytes(), &salt)?
+        .to_string();
 
     Ok(Secret::new(password_hash))
 }
+
+#[cfg(test)]
+mod tests {
+    use argon2::{Params, PasswordHash};
+    use claims::assert_ok;
+    use secrecy::{ExposeSecret, Secret};
+
+    use super::{compute_password_hash, PasswordHashing};
+
+    fn hash_with(params: Params) -> String {
+        let password_hash = compute_password_hash(Secret::new("password".into()), params);
+        assert_ok!(password_hash).expose_secret().clone()
+    }
+
+    #[test]
+    fn hashes_made_with_the_current_params_are_kept() {
+        let hashing = assert_ok!(PasswordHashing::new(Params::new(64, 1, 1, None).unwrap()));
+        let password_hash = hash_with(Params::new(64, 1, 1, None).unwrap());
+        assert!(!hashing.is_outdated(&PasswordHash::new(&password_hash).unwrap()));
+    }
+
+    #[test]
+    fn hashes_made_with_other_params_or_algorithms_are_outdated() {
+        let hashing = assert_ok!(PasswordHashing::new(Params::new(64, 2, 1, None).unwrap()));
+        for params in [
+            Params::new(32, 2, 1, None).unwrap(),
+            Params::new(64, 1, 1, None).unwrap(),
+            Params::new(64, 2, 2, None).unwrap(),
+        ] {
+            let password_hash = hash_with(params);
+            assert!(hashing.is_outdated(&PasswordHash::new(&password_hash).unwrap()));
+        }
+        let argon2i =
+            "$argon2i$v=19$m=64,t=2,p=1$c29tZXNhbHQ$iWh06vD8Fy27wf9npn6FXWiCX4K6pW6Ue1Bnzz07Z8A";
+        assert!(hashing.is_outdated(&PasswordHash::new(argon2i).unwrap()));
+    }
+}
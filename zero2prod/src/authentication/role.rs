@@ -0,0 +1,61 @@
+/// What a user is allowed to do in the admin section. Every role can do what the ones below
+/// it can.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    /// Can look at issues and delivery reports.
+    Viewer,
+    /// Can also write, publish and send issues.
+    Editor,
+    /// Can also manage the other users.
+    Owner,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Owner, Role::Editor, Role::Viewer];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Owner => "owner",
+        }
+    }
+}
+
+impl TryFrom<String> for Role {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Role::ALL
+            .into_iter()
+            .find(|role| role.as_str() == value)
+            .ok_or_else(|| format!("{} is not a valid role.", value))
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok_eq};
+
+    use super::Role;
+
+    #[test]
+    fn roles_round_trip_through_their_names() {
+        for role in Role::ALL {
+            assert_ok_eq!(Role::try_from(role.to_string()), role);
+        }
+        assert_err!(Role::try_from("admin".to_string()));
+    }
+
+    #[test]
+    fn owners_can_do_everything_editors_can() {
+        assert!(Role::Owner > Role::Editor);
+        assert!(Role::Editor > Role::Viewer);
+    }
+}
@@ -0,0 +1,348 @@
+use std::{convert::Infallible, net::SocketAddr};
+
+use anyhow::Context;
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRef, FromRequestParts},
+};
+use redis::AsyncCommands;
+
+use crate::configuration::LoginThrottleSettings;
+
+const KEY_PREFIX: &str = "login_failures:";
+const RESET_KEY_PREFIX: &str = "password_resets:";
+
+/// Counts failed logins per username and per client address in redis, and refuses further
+/// attempts once either count reaches its limit.
+///
+/// The fallback password hash in `validate_credentials` stops enumeration, but not guessing.
+///
+/// Anyone can lock a username by guessing at it, including the owner's. That is accepted:
+/// the lock ends on its own, an owner can lift it from the admin pages, and resetting the
+/// password through an emailed link lifts it too.
+#[derive(Clone)]
+pub struct LoginThrottle {
+    redis: redis::Client,
+    settings: LoginThrottleSettings,
+}
+
+/// Why a login attempt was refused before checking the credentials.
+#[derive(Debug)]
+pub struct Lockout {
+    retry_after_seconds: u64,
+}
+
+impl std::fmt::Display for Lockout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let minutes = self.retry_after_seconds.div_ceil(60).max(1);
+        write!(
+            f,
+            "Too many failed login attempts. Try again in {} minute{}.",
+            minutes,
+            if minutes == 1 { "" } else { "s" }
+        )
+    }
+}
+
+/// A username or address with failed logins on record.
+pub struct LoginLock {
+    pub key: String,
+    pub failures: u32,
+    pub expires_in_seconds: i64,
+    pub locked: bool,
+}
+
+impl LoginThrottle {
+    pub fn new(redis: redis::Client, settings: LoginThrottleSettings) -> Self {
+        Self { redis, settings }
+    }
+
+    /// Count a login attempt against the username and the address, and refuse it once
+    /// either count is over its limit.
+    ///
+    /// Attempts are counted before the credentials are checked, and the count each one gets
+    /// back decides it, so concurrent guesses can't all slip in under the limit. Logins go
+    /// ahead when redis can't be reached, rather than locking everyone out.
+    #[tracing::instrument(name = "Count login attempt", skip(self))]
+    pub async fn count_attempt(&self, username: &str, client_ip: &ClientIp) -> Result<(), Lockout> {
+        let keys = self.keys(KEY_PREFIX, username, client_ip);
+        let result = async {
+            let mut connection = self.redis.get_async_connection().await?;
+            let mut pipe = redis::pipe();
+            pipe.atomic();
+            for (key, _) in &keys {
+                pipe.incr(key, 1);
+            }
+            let counts: Vec<u32> = pipe.query_async(&mut connection).await?;
+            let mut retry_after_seconds = 0;
+            for (count, (key, max_failures)) in counts.into_iter().zip(&keys) {
+                // The window starts with the first attempt and isn't extended by later ones
+                if count == 1 {
+                    connection
+                        .expire::<_, ()>(key, self.settings.lockout_seconds as usize)
+                        .await?;
+                }
+                if count > *max_failures {
+                    let ttl: i64 = connection.ttl(key).await?;
+                    retry_after_seconds = retry_after_seconds.max(ttl.max(1) as u64);
+                }
+            }
+            Ok::<_, redis::RedisError>(retry_after_seconds)
+        }
+        .await;
+        match result {
+            Ok(0) => Ok(()),
+            Ok(retry_after_seconds) => {
+                tracing::warn!("Login attempt refused, too many failures.");
+                Err(Lockout {
+                    retry_after_seconds,
+                })
+            }
+            Err(e) => {
+                tracing::error!(error.cause_chain = ?e, "Failed to count a login attempt.");
+                Ok(())
+            }
+        }
+    }
+
+    /// Take back an attempt that turned out not to be a failed login, such as a right
+    /// password that still needs a second factor.
+    #[tracing::instrument(name = "Forgive login attempt", skip(self))]
+    pub async fn forgive_attempt(&self, username: &str, client_ip: &ClientIp) {
+        let result = async {
+            let mut connection = self.redis.get_async_connection().await?;
+            for (key, _) in self.keys(KEY_PREFIX, username, client_ip) {
+                let count: i64 = connection.decr(&key, 1).await?;
+                // The count may have expired in the meantime, which leaves nothing to take back
+                if count <= 0 {
+                    connection.del::<_, ()>(&key).await?;
+                }
+            }
+            Ok::<_, redis::RedisError>(())
+        }
+        .await;
+        if let Err(e) = result {
+            tracing::error!(error.cause_chain = ?e, "Failed to forgive a login attempt.");
+        }
+    }
+
+    /// Count a password reset request and tell whether it stays within the limits.
+    ///
+    /// Every request sends an email, so all of them count, not just failed ones. They are
+    /// counted apart from failed logins, against the same limits. Requests go ahead when
+    /// redis can't be reached.
+    #[tracing::instrument(name = "Count password reset request", skip(self))]
+    pub async fn allow_password_reset(&self, username: &str, client_ip: &ClientIp) -> bool {
+        let keys = self.keys(RESET_KEY_PREFIX, username, client_ip);
+        let result = async {
+            let mut connection = self.redis.get_async_connection().await?;
+            let mut pipe = redis::pipe();
+            pipe.atomic();
+            for (key, _) in &keys {
+                pipe.incr(key, 1)
+                    .expire(key, self.settings.lockout_seconds as usize)
+                    .ignore();
+            }
+            pipe.query_async::<_, Vec<u32>>(&mut connection).await
+        }
+        .await;
+        match result {
+            Ok(counts) => {
+                let allowed = counts
+                    .iter()
+                    .zip(&keys)
+                    .all(|(count, (_, max_requests))| count <= max_requests);
+                if !allowed {
+                    tracing::warn!("Password reset refused, too many requests.");
+                }
+                allowed
+            }
+            Err(e) => {
+                tracing::error!(error.cause_chain = ?e, "Failed to count a password reset request.");
+                true
+            }
+        }
+    }
+
+    /// Forget the failures of a username once its owner got in. Failures from the address
+    /// stay, as they may have been guesses at other usernames.
+    ///
+    /// The attempt that got in still counts against the address, take it back with
+    /// [`LoginThrottle::forgive_attempt`].
+    #[tracing::instrument(name = "Record successful login", skip(self))]
+    pub async fn record_success(&self, username: &str) {
+        if let Err(e) = self.clear(&username_key(username)).await {
+            tracing::error!(error.cause_chain = ?e, "Failed to clear the failed logins.");
+        }
+    }
+
+    /// Every username and address with failed logins on record.
+    #[tracing::instrument(name = "List login locks", skip(self))]
+    pub async fn locks(&self) -> Result<Vec<LoginLock>, anyhow::Error> {
+        let mut connection = self
+            .redis
+            .get_async_connection()
+            .await
+            .context("Failed to connect to redis")?;
+        let keys: Vec<String> = {
+            let mut iter = connection
+                .scan_match::<_, String>(format!("{}*", KEY_PREFIX))
+                .await
+                .context("Failed to scan for failed logins")?;
+            let mut keys = Vec::new();
+            while let Some(key) = iter.next_item().await {
+                keys.push(key);
+            }
+            keys
+        };
+
+        let mut locks = Vec::new();
+        for key in keys {
+            let failures: Option<u32> = connection
+                .get(&key)
+                .await
+                .context("Failed to get the failed logins")?;
+            // The key may have expired since the scan
+            let Some(failures) = failures else {
+                continue;
+            };
+            let expires_in_seconds: i64 = connection
+                .ttl(&key)
+                .await
+                .context("Failed to get the expiry of the failed logins")?;
+            let max_failures = if key.starts_with(&username_key("")) {
+                self.settings.max_failures_per_username
+            } else {
+                self.settings.max_failures_per_ip
+            };
+            locks.push(LoginLock {
+                locked: failures >= max_failures,
+                key,
+                failures,
+                expires_in_seconds,
+            });
+        }
+        locks.sort_by(|a, b| b.failures.cmp(&a.failures).then(a.key.cmp(&b.key)));
+        Ok(locks)
+    }
+
+    /// Forget the failures recorded under `key`, lifting any lock.
+    #[tracing::instrument(name = "Clear login lock", skip(self))]
+    pub async fn clear(&self, key: &str) -> Result<(), anyhow::Error> {
+        if !key.starts_with(KEY_PREFIX) {
+            anyhow::bail!("{} does not hold failed logins", key);
+        }
+        let mut connection = self
+            .redis
+            .get_async_connection()
+            .await
+            .context("Failed to connect to redis")?;
+        connection
+            .del::<_, ()>(key)
+            .await
+            .context("Failed to delete the failed logins")?;
+        Ok(())
+    }
+
+    fn keys(&self, prefix: &str, username: &str, client_ip: &ClientIp) -> [(String, u32); 2] {
+        [
+            (
+                format!("{}username:{}", prefix, username),
+                self.settings.max_failures_per_username,
+            ),
+            (
+                format!("{}ip:{}", prefix, client_ip.0),
+                self.settings.max_failures_per_ip,
+            ),
+        ]
+    }
+}
+
+fn username_key(username: &str) -> String {
+    format!("{}username:{}", KEY_PREFIX, username)
+}
+
+/// The address a request came from, as far as we can tell.
+#[derive(Debug)]
+pub struct ClientIp(String);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientIp
+where
+    LoginThrottle: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut http::request::Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let throttle = LoginThrottle::from_ref(state);
+        let forwarded = throttle
+            .settings
+            .client_ip_header
+            .as_deref()
+            .and_then(|header| parts.headers.get(header))
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| forwarded_client_ip(value, throttle.settings.trusted_proxies));
+        if let Some(ip) = forwarded {
+            return Ok(Self(ip.to_string()));
+        }
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| address.ip().to_string());
+        Ok(Self(peer.unwrap_or_else(|| "unknown".into())))
+    }
+}
+
+/// Pick the client address out of a forwarding header.
+///
+/// Every trusted proxy appends the address it saw, so the entry added by the outermost one
+/// is the client. `None` when the header has fewer entries than there are proxies.
+fn forwarded_client_ip(value: &str, trusted_proxies: usize) -> Option<&str> {
+    value
+        .rsplit(',')
+        .nth(trusted_proxies.max(1) - 1)
+        .map(str::trim)
+        .filter(|ip| !ip.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_none, assert_some_eq};
+
+    use super::{forwarded_client_ip, Lockout};
+
+    #[test]
+    fn the_entry_added_by_the_outermost_trusted_proxy_is_the_client() {
+        let header = "6.6.6.6, 203.0.113.7, 10.0.0.2";
+        assert_some_eq!(forwarded_client_ip(header, 1), "10.0.0.2");
+        assert_some_eq!(forwarded_client_ip(header, 2), "203.0.113.7");
+        assert_none!(forwarded_client_ip("203.0.113.7", 2));
+        assert_none!(forwarded_client_ip("", 1));
+    }
+
+    #[test]
+    fn the_lockout_message_rounds_up_to_whole_minutes() {
+        let message = |retry_after_seconds| {
+            Lockout {
+                retry_after_seconds,
+            }
+            .to_string()
+        };
+        assert_eq!(
+            message(1),
+            "Too many failed login attempts. Try again in 1 minute."
+        );
+        assert_eq!(
+            message(61),
+            "Too many failed login attempts. Try again in 2 minutes."
+        );
+        assert_eq!(
+            message(900),
+            "Too many failed login attempts. Try again in 15 minutes."
+        );
+    }
+}
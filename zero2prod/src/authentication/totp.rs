@@ -0,0 +1,194 @@
+use base32::Alphabet;
+use hmac::{Hmac, Mac};
+use qrcode::{render::svg, QrCode};
+use rand::{distributions::Alphanumeric, thread_rng, Rng, RngCore};
+use secrecy::{ExposeSecret, Secret};
+use sha1::Sha1;
+
+type HmacSha1 = Hmac<Sha1>;
+
+/// How long each code is valid for, in seconds.
+const STEP_SECONDS: i64 = 30;
+const DIGITS: u32 = 6;
+/// Codes from this many steps either side of now are accepted, to allow for clock drift.
+const ALLOWED_DRIFT: i64 = 1;
+const ALPHABET: Alphabet = Alphabet::RFC4648 { padding: false };
+
+/// The shared secret behind an RFC 6238 one-time password, base32 encoded as
+/// authenticator apps expect it.
+#[derive(Clone, Debug)]
+pub struct TotpSecret(Secret<String>);
+
+impl TotpSecret {
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; 20];
+        thread_rng().fill_bytes(&mut bytes);
+        Self(Secret::new(base32::encode(ALPHABET, &bytes)))
+    }
+
+    pub fn parse(secret: String) -> Result<Self, String> {
+        match base32::decode(ALPHABET, &secret) {
+            Some(bytes) if !bytes.is_empty() => Ok(Self(Secret::new(secret))),
+            _ => Err("A TOTP secret must be base32 encoded.".into()),
+        }
+    }
+
+    /// The `otpauth://` URI authenticator apps scan to enroll.
+    pub fn otpauth_uri(&self, issuer: &str, account: &str) -> String {
+        let issuer = percent_encode(issuer);
+        format!(
+            "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}\
+            &algorithm=SHA1&digits={DIGITS}&period={STEP_SECONDS}",
+            account = percent_encode(account),
+            secret = self.0.expose_secret(),
+        )
+    }
+
+    /// Check a code typed in at `unix_time`, returning the time step it belongs to.
+    ///
+    /// Callers must refuse steps that were already used, or a code seen over someone's
+    /// shoulder could be replayed within its window.
+    pub fn verify(&self, code: &str, unix_time: i64) -> Option<i64> {
+        let code = code.trim();
+        if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let current_step = unix_time.div_euclid(STEP_SECONDS);
+        (current_step - ALLOWED_DRIFT..=current_step + ALLOWED_DRIFT)
+            .find(|&step| self.code_at(step) == code)
+    }
+
+    /// The code an authenticator app shows at `unix_time`.
+    pub fn code_at_time(&self, unix_time: i64) -> String {
+        self.code_at(unix_time.div_euclid(STEP_SECONDS))
+    }
+
+    fn code_at(&self, step: i64) -> String {
+        let key = base32::decode(ALPHABET, self.0.expose_secret())
+            .expect("A TOTP secret is checked to be base32 when it is created");
+        let mut mac = HmacSha1::new_from_slice(&key).expect("HMAC can take a key of any size");
+        mac.update(&step.to_be_bytes());
+        let digest = mac.finalize().into_bytes();
+        // Dynamic truncation, as described in RFC 4226
+        let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+        let binary = u32::from_be_bytes([
+            digest[offset] & 0x7f,
+            digest[offset + 1],
+            digest[offset + 2],
+            digest[offset + 3],
+        ]);
+        format!(
+            "{:0width$}",
+            binary % 10u32.pow(DIGITS),
+            width = DIGITS as usize
+        )
+    }
+}
+
+impl ExposeSecret<String> for TotpSecret {
+    fn expose_secret(&self) -> &String {
+        self.0.expose_secret()
+    }
+}
+
+/// Render an `otpauth://` URI as an inline svg QR code.
+pub fn qr_code_svg(uri: &str) -> String {
+    QrCode::new(uri.as_bytes())
+        .expect("An otpauth URI fits in a QR code")
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .build()
+}
+
+/// How many characters a recovery code has, leaving out the dash in the middle.
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+/// Single-use codes that stand in for the authenticator app when it is lost.
+pub fn generate_recovery_codes(count: usize) -> Vec<String> {
+    let mut rng = thread_rng();
+    (0..count)
+        .map(|_| {
+            let code: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+                .map(|c| char::from(c).to_ascii_lowercase())
+                .take(RECOVERY_CODE_LENGTH)
+                .collect();
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+/// Recovery codes are compared without the dash and regardless of case.
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.trim().replace('-', "").to_ascii_lowercase()
+}
+
+/// Whether `code` has the shape of a recovery code at all. Checking one costs a hash per
+/// unused recovery code, which a mistyped authenticator code shouldn't.
+pub fn looks_like_recovery_code(code: &str) -> bool {
+    let code = normalize_recovery_code(code);
+    code.len() == RECOVERY_CODE_LENGTH && code.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_none, assert_ok, assert_some_eq};
+
+    use super::{generate_recovery_codes, looks_like_recovery_code, TotpSecret, ALPHABET};
+
+    fn rfc_6238_secret() -> TotpSecret {
+        let secret = base32::encode(ALPHABET, b"12345678901234567890");
+        assert_ok!(TotpSecret::parse(secret))
+    }
+
+    #[test]
+    fn codes_match_the_rfc_6238_test_vectors() {
+        // The RFC lists 8 digit codes, of which we use the last 6
+        let secret = rfc_6238_secret();
+        assert_eq!(secret.code_at(59 / 30), "287082");
+        assert_eq!(secret.code_at(1111111109 / 30), "081804");
+        assert_eq!(secret.code_at(20000000000 / 30), "353130");
+    }
+
+    #[test]
+    fn codes_from_the_neighbouring_steps_are_accepted() {
+        let secret = rfc_6238_secret();
+        assert_some_eq!(secret.verify("081804", 1111111109), 1111111109 / 30);
+        assert_some_eq!(secret.verify("081804", 1111111109 + 30), 1111111109 / 30);
+        assert_none!(secret.verify("081804", 1111111109 + 90));
+        assert_none!(secret.verify("08180", 1111111109));
+    }
+
+    #[test]
+    fn only_input_shaped_like_a_recovery_code_is_checked_as_one() {
+        for code in generate_recovery_codes(3) {
+            assert!(looks_like_recovery_code(&code));
+            assert!(looks_like_recovery_code(
+                &code.to_uppercase().replace('-', "")
+            ));
+        }
+        assert!(!looks_like_recovery_code("123456"));
+        assert!(!looks_like_recovery_code("abcde-fghi!"));
+        assert!(!looks_like_recovery_code("abcde-fghij-k"));
+    }
+
+    #[test]
+    fn the_otpauth_uri_escapes_the_account_name() {
+        let secret = rfc_6238_secret();
+        let uri = secret.otpauth_uri("Our newsletter", "ursula@example.com");
+        assert!(uri.starts_with(
+            "otpauth://totp/Our%20newsletter:ursula%40example.com?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&"
+        ));
+    }
+}
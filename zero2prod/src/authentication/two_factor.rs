@@ -0,0 +1,300 @@
+use anyhow::Context;
+use argon2::{Argon2, Params, PasswordHash, PasswordVerifier};
+use chrono::Utc;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::telemetry::spawn_blocking_with_tracing;
+
+use super::{
+    compute_password_hash,
+    totp::{
+        generate_recovery_codes, looks_like_recovery_code, normalize_recovery_code, TotpSecret,
+    },
+};
+
+/// How many recovery codes are handed out when two-factor authentication is turned on.
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Recovery codes are long and random, so they don't need the cost of a password hash.
+///
+/// The cost is fixed rather than following the password settings, since checking a code
+/// may hash it once for every unused code the user has.
+fn recovery_code_hash_params() -> Params {
+    Params::new(4096, 1, 1, None).expect("Recovery code hash parameters are valid")
+}
+
+/// Where a user stands with two-factor authentication.
+pub enum TwoFactorStatus {
+    Disabled,
+    /// A secret was generated but no code from it has been entered yet
+    Pending(TotpSecret),
+    Enabled,
+}
+
+#[tracing::instrument(name = "Get two-factor status", skip(pool))]
+pub async fn get_two_factor_status(
+    user_id: Uuid,
+    pool: &PgPool,
+) -> Result<TwoFactorStatus, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT totp_secret, totp_enabled_at
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to fetch the two-factor status of the user.")?;
+    Ok(match (row.totp_secret, row.totp_enabled_at) {
+        (Some(_), Some(_)) => TwoFactorStatus::Enabled,
+        (Some(secret), None) => {
+            TwoFactorStatus::Pending(TotpSecret::parse(secret).map_err(anyhow::Error::msg)?)
+        }
+        (None, _) => TwoFactorStatus::Disabled,
+    })
+}
+
+/// Store a fresh secret for a user to enroll with. It does nothing until confirmed.
+#[tracing::instrument(name = "Start two-factor enrollment", skip(pool))]
+pub async fn start_two_factor_enrollment(
+    user_id: Uuid,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let secret = TotpSecret::generate();
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = $1, totp_enabled_at = NULL, totp_last_used_step = NULL
+        WHERE user_id = $2 AND totp_enabled_at IS NULL
+        "#,
+        secret.expose_secret(),
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the TOTP secret.")?;
+    Ok(())
+}
+
+/// Turn two-factor authentication on once the user proved their app has the secret.
+///
+/// Returns the recovery codes in the clear, which is the only time they are ever seen.
+#[tracing::instrument(name = "Confirm two-factor enrollment", skip(code, pool))]
+pub async fn confirm_two_factor_enrollment(
+    user_id: Uuid,
+    code: &str,
+    pool: &PgPool,
+) -> Result<Option<Vec<String>>, anyhow::Error> {
+    let TwoFactorStatus::Pending(secret) = get_two_factor_status(user_id, pool).await? else {
+        return Ok(None);
+    };
+    let Some(step) = secret.verify(code, Utc::now().timestamp()) else {
+        return Ok(None);
+    };
+
+    let recovery_codes = generate_recovery_codes(RECOVERY_CODE_COUNT);
+    let codes = recovery_codes.clone();
+    let code_hashes = spawn_blocking_with_tracing(move || {
+        codes
+            .into_iter()
+            .map(|code| {
+                compute_password_hash(
+                    Secret::new(normalize_recovery_code(&code)),
+                    recovery_code_hash_params(),
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()
+    })
+    .await?
+    .context("Failed to hash the recovery codes.")?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_enabled_at = now(), totp_last_used_step = $1
+        WHERE user_id = $2
+        "#,
+        step,
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to enable two-factor authentication.")?;
+    sqlx::query!("DELETE FROM recovery_codes WHERE user_id = $1", user_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete the old recovery codes.")?;
+    for code_hash in code_hashes {
+        sqlx::query!(
+            r#"
+            INSERT INTO recovery_codes (recovery_code_id, user_id, code_hash)
+            VALUES ($1, $2, $3)
+            "#,
+            Uuid::new_v4(),
+            user_id,
+            code_hash.expose_secret()
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to store a recovery code.")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the two-factor enrollment.")?;
+    Ok(Some(recovery_codes))
+}
+
+#[tracing::instrument(name = "Disable two-factor authentication", skip(pool))]
+pub async fn disable_two_factor(user_id: Uuid, pool: &PgPool) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_used_step = NULL
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to disable two-factor authentication.")?;
+    sqlx::query!("DELETE FROM recovery_codes WHERE user_id = $1", user_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete the recovery codes.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit disabling two-factor authentication.")?;
+    Ok(())
+}
+
+/// Check the second factor of a login: a code from the authenticator app, or one of the
+/// recovery codes. Either can only be used once.
+#[tracing::instrument(name = "Verify second factor", skip(code, pool))]
+pub async fn verify_second_factor(
+    user_id: Uuid,
+    code: &str,
+    pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT totp_secret AS "totp_secret!"
+        FROM users
+        WHERE user_id = $1 AND totp_enabled_at IS NOT NULL AND disabled_at IS NULL
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch the TOTP secret of the user.")?;
+    let Some(row) = row else {
+        return Ok(false);
+    };
+    let secret = TotpSecret::parse(row.totp_secret).map_err(anyhow::Error::msg)?;
+
+    if let Some(step) = secret.verify(code, Utc::now().timestamp()) {
+        // Only the first login with a given code gets to move the step forward
+        let accepted = sqlx::query!(
+            r#"
+            UPDATE users
+            SET totp_last_used_step = $1
+            WHERE user_id = $2 AND (totp_last_used_step IS NULL OR totp_last_used_step < $1)
+            "#,
+            step,
+            user_id
+        )
+        .execute(pool)
+        .await
+        .context("Failed to record the used TOTP step.")?
+        .rows_affected()
+            == 1;
+        return Ok(accepted);
+    }
+
+    if !looks_like_recovery_code(code) {
+        return Ok(false);
+    }
+    use_recovery_code(user_id, code, pool).await
+}
+
+#[tracing::instrument(name = "Use recovery code", skip(code, pool))]
+async fn use_recovery_code(
+    user_id: Uuid,
+    code: &str,
+    pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let candidates = sqlx::query!(
+        r#"
+        SELECT recovery_code_id, code_hash
+        FROM recovery_codes
+        WHERE user_id = $1 AND used_at IS NULL
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch the recovery codes.")?
+    .into_iter()
+    .map(|row| (row.recovery_code_id, row.code_hash))
+    .collect::<Vec<_>>();
+
+    let code = normalize_recovery_code(code);
+    let matching = spawn_blocking_with_tracing(move || {
+        candidates.into_iter().find_map(|(id, code_hash)| {
+            let code_hash = PasswordHash::new(&code_hash).ok()?;
+            Argon2::default()
+                .verify_password(code.as_bytes(), &code_hash)
+                .ok()
+                .map(|_| id)
+        })
+    })
+    .await?;
+    let Some(recovery_code_id) = matching else {
+        return Ok(false);
+    };
+
+    let used = sqlx::query!(
+        r#"
+        UPDATE recovery_codes
+        SET used_at = now()
+        WHERE recovery_code_id = $1 AND used_at IS NULL
+        "#,
+        recovery_code_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to mark the recovery code as used.")?
+    .rows_affected()
+        == 1;
+    Ok(used)
+}
+
+/// How many unused recovery codes a user has left.
+#[tracing::instrument(name = "Count recovery codes", skip(pool))]
+pub async fn remaining_recovery_codes(user_id: Uuid, pool: &PgPool) -> Result<i64, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM recovery_codes
+        WHERE user_id = $1 AND used_at IS NULL
+        "#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count the recovery codes.")?;
+    Ok(row.count)
+}
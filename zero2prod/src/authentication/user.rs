@@ -17,3 +17,20 @@ pub async fn get_username(user_id: Uuid, pool: &PgPool) -> Result<String, anyhow
     .context("Failed to perform a query to retrieve a username.")?;
     Ok(row.username)
 }
+
+/// The current session generation of a user, to be stored in their session at login.
+#[tracing::instrument(name = "Get session generation", skip(pool))]
+pub async fn get_session_generation(user_id: Uuid, pool: &PgPool) -> Result<i32, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT session_generation
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to perform a query to retrieve the session generation.")?;
+    Ok(row.session_generation)
+}
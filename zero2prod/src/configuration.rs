@@ -1,3 +1,7 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use argon2::Params;
 use secrecy::{ExposeSecret, Secret};
 use serde::Deserialize;
 use serde_aux::field_attributes::deserialize_number_from_string;
@@ -6,7 +10,12 @@ use sqlx::{
     ConnectOptions,
 };
 
-use crate::{domain::SubscriberEmail, email_client::EmailClient};
+use crate::{
+    authentication::PasswordHashing,
+    domain::{EmailLayout, SubscriberEmail},
+    email_client::{EmailSender, FileEmailClient, PostmarkEmailClient, SmtpEmailClient, SmtpTls},
+    retry_policy::RetryPolicy,
+};
 
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
     // Grab the execution directory
@@ -47,8 +56,13 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
 pub struct Settings {
     pub application: ApplicationSettings,
     pub database: DatabaseSettings,
+    pub delivery_worker: DeliveryWorkerSettings,
     pub email_client: EmailClientSettings,
+    pub login_throttle: LoginThrottleSettings,
+    pub newsletter: NewsletterSettings,
+    pub password_hashing: PasswordHashingSettings,
     pub redis: RedisSettings,
+    pub webhooks: WebhookSettings,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -56,6 +70,24 @@ pub struct RedisSettings {
     pub uri: Secret<String>,
 }
 
+/// Credentials providers must present when they post events to our webhooks.
+#[derive(Clone, Debug, Deserialize)]
+pub struct WebhookSettings {
+    pub postmark_username: String,
+    pub postmark_password: Secret<String>,
+}
+
+impl WebhookSettings {
+    /// The value base.yml ships with, which must never be accepted as a real password.
+    pub const PLACEHOLDER_PASSWORD: &'static str = "set this in an environment variable";
+
+    /// The password Postmark must present, or `None` while it is still the placeholder.
+    pub fn postmark_password(&self) -> Option<Secret<String>> {
+        (self.postmark_password.expose_secret() != Self::PLACEHOLDER_PASSWORD)
+            .then(|| self.postmark_password.clone())
+    }
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct ApplicationSettings {
     #[serde(deserialize_with = "deserialize_number_from_string")]
@@ -63,6 +95,97 @@ pub struct ApplicationSettings {
     pub host: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub subscription_token_ttl_hours: i64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub shutdown_deadline_seconds: u64,
+}
+
+impl ApplicationSettings {
+    /// How long a subscription confirmation link stays valid.
+    pub fn subscription_token_ttl(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.subscription_token_ttl_hours)
+    }
+
+    /// How long in-flight requests and deliveries get to finish once a shutdown is requested.
+    pub fn shutdown_deadline(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.shutdown_deadline_seconds)
+    }
+}
+
+/// Limits on failed logins, counted in redis.
+#[derive(Clone, Debug, Deserialize)]
+pub struct LoginThrottleSettings {
+    /// Failed logins allowed for one username before it is locked.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failures_per_username: u32,
+    /// Failed logins allowed from one address, whatever the username, before it is locked.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failures_per_ip: u32,
+    /// How long failures are remembered, counted from the latest one. Also how long a lock lasts.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub lockout_seconds: u64,
+    /// The header a reverse proxy puts the client address in. Without it, the address of the
+    /// peer is used, which behind a proxy is the proxy itself.
+    pub client_ip_header: Option<String>,
+    /// How many proxies in front of the app append to `client_ip_header`. Entries further to
+    /// the left than theirs could have been made up by the client.
+    #[serde(
+        default = "default_trusted_proxies",
+        deserialize_with = "deserialize_number_from_string"
+    )]
+    pub trusted_proxies: usize,
+}
+
+fn default_trusted_proxies() -> usize {
+    1
+}
+
+/// The cost of hashing passwords with Argon2id. Stored hashes made with other values are
+/// upgraded the next time their user logs in.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PasswordHashingSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub memory_kib: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub iterations: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub parallelism: u32,
+}
+
+impl PasswordHashingSettings {
+    pub fn hashing(&self) -> Result<PasswordHashing, anyhow::Error> {
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+            .map_err(|e| anyhow::anyhow!("Invalid password hashing parameters: {}", e))?;
+        PasswordHashing::new(params)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct DeliveryWorkerSettings {
+    /// How many workers pull from the delivery queue at the same time.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub concurrency: usize,
+    /// How many queued deliveries a worker takes on at once.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub batch_size: u16,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub poll_interval_milliseconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub error_backoff_milliseconds: u64,
+    pub retry_policy: RetryPolicy,
+}
+
+impl DeliveryWorkerSettings {
+    /// How long an idle worker waits for a queue notification before polling anyway.
+    pub fn poll_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.poll_interval_milliseconds)
+    }
+
+    /// How long a worker waits after a failed attempt before trying again.
+    pub fn error_backoff(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.error_backoff_milliseconds)
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -103,24 +226,100 @@ impl DatabaseSettings {
     }
 }
 
+#[derive(Clone, Debug, Deserialize)]
+pub struct NewsletterSettings {
+    /// The html file issues written in Markdown are wrapped in. A bare page when unset.
+    pub layout_path: Option<String>,
+}
+
+impl NewsletterSettings {
+    pub fn layout(&self) -> Result<EmailLayout, anyhow::Error> {
+        let Some(layout_path) = &self.layout_path else {
+            return Ok(EmailLayout::default());
+        };
+        let layout = std::fs::read_to_string(layout_path)
+            .with_context(|| format!("Failed to read the email layout at {}.", layout_path))?;
+        EmailLayout::parse(layout)
+            .map_err(anyhow::Error::msg)
+            .with_context(|| format!("Invalid email layout at {}.", layout_path))
+    }
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct EmailClientSettings {
+    #[serde(default)]
+    pub transport: EmailTransport,
     pub authorization_token: Secret<String>,
     pub base_url: String,
     pub sender_email: String,
     pub timeout_milliseconds: u64,
+    pub smtp: Option<SmtpSettings>,
+    pub file_directory: Option<String>,
+}
+
+/// Which backend delivers the application's emails.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailTransport {
+    /// Postmark's HTTP API, configured by `base_url` and `authorization_token`.
+    #[default]
+    Postmark,
+    /// Any SMTP relay, configured by the `smtp` section.
+    Smtp,
+    /// `.eml` files written into `file_directory`, for local development.
+    File,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
 }
 
 impl EmailClientSettings {
-    pub fn client(self) -> EmailClient {
-        let sender_email = self.sender().expect("Invalid sender email address.");
+    pub fn client(self) -> Result<Arc<dyn EmailSender>, anyhow::Error> {
+        let sender_email = self
+            .sender()
+            .map_err(anyhow::Error::msg)
+            .context("Invalid sender email address.")?;
         let timeout = self.timeout();
-        EmailClient::new(
-            self.base_url,
-            sender_email,
-            self.authorization_token,
-            timeout,
-        )
+        let client: Arc<dyn EmailSender> = match self.transport {
+            EmailTransport::Postmark => Arc::new(PostmarkEmailClient::new(
+                self.base_url,
+                sender_email,
+                self.authorization_token,
+                timeout,
+            )),
+            EmailTransport::Smtp => {
+                let smtp = self
+                    .smtp
+                    .context("The smtp transport requires an `smtp` section.")?;
+                let credentials = match (smtp.username, smtp.password) {
+                    (Some(username), Some(password)) => Some((username, password)),
+                    (None, None) => None,
+                    _ => anyhow::bail!("SMTP username and password must be set together."),
+                };
+                Arc::new(SmtpEmailClient::new(
+                    &smtp.host,
+                    smtp.port,
+                    smtp.tls,
+                    credentials,
+                    sender_email,
+                    timeout,
+                )?)
+            }
+            EmailTransport::File => {
+                let directory = self
+                    .file_directory
+                    .context("The file transport requires a `file_directory`.")?;
+                Arc::new(FileEmailClient::new(directory, sender_email))
+            }
+        };
+        Ok(client)
     }
 
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
@@ -1,7 +1,13 @@
+mod issue_body;
+mod issue_template;
 mod new_subscriber;
 mod subscriber_email;
 mod subscriber_name;
+mod unsubscribe_token;
 
+pub use issue_body::{check_ready_to_publish, EmailLayout, IssueBody};
+pub use issue_template::{IssueTemplate, TemplateError, TemplateValues};
 pub use new_subscriber::NewSubscriber;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use unsubscribe_token::UnsubscribeToken;
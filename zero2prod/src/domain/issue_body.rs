@@ -0,0 +1,358 @@
+use pulldown_cmark::{html, Event, HeadingLevel, Options, Parser, Tag};
+
+use super::IssueTemplate;
+
+/// The html every issue written in Markdown is wrapped in before it is sent.
+///
+/// The layout must contain `{{ content }}` exactly once, which is where the issue goes. It may
+/// use the same placeholders as the issue itself, e.g. for an unsubscribe link in the footer.
+#[derive(Clone, Debug)]
+pub struct EmailLayout(String);
+
+const CONTENT_MARKER: &str = "{{ content }}";
+
+impl EmailLayout {
+    pub fn parse(layout: String) -> Result<Self, String> {
+        if layout.matches(CONTENT_MARKER).count() != 1 {
+            return Err(format!(
+                "An email layout must contain `{}` exactly once.",
+                CONTENT_MARKER
+            ));
+        }
+        // Otherwise every issue would fail to publish once wrapped in it
+        IssueTemplate::parse(&layout.replace(CONTENT_MARKER, ""))
+            .map_err(|e| format!("The email layout is not a valid template. {}", e))?;
+        Ok(Self(layout))
+    }
+
+    fn wrap(&self, content: &str) -> String {
+        self.0.replace(CONTENT_MARKER, content)
+    }
+}
+
+impl Default for EmailLayout {
+    fn default() -> Self {
+        Self(format!(
+            "<!DOCTYPE html>\n<html>\n<body>\n{}\n</body>\n</html>",
+            CONTENT_MARKER
+        ))
+    }
+}
+
+/// Both bodies of an issue, ready to be stored.
+#[derive(Debug, PartialEq, Eq)]
+pub struct IssueBody {
+    pub html_content: String,
+    pub text_content: String,
+    /// Whether the html body was written by hand rather than generated from Markdown
+    pub html_overridden: bool,
+    /// Whether the plain text body was written by hand rather than generated from Markdown
+    pub text_overridden: bool,
+}
+
+impl IssueBody {
+    /// Work out both bodies of an issue from what the editor filled in.
+    ///
+    /// A Markdown body generates the html and the plain text body, unless the editor also
+    /// wrote either of those by hand. Without Markdown, both hand-written bodies are required.
+    pub fn compose(
+        markdown_content: &str,
+        html_content: &str,
+        text_content: &str,
+        layout: &EmailLayout,
+    ) -> Result<Self, &'static str> {
+        if markdown_content.trim().is_empty() {
+            if html_content.trim().is_empty() || text_content.trim().is_empty() {
+                return Err(
+                    "Write the issue in Markdown, or fill in both the html and the \
+                    plain text body.",
+                );
+            }
+            return Ok(Self::hand_written(html_content, text_content));
+        }
+        Ok(Self::from_markdown(
+            markdown_content,
+            html_content,
+            text_content,
+            layout,
+        ))
+    }
+
+    /// Generate both bodies from Markdown, keeping either of them that was written by hand.
+    pub fn from_markdown(
+        markdown_content: &str,
+        html_content: &str,
+        text_content: &str,
+        layout: &EmailLayout,
+    ) -> Self {
+        let html_overridden = !html_content.trim().is_empty();
+        let text_overridden = !text_content.trim().is_empty();
+        Self {
+            html_content: if html_overridden {
+                html_content.to_string()
+            } else {
+                layout.wrap(&markdown_to_html(markdown_content))
+            },
+            text_content: if text_overridden {
+                text_content.to_string()
+            } else {
+                markdown_to_text(markdown_content)
+            },
+            html_overridden,
+            text_overridden,
+        }
+    }
+
+    /// Both bodies as written, for an issue without Markdown.
+    pub fn hand_written(html_content: &str, text_content: &str) -> Self {
+        Self {
+            html_content: html_content.to_string(),
+            text_content: text_content.to_string(),
+            html_overridden: true,
+            text_overridden: true,
+        }
+    }
+}
+
+/// Check a stored issue is complete enough to go out to subscribers.
+///
+/// Drafts may be saved incomplete, so this is repeated whenever one is about to be published.
+pub fn check_ready_to_publish(
+    title: &str,
+    html_content: &str,
+    text_content: &str,
+) -> Result<(), String> {
+    if title.trim().is_empty() {
+        return Err("The issue needs a title.".into());
+    }
+    if html_content.trim().is_empty() || text_content.trim().is_empty() {
+        return Err("The issue needs both an html and a plain text body.".into());
+    }
+    IssueTemplate::parse(html_content)
+        .and(IssueTemplate::parse(text_content))
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn parser(markdown: &str) -> Parser<'_, '_> {
+    Parser::new_ext(
+        markdown,
+        Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES,
+    )
+}
+
+/// Render Markdown to html, dropping scripts, styles and any other markup that has no
+/// business in an email.
+fn markdown_to_html(markdown: &str) -> String {
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, parser(markdown));
+    restore_placeholders(&ammonia::clean(&unsafe_html))
+}
+
+/// Links are percent-encoded, which would hide a placeholder used as a link target,
+/// e.g. `[Read it online]({{ web_view_url }})`. Decode them again.
+fn restore_placeholders(html: &str) -> String {
+    let mut restored = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find("%7B%7B") {
+        let Some(end) = rest[start..].find("%7D%7D") else {
+            break;
+        };
+        let placeholder = &rest[start + 6..start + end];
+        restored.push_str(&rest[..start]);
+        restored.push_str("{{");
+        restored.push_str(&placeholder.replace("%20", " ").replace("%7C", "|"));
+        restored.push_str("}}");
+        rest = &rest[start + end + 6..];
+    }
+    restored.push_str(rest);
+    restored
+}
+
+/// Render Markdown as plain text that reads well in a mail client without html.
+fn markdown_to_text(markdown: &str) -> String {
+    let mut text = String::new();
+    // The number of the next item of every open list, or `None` for bullet lists
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut heading_start = None;
+    let mut link_targets = Vec::new();
+    let mut in_code_block = false;
+
+    for event in parser(markdown) {
+        match event {
+            Event::Start(Tag::Heading(..)) => heading_start = Some(text.len()),
+            Event::End(Tag::Heading(level, ..)) => {
+                let start = heading_start.take().unwrap_or(text.len());
+                let width = text[start..].chars().count();
+                let underline = if level == HeadingLevel::H1 { "=" } else { "-" };
+                text.push('\n');
+                text.push_str(&underline.repeat(width));
+                text.push_str("\n\n");
+            }
+            Event::End(Tag::Paragraph) => {
+                text.push_str(if lists.is_empty() { "\n\n" } else { "\n" });
+            }
+            Event::Start(Tag::List(first_number)) => {
+                if !lists.is_empty() && !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                lists.push(first_number);
+            }
+            Event::End(Tag::List(_)) => {
+                lists.pop();
+                if lists.is_empty() {
+                    text.push('\n');
+                }
+            }
+            Event::Start(Tag::Item) => {
+                text.push_str(&"   ".repeat(lists.len().saturating_sub(1)));
+                match lists.last_mut() {
+                    Some(Some(number)) => {
+                        text.push_str(&format!("{}. ", number));
+                        *number += 1;
+                    }
+                    _ => text.push_str("- "),
+                }
+            }
+            Event::End(Tag::Item) if !text.ends_with('\n') => text.push('\n'),
+            Event::Start(Tag::Link(_, destination, _)) => {
+                link_targets.push((destination, text.len()));
+            }
+            Event::End(Tag::Link(..)) => {
+                if let Some((destination, start)) = link_targets.pop() {
+                    if text[start..] != *destination {
+                        text.push_str(&format!(" ({})", destination));
+                    }
+                }
+            }
+            Event::Start(Tag::CodeBlock(_)) => {
+                in_code_block = true;
+                text.push_str("    ");
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                in_code_block = false;
+                let trimmed_len = text.trim_end_matches([' ', '\n']).len();
+                text.truncate(trimmed_len);
+                text.push_str("\n\n");
+            }
+            Event::Text(content) if in_code_block => {
+                text.push_str(&content.replace('\n', "\n    "));
+            }
+            Event::Text(content) | Event::Code(content) => text.push_str(&content),
+            Event::SoftBreak | Event::HardBreak => text.push('\n'),
+            Event::Rule => text.push_str("----\n\n"),
+            Event::End(Tag::TableCell) => text.push_str("  "),
+            Event::End(Tag::TableHead) | Event::End(Tag::TableRow) => text.push('\n'),
+            Event::End(Tag::Table(_)) => text.push('\n'),
+            _ => {}
+        }
+    }
+    text.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use super::{
+        check_ready_to_publish, markdown_to_html, markdown_to_text, EmailLayout, IssueBody,
+    };
+
+    #[test]
+    fn markdown_is_rendered_to_html() {
+        assert_eq!(
+            markdown_to_html("# Hello\n\nSome *emphasis*."),
+            "<h1>Hello</h1>\n<p>Some <em>emphasis</em>.</p>\n"
+        );
+    }
+
+    #[test]
+    fn scripts_and_event_handlers_are_stripped_from_the_html() {
+        let html = markdown_to_html(
+            "<script>alert('hi')</script>\n\n<a href=\"https://example.com\" onclick=\"steal()\">Link</a>",
+        );
+        assert!(!html.contains("script"));
+        assert!(!html.contains("onclick"));
+        assert!(html.contains("https://example.com"));
+    }
+
+    #[test]
+    fn placeholders_survive_as_link_targets() {
+        let html = markdown_to_html("[Read it online](<{{ web_view_url }}>)");
+        assert!(html.contains(r#"href="{{ web_view_url }}""#), "{}", html);
+    }
+
+    #[test]
+    fn markdown_is_rendered_to_readable_text() {
+        let markdown = "# Hello\n\nRead [our blog](https://example.com) or \
+            <https://example.com>.\n\n- one\n- two\n\n1. first\n2. second\n\nBye";
+        assert_eq!(
+            markdown_to_text(markdown),
+            "Hello\n=====\n\nRead our blog (https://example.com) or https://example.com.\n\n\
+            - one\n- two\n\n1. first\n2. second\n\nBye"
+        );
+    }
+
+    #[test]
+    fn the_layout_wraps_the_generated_html() {
+        let layout = assert_ok!(EmailLayout::parse(
+            "<main>{{ content }}</main><a href=\"{{ unsubscribe_url }}\">Unsubscribe</a>".into()
+        ));
+        let body = assert_ok!(IssueBody::compose("Hi", "", "", &layout));
+        assert_eq!(
+            body.html_content,
+            "<main><p>Hi</p>\n</main><a href=\"{{ unsubscribe_url }}\">Unsubscribe</a>"
+        );
+        assert_eq!(body.text_content, "Hi");
+    }
+
+    #[test]
+    fn a_layout_without_a_content_marker_is_rejected() {
+        assert_err!(EmailLayout::parse("<main></main>".into()));
+    }
+
+    #[test]
+    fn a_layout_with_an_unknown_placeholder_is_rejected() {
+        assert_err!(EmailLayout::parse(
+            "<main>{{ content }}</main>{{ unsubscribe_link }}".into()
+        ));
+        assert_err!(EmailLayout::parse(
+            "<main>{{ content }}</main>{{ name".into()
+        ));
+    }
+
+    #[test]
+    fn hand_written_bodies_override_the_generated_ones() {
+        let body = assert_ok!(IssueBody::compose(
+            "Hi",
+            "<p>Hand-written</p>",
+            "",
+            &EmailLayout::default()
+        ));
+        assert_eq!(body.html_content, "<p>Hand-written</p>");
+        assert_eq!(body.text_content, "Hi");
+        assert!(body.html_overridden);
+        assert!(!body.text_overridden);
+    }
+
+    #[test]
+    fn both_hand_written_bodies_are_required_without_markdown() {
+        let layout = EmailLayout::default();
+        assert_err!(IssueBody::compose("", "<p>Hi</p>", "", &layout));
+        assert_err!(IssueBody::compose(" ", "", "Hi", &layout));
+        assert_ok!(IssueBody::compose("", "<p>Hi</p>", "Hi", &layout));
+    }
+
+    #[test]
+    fn incomplete_issues_are_not_ready_to_publish() {
+        assert_err!(check_ready_to_publish(" ", "<p>Hi</p>", "Hi"));
+        assert_err!(check_ready_to_publish("Title", "", "Hi"));
+        assert_err!(check_ready_to_publish("Title", "<p>Hi</p>", " "));
+        assert_err!(check_ready_to_publish("Title", "<p>{{ nmae }}</p>", "Hi"));
+        assert_ok!(check_ready_to_publish(
+            "Title",
+            "<p>Hi {{ name }}</p>",
+            "Hi"
+        ));
+    }
+}
@@ -0,0 +1,218 @@
+use html_escape::encode_double_quoted_attribute;
+
+/// The body of a newsletter issue, with placeholders filled in for every recipient.
+///
+/// Placeholders look like `{{ name }}`. A fallback for when the value is missing goes after
+/// a pipe, as in `{{ name | reader }}`. Only `name`, `unsubscribe_url` and `web_view_url`
+/// are known, so a typo is caught when the issue is published rather than mailed out verbatim.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IssueTemplate {
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Placeholder {
+        placeholder: Placeholder,
+        fallback: String,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Placeholder {
+    Name,
+    UnsubscribeUrl,
+    WebViewUrl,
+}
+
+impl Placeholder {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "name" => Some(Self::Name),
+            "unsubscribe_url" => Some(Self::UnsubscribeUrl),
+            "web_view_url" => Some(Self::WebViewUrl),
+            _ => None,
+        }
+    }
+}
+
+/// The values a template is rendered with. Missing values fall back to the placeholder's
+/// fallback, or to nothing at all.
+#[derive(Debug, Default)]
+pub struct TemplateValues<'a> {
+    pub name: Option<&'a str>,
+    pub unsubscribe_url: Option<&'a str>,
+    pub web_view_url: Option<&'a str>,
+}
+
+impl TemplateValues<'_> {
+    fn get(&self, placeholder: Placeholder) -> Option<&str> {
+        let value = match placeholder {
+            Placeholder::Name => self.name,
+            Placeholder::UnsubscribeUrl => self.unsubscribe_url,
+            Placeholder::WebViewUrl => self.web_view_url,
+        };
+        value.filter(|value| !value.trim().is_empty())
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum TemplateError {
+    #[error(
+        "`{{{{ {0} }}}}` is not a known placeholder. Use name, unsubscribe_url or web_view_url."
+    )]
+    UnknownPlaceholder(String),
+    #[error("A placeholder was opened with `{{{{` but never closed with `}}}}`.")]
+    UnclosedPlaceholder,
+}
+
+impl IssueTemplate {
+    pub fn parse(source: &str) -> Result<Self, TemplateError> {
+        let mut parts = Vec::new();
+        let mut rest = source;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            let after_open = &rest[start + 2..];
+            let end = after_open
+                .find("}}")
+                .ok_or(TemplateError::UnclosedPlaceholder)?;
+            let (name, fallback) = match after_open[..end].split_once('|') {
+                Some((name, fallback)) => (name.trim(), fallback.trim()),
+                None => (after_open[..end].trim(), ""),
+            };
+            let placeholder = Placeholder::parse(name)
+                .ok_or_else(|| TemplateError::UnknownPlaceholder(name.to_string()))?;
+            parts.push(Part::Placeholder {
+                placeholder,
+                fallback: fallback.to_string(),
+            });
+            rest = &after_open[end + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        Ok(Self { parts })
+    }
+
+    /// Parse `source`, or render it exactly as written if it isn't a valid template.
+    ///
+    /// For pages showing issues published before placeholders were validated.
+    pub fn parse_or_verbatim(source: &str) -> Self {
+        Self::parse(source).unwrap_or_else(|_| Self::verbatim(source))
+    }
+
+    /// A template that renders `source` exactly as written, placeholders and all.
+    pub fn verbatim(source: &str) -> Self {
+        Self {
+            parts: vec![Part::Text(source.to_string())],
+        }
+    }
+
+    /// Render for the html part of an email. Values are escaped, the markup around them is not.
+    pub fn render_html(&self, values: &TemplateValues) -> String {
+        self.render(values, |value| {
+            encode_double_quoted_attribute(value).into_owned()
+        })
+    }
+
+    /// Render for the plain text part of an email.
+    pub fn render_text(&self, values: &TemplateValues) -> String {
+        self.render(values, str::to_string)
+    }
+
+    fn render(&self, values: &TemplateValues, escape: impl Fn(&str) -> String) -> String {
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => rendered.push_str(text),
+                Part::Placeholder {
+                    placeholder,
+                    fallback,
+                } => {
+                    let value = values.get(*placeholder).unwrap_or(fallback);
+                    rendered.push_str(&escape(value));
+                }
+            }
+        }
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err_eq, assert_ok};
+
+    use super::{IssueTemplate, TemplateError, TemplateValues};
+
+    fn values() -> TemplateValues<'static> {
+        TemplateValues {
+            name: Some("Ursula <Le Guin>"),
+            unsubscribe_url: Some("https://example.com/unsubscribe?a=1&b=2"),
+            web_view_url: Some("https://example.com/issues/1"),
+        }
+    }
+
+    #[test]
+    fn placeholders_are_filled_in() {
+        let template = assert_ok!(IssueTemplate::parse(
+            "Hi {{name}}, read it online at {{ web_view_url }}."
+        ));
+        assert_eq!(
+            template.render_text(&values()),
+            "Hi Ursula <Le Guin>, read it online at https://example.com/issues/1."
+        );
+    }
+
+    #[test]
+    fn values_are_escaped_in_html() {
+        let template = assert_ok!(IssueTemplate::parse(
+            r#"<p>Hi {{ name }}</p><a href="{{ unsubscribe_url }}">Unsubscribe</a>"#
+        ));
+        assert_eq!(
+            template.render_html(&values()),
+            r#"<p>Hi Ursula &lt;Le Guin&gt;</p><a href="https://example.com/unsubscribe?a=1&amp;b=2">Unsubscribe</a>"#
+        );
+    }
+
+    #[test]
+    fn missing_values_use_the_fallback() {
+        let template = assert_ok!(IssueTemplate::parse("Hi {{ name | reader }}!"));
+        let values = TemplateValues {
+            name: Some("  "),
+            ..TemplateValues::default()
+        };
+        assert_eq!(template.render_text(&values), "Hi reader!");
+    }
+
+    #[test]
+    fn missing_values_without_a_fallback_render_as_nothing() {
+        let template = assert_ok!(IssueTemplate::parse("Hi {{ name }}!"));
+        assert_eq!(template.render_text(&TemplateValues::default()), "Hi !");
+    }
+
+    #[test]
+    fn unknown_placeholders_are_rejected() {
+        assert_err_eq!(
+            IssueTemplate::parse("Hi {{ first_name }}!"),
+            TemplateError::UnknownPlaceholder("first_name".into())
+        );
+    }
+
+    #[test]
+    fn unclosed_placeholders_are_rejected() {
+        assert_err_eq!(
+            IssueTemplate::parse("Hi {{ name!"),
+            TemplateError::UnclosedPlaceholder
+        );
+    }
+
+    #[test]
+    fn text_without_placeholders_is_left_alone() {
+        let source = "<p>A plain { issue } with } braces</p>";
+        let template = assert_ok!(IssueTemplate::parse(source));
+        assert_eq!(template.render_html(&values()), source);
+    }
+}
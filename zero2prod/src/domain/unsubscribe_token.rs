@@ -0,0 +1,98 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// A signed token proving an unsubscribe link was issued for a specific subscriber.
+///
+/// The token is an HMAC of the subscriber id keyed with the application's hmac secret,
+/// so it never has to be stored in the database and never expires.
+#[derive(Clone, Debug)]
+pub struct UnsubscribeToken(String);
+
+impl UnsubscribeToken {
+    /// Generate the token for a subscriber.
+    pub fn generate(subscriber_id: Uuid, hmac_secret: &Secret<String>) -> Self {
+        let tag = Self::mac(subscriber_id, hmac_secret)
+            .finalize()
+            .into_bytes();
+        Self(URL_SAFE_NO_PAD.encode(tag))
+    }
+
+    /// Check that this token was issued for `subscriber_id` with `hmac_secret`.
+    pub fn verify(&self, subscriber_id: Uuid, hmac_secret: &Secret<String>) -> Result<(), String> {
+        let tag = URL_SAFE_NO_PAD
+            .decode(&self.0)
+            .map_err(|_| "The unsubscribe token is not valid base64".to_string())?;
+        Self::mac(subscriber_id, hmac_secret)
+            .verify_slice(&tag)
+            .map_err(|_| {
+                format!(
+                    "The unsubscribe token does not match subscriber {}",
+                    subscriber_id
+                )
+            })
+    }
+
+    fn mac(subscriber_id: Uuid, hmac_secret: &Secret<String>) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(hmac_secret.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size");
+        mac.update(b"unsubscribe:");
+        mac.update(subscriber_id.as_bytes());
+        mac
+    }
+}
+
+impl From<String> for UnsubscribeToken {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl AsRef<str> for UnsubscribeToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    use super::UnsubscribeToken;
+
+    fn secret() -> Secret<String> {
+        Secret::new("a-very-secret-key".to_string())
+    }
+
+    #[test]
+    fn a_generated_token_is_valid_for_its_subscriber() {
+        let subscriber_id = Uuid::new_v4();
+        let token = UnsubscribeToken::generate(subscriber_id, &secret());
+        assert_ok!(token.verify(subscriber_id, &secret()));
+    }
+
+    #[test]
+    fn a_token_is_rejected_for_another_subscriber() {
+        let token = UnsubscribeToken::generate(Uuid::new_v4(), &secret());
+        assert_err!(token.verify(Uuid::new_v4(), &secret()));
+    }
+
+    #[test]
+    fn a_token_signed_with_another_secret_is_rejected() {
+        let subscriber_id = Uuid::new_v4();
+        let token = UnsubscribeToken::generate(subscriber_id, &Secret::new("other".to_string()));
+        assert_err!(token.verify(subscriber_id, &secret()));
+    }
+
+    #[test]
+    fn a_malformed_token_is_rejected() {
+        let token = UnsubscribeToken::from("not a token!".to_string());
+        assert_err!(token.verify(Uuid::new_v4(), &secret()));
+    }
+}
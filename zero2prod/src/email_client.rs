@@ -1,224 +1,207 @@
-use reqwest::Client;
-use secrecy::{ExposeSecret, Secret};
+mod file;
+mod postmark;
+mod smtp;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use lettre::{
+    message::{
+        header::{HeaderName, HeaderValue},
+        Mailbox, MultiPart,
+    },
+    Message,
+};
 use serde::Serialize;
 
 use crate::domain::SubscriberEmail;
 
-#[derive(Debug)]
-pub struct EmailClient {
-    authorization_token: Secret<String>,
-    base_url: String,
-    http_client: Client,
-    sender: SubscriberEmail,
-}
+pub use file::FileEmailClient;
+pub use postmark::PostmarkEmailClient;
+pub use smtp::{SmtpEmailClient, SmtpTls};
 
-impl EmailClient {
-    pub fn new(
-        base_url: String,
-        sender: SubscriberEmail,
-        authorization_token: Secret<String>,
-        timeout: std::time::Duration,
-    ) -> Self {
-        let http_client = Client::builder().timeout(timeout).build().unwrap();
-        Self {
-            authorization_token,
-            http_client,
-            base_url,
-            sender,
-        }
-    }
+/// A transport able to deliver emails on behalf of the application.
+#[async_trait]
+pub trait EmailSender: std::fmt::Debug + Send + Sync {
+    /// Send an email with additional custom headers.
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<SentEmail, EmailError>;
 
     /// Send an email.
-    pub async fn send_email(
+    async fn send_email(
         &self,
         recipient: &SubscriberEmail,
         subject: &str,
         html_content: &str,
         text_content: &str,
-    ) -> Result<(), reqwest::Error> {
-        //TODO: Replace this with Url::join() eventually
-        let url = format!("{}/email", self.base_url);
-
-        let request_body = SendEmailRequest {
-            from: self.sender.as_ref(),
-            to: recipient.as_ref(),
-            subject,
-            html_body: html_content,
-            text_body: text_content,
-        };
-
-        self.http_client
-            .post(&url)
-            .header(
-                "X-Postmark-Server-Token",
-                self.authorization_token.expose_secret(),
-            )
-            .json(&request_body)
-            .send()
-            .await?
-            .error_for_status()?;
-
-        Ok(())
+    ) -> Result<SentEmail, EmailError> {
+        self.send_email_with_headers(recipient, subject, html_content, text_content, &[])
+            .await
     }
-}
 
-#[derive(Serialize)]
-#[serde(rename_all = "PascalCase")]
-struct SendEmailRequest<'a> {
-    from: &'a str,
-    to: &'a str,
-    subject: &'a str,
-    html_body: &'a str,
-    text_body: &'a str,
-}
+    /// How many emails go out in a single request of [`EmailSender::send_batch`].
+    ///
+    /// Transports without a batch API send one email per request.
+    fn max_batch_size(&self) -> usize {
+        1
+    }
 
-#[cfg(test)]
-mod tests {
-    use claims::{assert_err, assert_ok};
-    use fake::{
-        faker::{
-            internet::en::SafeEmail,
-            lorem::en::{Paragraph, Sentence},
-        },
-        Fake, Faker,
-    };
-    use secrecy::Secret;
-    use wiremock::{
-        matchers::{any, header, header_exists, method, path},
-        Mock, MockServer, Request, ResponseTemplate,
-    };
-
-    use crate::{domain::SubscriberEmail, email_client::EmailClient};
-
-    struct SendEmailBodyMatcher;
-    impl wiremock::Match for SendEmailBodyMatcher {
-        fn matches(&self, request: &Request) -> bool {
-            // Try to parse the body's json
-            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
-            if let Ok(body) = result {
-                // Ensure mandatory fields are populated
-                body.get("From").is_some()
-                    && body.get("To").is_some()
-                    && body.get("Subject").is_some()
-                    && body.get("HtmlBody").is_some()
-                    && body.get("TextBody").is_some()
-            } else {
-                false
-            }
+    /// Send several emails at once and report the outcome of each one, in order.
+    ///
+    /// An outer error means the batch as a whole could not be handed over. Transports
+    /// without a batch API fall back to sending the emails one by one.
+    async fn send_batch(
+        &self,
+        emails: &[OutgoingEmail],
+    ) -> Result<Vec<Result<SentEmail, EmailError>>, anyhow::Error> {
+        let mut outcomes = Vec::with_capacity(emails.len());
+        for email in emails {
+            outcomes.push(
+                self.send_email_with_headers(
+                    &email.recipient,
+                    &email.subject,
+                    &email.html_content,
+                    &email.text_content,
+                    &email.headers,
+                )
+                .await,
+            );
         }
+        Ok(outcomes)
     }
+}
 
-    /// Generate a random email subject
-    fn subject() -> String {
-        Sentence(1..2).fake()
-    }
+/// What a transport reports back about an email it accepted.
+#[derive(Clone, Debug, Default)]
+pub struct SentEmail {
+    /// The id the provider assigned to the message, if it hands one back.
+    pub message_id: Option<String>,
+}
 
-    /// Generate some random email content
-    fn content() -> String {
-        Paragraph(1..10).fake()
-    }
+/// Why an email could not be sent.
+#[derive(thiserror::Error)]
+pub enum EmailError {
+    /// The provider refused this email and will keep refusing it, e.g. for an inactive recipient.
+    #[error("Permanently rejected: {0:#}")]
+    Permanent(anyhow::Error),
+    /// The email may go through if it is tried again later, e.g. after a 5xx or a timeout.
+    #[error("{0:#}")]
+    Transient(anyhow::Error),
+}
 
-    /// Generate a random subscriber email
-    fn email() -> SubscriberEmail {
-        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+impl EmailError {
+    pub fn is_permanent(&self) -> bool {
+        matches!(self, EmailError::Permanent(_))
     }
+}
 
-    /// Get a test instance of EmailClient
-    fn email_client(base_url: String) -> EmailClient {
-        EmailClient::new(
-            base_url,
-            email(),
-            Secret::new(Faker.fake()),
-            std::time::Duration::from_millis(200),
-        )
+impl std::fmt::Debug for EmailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        crate::error_chain_fmt(self, f)
     }
+}
 
-    #[tokio::test]
-    async fn send_email_sends_the_expected_request() {
-        // Arrange
-        let mock_server = MockServer::start().await;
-        let email_client = email_client(mock_server.uri());
-
-        Mock::given(header_exists("X-Postmark-Server-Token"))
-            .and(header("Content-Type", "application/json"))
-            .and(path("/email"))
-            .and(method("POST"))
-            .and(SendEmailBodyMatcher)
-            .respond_with(ResponseTemplate::new(200))
-            .expect(1)
-            .mount(&mock_server)
-            .await;
-
-        // Act
-        let _ = email_client
-            .send_email(&email(), &subject(), &content(), &content())
-            .await;
-
-        // Assertions will happen when the mock server goes out of scope
-    }
+/// A fully rendered email waiting to be handed to a transport as part of a batch.
+#[derive(Clone, Debug)]
+pub struct OutgoingEmail {
+    pub recipient: SubscriberEmail,
+    pub subject: String,
+    pub html_content: String,
+    pub text_content: String,
+    pub headers: Vec<EmailHeader>,
+}
 
-    #[tokio::test]
-    async fn send_email_succeeds_if_the_server_returns_200() {
-        // Arrange
-        let mock_server = MockServer::start().await;
-        let email_client = email_client(mock_server.uri());
-
-        Mock::given(any())
-            .respond_with(ResponseTemplate::new(200))
-            .expect(1)
-            .mount(&mock_server)
-            .await;
-
-        // Act
-        let outcome = email_client
-            .send_email(&email(), &subject(), &content(), &content())
-            .await;
-
-        // Assert
-        assert_ok!(outcome);
-    }
+/// A custom header to attach to an outgoing email.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmailHeader {
+    pub name: String,
+    pub value: String,
+}
 
-    #[tokio::test]
-    async fn send_email_fails_if_the_server_returns_500() {
-        // Arrange
-        let mock_server = MockServer::start().await;
-        let email_client = email_client(mock_server.uri());
-
-        Mock::given(any())
-            .respond_with(ResponseTemplate::new(500))
-            .expect(1)
-            .mount(&mock_server)
-            .await;
-
-        // Act
-        let outcome = email_client
-            .send_email(&email(), &subject(), &content(), &content())
-            .await;
-
-        // Assert
-        assert_err!(outcome);
+impl EmailHeader {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+        }
     }
+}
 
-    #[tokio::test]
-    async fn send_email_times_out_if_the_server_takes_too_long() {
-        // Arrange
-        let mock_server = MockServer::start().await;
-        let email_client = email_client(mock_server.uri());
-
-        // Delay 3 minutes before responding
-        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180));
-
-        Mock::given(any())
-            .respond_with(response)
-            .expect(1)
-            .mount(&mock_server)
-            .await;
-
-        // Act
-        let outcome = email_client
-            .send_email(&email(), &subject(), &content(), &content())
-            .await;
+/// Build a MIME message with both an html and a plain text alternative.
+fn build_message(
+    sender: &SubscriberEmail,
+    recipient: &SubscriberEmail,
+    subject: &str,
+    html_content: &str,
+    text_content: &str,
+    headers: &[EmailHeader],
+) -> Result<Message, anyhow::Error> {
+    let from: Mailbox = sender
+        .as_ref()
+        .parse()
+        .context("Failed to parse the sender address.")?;
+    let to: Mailbox = recipient
+        .as_ref()
+        .parse()
+        .context("Failed to parse the recipient address.")?;
+
+    let mut message = Message::builder()
+        .from(from)
+        .to(to)
+        .subject(subject)
+        .multipart(MultiPart::alternative_plain_html(
+            text_content.to_string(),
+            html_content.to_string(),
+        ))
+        .context("Failed to build the email message.")?;
+
+    for header in headers {
+        let name = HeaderName::new_from_ascii(header.name.clone())
+            .context("Invalid email header name.")?;
+        message
+            .headers_mut()
+            .insert_raw(HeaderValue::new(name, header.value.clone()));
+    }
+    Ok(message)
+}
 
-        assert_err!(outcome);
+#[cfg(test)]
+mod tests {
+    use claims::assert_ok;
+
+    use crate::domain::SubscriberEmail;
+
+    use super::{build_message, EmailHeader};
+
+    #[test]
+    fn built_messages_contain_both_bodies_and_custom_headers() {
+        let sender = SubscriberEmail::parse("sender@example.com".into()).unwrap();
+        let recipient = SubscriberEmail::parse("recipient@example.com".into()).unwrap();
+        let headers = [EmailHeader::new(
+            "List-Unsubscribe",
+            "<https://example.com>",
+        )];
+
+        let message = build_message(
+            &sender,
+            &recipient,
+            "Subject",
+            "<p>Html body</p>",
+            "Plain text body",
+            &headers,
+        );
+
+        let message = assert_ok!(message);
+        let raw = String::from_utf8(message.formatted()).unwrap();
+        assert!(raw.contains("List-Unsubscribe: <https://example.com>"));
+        assert!(raw.contains("To: recipient@example.com"));
+        assert!(raw.contains("Content-Type: text/plain"));
+        assert!(raw.contains("Content-Type: text/html"));
     }
 }
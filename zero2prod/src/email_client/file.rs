@@ -0,0 +1,106 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use lettre::{AsyncFileTransport, AsyncTransport, Tokio1Executor};
+
+use crate::domain::SubscriberEmail;
+
+use super::{build_message, EmailError, EmailHeader, EmailSender, SentEmail};
+
+/// Writes every email as an `.eml` file into a directory instead of sending it.
+///
+/// Meant for local development, where the files can be opened with any mail client.
+#[derive(Debug)]
+pub struct FileEmailClient {
+    directory: PathBuf,
+    transport: AsyncFileTransport<Tokio1Executor>,
+    sender: SubscriberEmail,
+}
+
+impl FileEmailClient {
+    pub fn new(directory: impl Into<PathBuf>, sender: SubscriberEmail) -> Self {
+        let directory = directory.into();
+        Self {
+            transport: AsyncFileTransport::new(&directory),
+            directory,
+            sender,
+        }
+    }
+}
+
+#[async_trait]
+impl EmailSender for FileEmailClient {
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<SentEmail, EmailError> {
+        let message = build_message(
+            &self.sender,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            headers,
+        )
+        .map_err(EmailError::Permanent)?;
+        tokio::fs::create_dir_all(&self.directory)
+            .await
+            .context("Failed to create the email output directory.")
+            .map_err(EmailError::Transient)?;
+        let id = self
+            .transport
+            .send(message)
+            .await
+            .context("Failed to write the email to disk.")
+            .map_err(EmailError::Transient)?;
+        tracing::info!("Wrote email {}.eml to {}", id, self.directory.display());
+        Ok(SentEmail {
+            message_id: Some(id),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::assert_ok;
+    use fake::{faker::internet::en::SafeEmail, Fake};
+
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{EmailSender, FileEmailClient},
+    };
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_email_writes_an_eml_file_to_the_directory() {
+        // Arrange
+        let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let email_client = FileEmailClient::new(&directory, email());
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), "Subject", "<p>Html body</p>", "Plain text body")
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let files: Vec<_> = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().unwrap(), "eml");
+        let raw = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(raw.contains("Subject: Subject"));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,551 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::SubscriberEmail;
+
+use super::{EmailError, EmailHeader, EmailSender, OutgoingEmail, SentEmail};
+
+/// The most messages Postmark accepts in a single call to the batch endpoint.
+const MAX_BATCH_SIZE: usize = 500;
+
+/// Sends emails through Postmark's HTTP API.
+#[derive(Debug)]
+pub struct PostmarkEmailClient {
+    authorization_token: Secret<String>,
+    base_url: String,
+    http_client: Client,
+    sender: SubscriberEmail,
+}
+
+impl PostmarkEmailClient {
+    pub fn new(
+        base_url: String,
+        sender: SubscriberEmail,
+        authorization_token: Secret<String>,
+        timeout: std::time::Duration,
+    ) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            authorization_token,
+            http_client,
+            base_url,
+            sender,
+        }
+    }
+}
+
+#[async_trait]
+impl EmailSender for PostmarkEmailClient {
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<SentEmail, EmailError> {
+        //TODO: Replace this with Url::join() eventually
+        let url = format!("{}/email", self.base_url);
+
+        let request_body = SendEmailRequest {
+            from: self.sender.as_ref(),
+            to: recipient.as_ref(),
+            subject,
+            html_body: html_content,
+            text_body: text_content,
+            headers,
+        };
+
+        let response = self
+            .http_client
+            .post(&url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(&request_body)
+            .send()
+            .await
+            .map_err(|e| EmailError::Transient(e.into()))?;
+
+        // Postmark explains why it refused an email in the body of a 422
+        if response.status() == StatusCode::UNPROCESSABLE_ENTITY {
+            let result: PostmarkResult = response
+                .json()
+                .await
+                .context("Failed to parse the error response from Postmark.")
+                .map_err(EmailError::Transient)?;
+            return result.into_outcome();
+        }
+        let response = response
+            .error_for_status()
+            .map_err(|e| EmailError::Transient(e.into()))?;
+
+        // The email is on its way, so a body we can't read only costs us the message id
+        let message_id = response
+            .json::<PostmarkResult>()
+            .await
+            .ok()
+            .and_then(|result| result.message_id);
+        Ok(SentEmail { message_id })
+    }
+
+    fn max_batch_size(&self) -> usize {
+        MAX_BATCH_SIZE
+    }
+
+    async fn send_batch(
+        &self,
+        emails: &[OutgoingEmail],
+    ) -> Result<Vec<Result<SentEmail, EmailError>>, anyhow::Error> {
+        let mut outcomes = Vec::with_capacity(emails.len());
+        for chunk in emails.chunks(MAX_BATCH_SIZE) {
+            match self.send_batch_request(chunk).await {
+                Ok(results) => {
+                    outcomes.extend(results.into_iter().map(PostmarkResult::into_outcome));
+                }
+                // Earlier chunks went out already, so only this chunk is worth retrying
+                Err(e) => {
+                    let e = format!("{:#}", e);
+                    outcomes.extend(
+                        chunk
+                            .iter()
+                            .map(|_| Err(EmailError::Transient(anyhow::anyhow!(e.clone())))),
+                    );
+                }
+            }
+        }
+        Ok(outcomes)
+    }
+}
+
+impl PostmarkEmailClient {
+    /// Send one call to the batch endpoint and return a result for every email, in order.
+    async fn send_batch_request(
+        &self,
+        emails: &[OutgoingEmail],
+    ) -> Result<Vec<PostmarkResult>, anyhow::Error> {
+        let url = format!("{}/email/batch", self.base_url);
+        let request_body: Vec<_> = emails
+            .iter()
+            .map(|email| SendEmailRequest {
+                from: self.sender.as_ref(),
+                to: email.recipient.as_ref(),
+                subject: &email.subject,
+                html_body: &email.html_content,
+                text_body: &email.text_content,
+                headers: &email.headers,
+            })
+            .collect();
+
+        let results: Vec<PostmarkResult> = self
+            .http_client
+            .post(&url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(&request_body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("Failed to parse the batch response from Postmark.")?;
+
+        if results.len() != emails.len() {
+            anyhow::bail!(
+                "Postmark returned {} results for a batch of {} emails.",
+                results.len(),
+                emails.len()
+            );
+        }
+        Ok(results)
+    }
+}
+
+/// Postmark error codes that will come back every time the same email is sent.
+///
+/// Other codes are about the account or the service as a whole and may clear up on their own.
+/// See <https://postmarkapp.com/developer/api/overview#error-codes>.
+const PERMANENT_ERROR_CODES: &[i64] = &[
+    300, // Invalid email request
+    406, // Inactive recipient
+];
+
+/// The outcome Postmark reports for a single email.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkResult {
+    error_code: i64,
+    message: String,
+    #[serde(rename = "MessageID", default)]
+    message_id: Option<String>,
+}
+
+impl PostmarkResult {
+    fn into_outcome(self) -> Result<SentEmail, EmailError> {
+        if self.error_code == 0 {
+            return Ok(SentEmail {
+                message_id: self.message_id,
+            });
+        }
+        let e = anyhow::anyhow!(
+            "Postmark rejected the email with error code {}: {}",
+            self.error_code,
+            self.message
+        );
+        if PERMANENT_ERROR_CODES.contains(&self.error_code) {
+            Err(EmailError::Permanent(e))
+        } else {
+            Err(EmailError::Transient(e))
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    headers: &'a [EmailHeader],
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+    use fake::{
+        faker::{
+            internet::en::SafeEmail,
+            lorem::en::{Paragraph, Sentence},
+        },
+        Fake, Faker,
+    };
+    use secrecy::Secret;
+    use wiremock::{
+        matchers::{any, header, header_exists, method, path},
+        Mock, MockServer, Request, ResponseTemplate,
+    };
+
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{EmailHeader, EmailSender, OutgoingEmail, PostmarkEmailClient},
+    };
+
+    struct SendEmailBodyMatcher;
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            // Try to parse the body's json
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                // Ensure mandatory fields are populated
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    struct HeadersBodyMatcher;
+    impl wiremock::Match for HeadersBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                body["Headers"]
+                    == serde_json::json!([{ "Name": "X-Test-Header", "Value": "test value" }])
+            } else {
+                false
+            }
+        }
+    }
+
+    /// Generate a random email subject
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    /// Generate some random email content
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    /// Generate a random subscriber email
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    /// Get a test instance of PostmarkEmailClient
+    fn email_client(base_url: String) -> PostmarkEmailClient {
+        PostmarkEmailClient::new(
+            base_url,
+            email(),
+            Secret::new(Faker.fake()),
+            std::time::Duration::from_millis(200),
+        )
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_the_expected_request() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let _ = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assertions will happen when the mock server goes out of scope
+    }
+
+    #[tokio::test]
+    async fn send_email_with_headers_includes_the_headers_in_the_request() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .and(HeadersBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let headers = [EmailHeader::new("X-Test-Header", "test value")];
+        let _ = email_client
+            .send_email_with_headers(&email(), &subject(), &content(), &content(), &headers)
+            .await;
+
+        // Assertions will happen when the mock server goes out of scope
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_the_server_returns_200() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_treats_an_inactive_recipient_as_a_permanent_failure() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+                "ErrorCode": 406,
+                "Message": "You tried to send to a recipient that has been marked as inactive."
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        let error = assert_err!(outcome);
+        assert!(error.is_permanent());
+    }
+
+    #[tokio::test]
+    async fn send_email_treats_a_500_as_a_transient_failure() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        let error = assert_err!(outcome);
+        assert!(!error.is_permanent());
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        // Delay 3 minutes before responding
+        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180));
+
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_err!(outcome);
+    }
+
+    /// Generate a batch of random emails
+    fn outgoing_emails(count: usize) -> Vec<OutgoingEmail> {
+        (0..count)
+            .map(|_| OutgoingEmail {
+                recipient: email(),
+                subject: subject(),
+                html_content: content(),
+                text_content: content(),
+                headers: vec![],
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn send_batch_reports_the_outcome_of_each_message() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "ErrorCode": 0, "Message": "OK", "MessageID": "b7bc2f4a" },
+                { "ErrorCode": 406, "Message": "Inactive recipient" },
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcomes = email_client.send_batch(&outgoing_emails(2)).await;
+
+        // Assert
+        let outcomes = assert_ok!(outcomes);
+        assert_eq!(outcomes.len(), 2);
+        let sent = assert_ok!(&outcomes[0]);
+        assert_eq!(sent.message_id.as_deref(), Some("b7bc2f4a"));
+        let error = assert_err!(&outcomes[1]);
+        assert!(error.is_permanent());
+    }
+
+    #[tokio::test]
+    async fn send_batch_reports_a_500_as_a_transient_failure_of_every_email() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcomes = email_client.send_batch(&outgoing_emails(2)).await;
+
+        // Assert
+        let outcomes = assert_ok!(outcomes);
+        assert_eq!(outcomes.len(), 2);
+        for outcome in outcomes {
+            let error = assert_err!(outcome);
+            assert!(!error.is_permanent());
+        }
+    }
+
+    #[tokio::test]
+    async fn send_batch_splits_large_batches_into_several_requests() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(|request: &Request| {
+                let body: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+                let results: Vec<_> = body
+                    .iter()
+                    .map(|_| serde_json::json!({ "ErrorCode": 0, "Message": "OK" }))
+                    .collect();
+                ResponseTemplate::new(200).set_body_json(results)
+            })
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcomes = email_client.send_batch(&outgoing_emails(501)).await;
+
+        // Assert
+        let outcomes = assert_ok!(outcomes);
+        assert_eq!(outcomes.len(), 501);
+    }
+}
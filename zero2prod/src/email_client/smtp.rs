@@ -0,0 +1,223 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use lettre::{
+    transport::smtp::authentication::Credentials, AsyncSmtpTransport, AsyncTransport,
+    Tokio1Executor,
+};
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+
+use crate::domain::SubscriberEmail;
+
+use super::{build_message, EmailError, EmailHeader, EmailSender, SentEmail};
+
+/// How the connection to the SMTP relay is secured.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Plain text connection, only suitable for a relay on a trusted network.
+    None,
+    /// Connect in plain text, then upgrade the connection with STARTTLS.
+    StartTls,
+    /// Implicit TLS from the first byte (SMTPS).
+    Implicit,
+}
+
+/// Sends emails through an SMTP relay.
+#[derive(Debug)]
+pub struct SmtpEmailClient {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    sender: SubscriberEmail,
+}
+
+impl SmtpEmailClient {
+    pub fn new(
+        host: &str,
+        port: u16,
+        tls: SmtpTls,
+        credentials: Option<(String, Secret<String>)>,
+        sender: SubscriberEmail,
+        timeout: std::time::Duration,
+    ) -> Result<Self, anyhow::Error> {
+        let builder = match tls {
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+            SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+                .context("Failed to set up STARTTLS for the SMTP relay.")?,
+            SmtpTls::Implicit => AsyncSmtpTransport::<Tokio1Executor>::relay(host)
+                .context("Failed to set up TLS for the SMTP relay.")?,
+        };
+        let mut builder = builder.port(port).timeout(Some(timeout));
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(
+                username,
+                password.expose_secret().to_owned(),
+            ));
+        }
+        Ok(Self {
+            transport: builder.build(),
+            sender,
+        })
+    }
+}
+
+#[async_trait]
+impl EmailSender for SmtpEmailClient {
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<SentEmail, EmailError> {
+        let message = build_message(
+            &self.sender,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            headers,
+        )
+        .map_err(EmailError::Permanent)?;
+        match self.transport.send(message).await {
+            Ok(_) => Ok(SentEmail::default()),
+            // 5xx replies mean the relay will never accept this email
+            Err(e) if e.is_permanent() => Err(EmailError::Permanent(
+                anyhow::Error::new(e).context("The SMTP relay rejected the email."),
+            )),
+            Err(e) => Err(EmailError::Transient(
+                anyhow::Error::new(e).context("Failed to hand the email to the SMTP relay."),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+    use fake::{faker::internet::en::SafeEmail, Fake};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+        task::JoinHandle,
+    };
+
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{EmailSender, SmtpEmailClient, SmtpTls},
+    };
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn email_client(port: u16) -> SmtpEmailClient {
+        SmtpEmailClient::new(
+            "127.0.0.1",
+            port,
+            SmtpTls::None,
+            None,
+            email(),
+            std::time::Duration::from_secs(5),
+        )
+        .unwrap()
+    }
+
+    /// A minimal SMTP relay that accepts a single session, answers `RCPT TO` with
+    /// `rcpt_reply` and returns everything the client sent.
+    async fn start_relay(rcpt_reply: &'static str) -> (u16, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let mut transcript = String::new();
+            let mut in_data = false;
+            writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+            while let Ok(Some(line)) = lines.next_line().await {
+                transcript.push_str(&line);
+                transcript.push('\n');
+                let reply = if in_data {
+                    if line != "." {
+                        continue;
+                    }
+                    in_data = false;
+                    "250 Queued"
+                } else {
+                    match line.split(':').next().unwrap_or_default() {
+                        "RCPT TO" => rcpt_reply,
+                        "DATA" => {
+                            in_data = true;
+                            "354 Go ahead"
+                        }
+                        "QUIT" => "221 Bye",
+                        _ => "250 OK",
+                    }
+                };
+                if writer
+                    .write_all(format!("{reply}\r\n").as_bytes())
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            transcript
+        });
+        (port, handle)
+    }
+
+    #[tokio::test]
+    async fn send_email_hands_the_message_to_the_relay() {
+        // Arrange
+        let (port, relay) = start_relay("250 OK").await;
+        let email_client = email_client(port);
+        let recipient = email();
+
+        // Act
+        let outcome = email_client
+            .send_email(&recipient, "Subject", "<p>Html body</p>", "Plain text body")
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        drop(email_client);
+        let transcript = relay.await.unwrap();
+        assert!(transcript.contains(&format!("RCPT TO:<{}>", recipient.as_ref())));
+        assert!(transcript.contains("Subject: Subject"));
+        assert!(transcript.contains("Plain text body"));
+    }
+
+    #[tokio::test]
+    async fn send_email_treats_a_5xx_reply_as_a_permanent_failure() {
+        // Arrange
+        let (port, _relay) = start_relay("550 No such user").await;
+        let email_client = email_client(port);
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), "Subject", "<p>Html body</p>", "Plain text body")
+            .await;
+
+        // Assert
+        let error = assert_err!(outcome);
+        assert!(error.is_permanent());
+    }
+
+    #[tokio::test]
+    async fn send_email_treats_a_4xx_reply_as_a_transient_failure() {
+        // Arrange
+        let (port, _relay) = start_relay("451 Try again later").await;
+        let email_client = email_client(port);
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), "Subject", "<p>Html body</p>", "Plain text body")
+            .await;
+
+        // Assert
+        let error = assert_err!(outcome);
+        assert!(!error.is_permanent());
+    }
+}
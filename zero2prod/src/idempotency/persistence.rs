@@ -137,6 +137,7 @@ pub async fn try_processing(
     }
 }
 
+#[allow(clippy::large_enum_variant)]
 pub enum NextAction {
     StartProcessing(Transaction<'static, Postgres>),
     ReturnSavedResponse(Response),
@@ -2,19 +2,26 @@ use std::time::Duration;
 
 use sqlx::PgPool;
 
-use crate::{configuration::Settings, startup::get_db_pool};
+use crate::{configuration::Settings, shutdown::Shutdown, startup::get_db_pool};
 
-pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
     // Set up the worker
     let connection_pool = get_db_pool(&configuration.database);
-    worker_loop(connection_pool).await
+    worker_loop(connection_pool, shutdown).await
 }
 
-async fn worker_loop(pool: PgPool) -> Result<(), anyhow::Error> {
-    loop {
+async fn worker_loop(pool: PgPool, shutdown: Shutdown) -> Result<(), anyhow::Error> {
+    while !shutdown.is_requested() {
         remove_old_idempotency_entries(&pool).await?;
-        tokio::time::sleep(Duration::from_secs(60 * 60 * 24)).await;
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(60 * 60 * 24)) => {}
+            _ = shutdown.clone().requested() => {}
+        }
     }
+    Ok(())
 }
 
 #[tracing::instrument(skip_all)]
@@ -1,18 +1,76 @@
-use std::time::Duration;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::Arc,
+    time::Duration,
+};
 
 use crate::{
-    configuration::Settings, domain::SubscriberEmail, email_client::EmailClient,
-    startup::get_db_pool,
+    configuration::{DeliveryWorkerSettings, Settings},
+    domain::{IssueTemplate, SubscriberEmail, TemplateValues},
+    email_client::{EmailError, EmailHeader, EmailSender, OutgoingEmail, SentEmail},
+    retry_policy::RetryPolicy,
+    routes::{build_unsubscribe_link, build_web_view_link},
+    shutdown::Shutdown,
+    startup::{ApplicationBaseUrl, HmacSecret},
+};
+use chrono::{DateTime, Utc};
+use sqlx::{
+    postgres::{PgListener, PgPoolOptions},
+    PgPool, Postgres, Transaction,
 };
-use sqlx::{PgPool, Postgres, Transaction};
-use tracing::{field::display, Span};
+use tokio::{sync::Notify, task::JoinSet};
+use tracing::Span;
 use uuid::Uuid;
 
-pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
-    // Set up the worker
-    let connection_pool = get_db_pool(&configuration.database);
-    let email_client = configuration.email_client.client();
-    worker_loop(connection_pool, email_client).await
+/// Deliver queued issues until a shutdown is requested.
+///
+/// A worker stops sending once a shutdown is requested, commits what it has sent and
+/// leaves the rest of its batch queued, so no delivery is left half-sent inside a rolled
+/// back transaction.
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    // Set up the workers
+    let settings = configuration.delivery_worker;
+    let concurrency = settings.concurrency.max(1);
+    // Each worker holds a transaction open while it sends, and runs lookups beside it
+    let connection_pool = PgPoolOptions::new()
+        .acquire_timeout(Duration::from_secs(2))
+        .max_connections(2 * concurrency as u32)
+        .connect_lazy_with(configuration.database.with_db());
+    let email_client = configuration.email_client.client()?;
+    let base_url = ApplicationBaseUrl(configuration.application.base_url);
+    let hmac_secret = HmacSecret(configuration.application.hmac_secret);
+
+    // Idle workers sleep until a new task is announced, with polling kept as a fallback
+    let wake_up = Arc::new(Notify::new());
+    let mut workers = JoinSet::new();
+    workers.spawn(listen_for_new_tasks(
+        connection_pool.clone(),
+        wake_up.clone(),
+        settings.error_backoff(),
+        shutdown.clone(),
+    ));
+    for _ in 0..concurrency {
+        workers.spawn(worker_loop(
+            connection_pool.clone(),
+            email_client.clone(),
+            base_url.clone(),
+            hmac_secret.clone(),
+            settings.clone(),
+            wake_up.clone(),
+            shutdown.clone(),
+        ));
+    }
+    tracing::info!("Started {} delivery workers", concurrency);
+
+    // A failing worker takes the others down with it when the set is dropped
+    while let Some(outcome) = workers.join_next().await {
+        outcome??;
+    }
+    tracing::info!("All delivery workers have stopped");
+    Ok(())
 }
 
 pub enum ExecutionOutcome {
@@ -21,116 +79,373 @@ pub enum ExecutionOutcome {
     EmptyQueue,
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
-    loop {
-        match try_execute_task(&pool, &email_client).await {
+async fn worker_loop(
+    pool: PgPool,
+    email_client: Arc<dyn EmailSender>,
+    base_url: ApplicationBaseUrl,
+    hmac_secret: HmacSecret,
+    settings: DeliveryWorkerSettings,
+    wake_up: Arc<Notify>,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    while !shutdown.is_requested() {
+        // Register interest before looking at the queue so a notification sent in between is kept
+        let notified = wake_up.notified();
+        tokio::pin!(notified);
+        notified.as_mut().enable();
+
+        match try_execute_task(
+            &pool,
+            email_client.as_ref(),
+            &base_url,
+            &hmac_secret,
+            &settings,
+            &shutdown,
+        )
+        .await
+        {
             Ok(ExecutionOutcome::EmptyQueue) => {
-                tokio::time::sleep(Duration::from_secs(10)).await;
+                tokio::select! {
+                    _ = notified => {}
+                    _ = tokio::time::sleep(settings.poll_interval()) => {}
+                    _ = shutdown.clone().requested() => {}
+                }
             }
             Ok(ExecutionOutcome::TaskQueuedForRetry) | Ok(ExecutionOutcome::TaskCompleted) => {}
             Err(_) => {
-                tokio::time::sleep(Duration::from_secs(1)).await;
+                tokio::select! {
+                    _ = tokio::time::sleep(settings.error_backoff()) => {}
+                    _ = shutdown.clone().requested() => {}
+                }
             }
         }
     }
+    Ok(())
+}
+
+/// The channel announcing that new deliveries have been queued.
+pub const DELIVERY_QUEUE_CHANNEL: &str = "issue_delivery_queue";
+
+/// Wake up idle delivery workers once the transaction commits.
+///
+/// Postgres holds back notifications until commit, so workers never see tasks that
+/// might still be rolled back.
+#[tracing::instrument(skip_all)]
+pub async fn notify_delivery_workers(
+    transaction: &mut Transaction<'static, Postgres>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!("SELECT pg_notify($1, '')", DELIVERY_QUEUE_CHANNEL)
+        .execute(transaction)
+        .await?;
+    Ok(())
+}
+
+/// Queue a delivery of the issue to every confirmed subscriber and wake up the workers.
+#[tracing::instrument(skip(transaction))]
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'static, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email
+        )
+        SELECT $1, email
+        FROM subscriptions
+        -- Pending, unsubscribed and suppressed readers never receive issues
+        WHERE status = 'confirmed'
+        "#,
+        newsletter_issue_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    notify_delivery_workers(transaction).await?;
+    Ok(())
+}
+
+/// Relay queue notifications from Postgres to the workers of this process until a shutdown
+/// is requested.
+async fn listen_for_new_tasks(
+    pool: PgPool,
+    wake_up: Arc<Notify>,
+    error_backoff: Duration,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    while !shutdown.is_requested() {
+        tokio::select! {
+            Err(e) = relay_notifications(&pool, &wake_up) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Lost the delivery queue listener. Falling back to polling until it reconnects.",
+                );
+                tokio::select! {
+                    _ = tokio::time::sleep(error_backoff) => {}
+                    _ = shutdown.clone().requested() => {}
+                }
+            }
+            _ = shutdown.clone().requested() => {}
+        }
+    }
+    Ok(())
+}
+
+async fn relay_notifications(pool: &PgPool, wake_up: &Notify) -> Result<(), anyhow::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(DELIVERY_QUEUE_CHANNEL).await?;
+    loop {
+        listener.recv().await?;
+        wake_up.notify_waiters();
+    }
 }
 
 #[tracing::instrument(
     skip_all,
     fields(
-        newsletter_issue_id=tracing::field::Empty,
-        subscriber_email=tracing::field::Empty,
+        batch_size=tracing::field::Empty,
      ),
      err
 )]
 pub async fn try_execute_task(
     pool: &PgPool,
-    email_client: &EmailClient,
+    email_client: &dyn EmailSender,
+    base_url: &ApplicationBaseUrl,
+    hmac_secret: &HmacSecret,
+    settings: &DeliveryWorkerSettings,
+    shutdown: &Shutdown,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
-    let task = dequeue_task(pool).await?;
-    if task.is_none() {
+    let retry_policy = &settings.retry_policy;
+    let batch = dequeue_tasks(pool, settings.batch_size.max(1).into()).await?;
+    if batch.is_none() {
         return Ok(ExecutionOutcome::EmptyQueue);
     }
-    let task = task.unwrap();
-    Span::current()
-        .record("newsletter_issue_id", &display(task.issue_id))
-        .record("subscriber_email", &display(&task.email));
-    if task.retries < 100 {
-        match SubscriberEmail::parse(task.email.clone()) {
-            Ok(email) => {
-                let issue = get_issue(pool, task.issue_id).await?;
-                if let Err(e) = email_client
-                    .send_email(
-                        &email,
-                        &issue.title,
-                        &issue.html_content,
-                        &issue.text_content,
-                    )
-                    .await
-                {
-                    tracing::error!(
-                        error.cause_chain = ?e,
-                        error.message = %e,
-                        "Failed to deliver issue to a confirmed subscriber. Skipping.",
-                    );
-                    return queue_retry_task(task).await;
-                }
-            }
+    let mut batch = batch.unwrap();
+    Span::current().record("batch_size", batch.tasks.len());
+
+    let recipients: Vec<String> = batch.tasks.iter().map(|t| t.email.clone()).collect();
+    let subscribers = get_subscribers(pool, &recipients).await?;
+    let mut issues = HashMap::new();
+
+    let mut completed = Vec::new();
+    let mut deliveries = Vec::new();
+    let mut failed = Vec::new();
+    let mut sendable = Vec::new();
+    let mut emails = Vec::new();
+    for task in std::mem::take(&mut batch.tasks) {
+        if retry_policy.is_exhausted(task.retries as u32) {
+            tracing::error!(
+                "Email task {}:{} has been retried {} times. Moving it to the failures.",
+                task.issue_id,
+                task.email,
+                task.retries
+            );
+            let error = format!(
+                "Gave up after {} attempts. Last error: {}",
+                task.retries,
+                task.last_error.as_deref().unwrap_or("unknown")
+            );
+            failed.push((task, error));
+            continue;
+        }
+        let email = match SubscriberEmail::parse(task.email.clone()) {
+            Ok(email) => email,
             Err(e) => {
                 tracing::error!(
                     error.cause_chain = ?e,
                     error.message = %e,
+                    subscriber_email = %task.email,
                     "Skipping a confirmed subscriber. Their stored contact details are invalid.",
                 );
                 // Don't attempt to retry for this error because the details are invalid and it
                 // will fail anyways
+                failed.push((task, format!("Invalid subscriber email: {}", e)));
+                continue;
             }
+        };
+        let subscriber = match subscribers.get(&task.email) {
+            Some(subscriber) => subscriber,
+            None => {
+                tracing::warn!(
+                    subscriber_email = %task.email,
+                    "Skipping a subscriber who is no longer on the mailing list."
+                );
+                deliveries.push(DeliveryRecord::skipped(&task));
+                completed.push(task);
+                continue;
+            }
+        };
+        if let Entry::Vacant(entry) = issues.entry(task.issue_id) {
+            entry.insert(get_issue(pool, task.issue_id).await?);
+        }
+        let issue: &NewsletterIssue = &issues[&task.issue_id];
+        let unsubscribe_url = build_unsubscribe_link(&base_url.0, subscriber.id, &hmac_secret.0);
+        let web_view_url = build_web_view_link(&base_url.0, task.issue_id);
+        let values = TemplateValues {
+            name: Some(&subscriber.name),
+            unsubscribe_url: Some(&unsubscribe_url),
+            web_view_url: Some(&web_view_url),
+        };
+        emails.push(OutgoingEmail {
+            recipient: email,
+            subject: issue.title.clone(),
+            html_content: issue.html_content.render_html(&values),
+            text_content: issue.text_content.render_text(&values),
+            headers: list_unsubscribe_headers(&unsubscribe_url).to_vec(),
+        });
+        sendable.push(task);
+    }
+
+    let mut retried = Vec::new();
+    let mut sendable = sendable.into_iter();
+    for chunk in emails.chunks(email_client.max_batch_size().max(1)) {
+        // What went out so far is committed below, the rest stays queued for the next run
+        if shutdown.is_requested() {
+            tracing::info!(
+                "Shutting down with {} deliveries of the batch still queued.",
+                sendable.len()
+            );
+            break;
         }
+        let outcomes = match email_client.send_batch(chunk).await {
+            Ok(outcomes) => outcomes,
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to deliver a batch of issues. Skipping.",
+                );
+                let error = format!("{:#}", e);
+                chunk
+                    .iter()
+                    .map(|_| Err(EmailError::Transient(anyhow::anyhow!(error.clone()))))
+                    .collect()
+            }
+        };
+        for (mut task, outcome) in sendable.by_ref().take(chunk.len()).zip(outcomes) {
+            let e = match outcome {
+                Ok(sent) => {
+                    deliveries.push(DeliveryRecord::sent(&task, sent));
+                    completed.push(task);
+                    continue;
+                }
+                Err(e) => e,
+            };
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                newsletter_issue_id = %task.issue_id,
+                subscriber_email = %task.email,
+                "Failed to deliver issue to a confirmed subscriber. Skipping.",
+            );
+            task.retries += 1;
+            if e.is_permanent() {
+                // Sending the same email again would only be refused again
+                failed.push((task, e.to_string()));
+            } else if retry_policy.is_exhausted(task.retries as u32) {
+                let error = format!("Gave up after {} attempts. Last error: {}", task.retries, e);
+                failed.push((task, error));
+            } else {
+                retried.push((task, e.to_string()));
+            }
+        }
+    }
+
+    deliveries.extend(
+        failed
+            .iter()
+            .map(|(task, error)| DeliveryRecord::failed(task, error)),
+    );
+
+    delete_tasks(&mut batch.transaction, &completed).await?;
+    move_tasks_to_failures(&mut batch.transaction, &failed).await?;
+    record_deliveries(&mut batch.transaction, &deliveries).await?;
+    queue_retry_tasks(&mut batch.transaction, &retried, retry_policy).await?;
+    batch.transaction.commit().await?;
+
+    if retried.is_empty() {
+        Ok(ExecutionOutcome::TaskCompleted)
     } else {
-        tracing::error!(
-            "Email task {}:{} has been retried 100 times. Dropping task.",
-            task.issue_id,
-            task.email
-        );
+        Ok(ExecutionOutcome::TaskQueuedForRetry)
     }
-    delete_task(task).await?;
-    Ok(ExecutionOutcome::TaskCompleted)
 }
 
-#[tracing::instrument(name = "DEQUEUE TASK", skip_all)]
-async fn dequeue_task(pool: &PgPool) -> Result<Option<EmailTask>, anyhow::Error> {
+#[tracing::instrument(name = "DEQUEUE TASKS", skip(pool))]
+async fn dequeue_tasks(pool: &PgPool, limit: i64) -> Result<Option<TaskBatch>, anyhow::Error> {
     let mut transaction = pool.begin().await?;
 
-    let r = sqlx::query!(
+    let rows = sqlx::query!(
         r#"
-        SELECT newsletter_issue_id, subscriber_email, retries
+        SELECT newsletter_issue_id, subscriber_email, retries, last_error
         FROM issue_delivery_queue
         WHERE
             retry_after IS NULL OR now() > retry_after
         FOR UPDATE
         SKIP LOCKED
-        LIMIT 1
-        "#
+        LIMIT $1
+        "#,
+        limit
     )
-    .fetch_optional(&mut transaction)
+    .fetch_all(&mut transaction)
     .await?;
 
-    if let Some(r) = r {
-        Ok(Some(EmailTask {
-            transaction,
+    if rows.is_empty() {
+        return Ok(None);
+    }
+    let tasks = rows
+        .into_iter()
+        .map(|r| EmailTask {
             issue_id: r.newsletter_issue_id,
             email: r.subscriber_email,
             retries: r.retries,
-        }))
-    } else {
-        Ok(None)
-    }
+            last_error: r.last_error,
+        })
+        .collect();
+    Ok(Some(TaskBatch { transaction, tasks }))
+}
+
+/// Build the RFC 8058 headers that let mailbox providers offer a one-click unsubscribe.
+fn list_unsubscribe_headers(unsubscribe_url: &str) -> [EmailHeader; 2] {
+    [
+        EmailHeader::new("List-Unsubscribe", format!("<{}>", unsubscribe_url)),
+        EmailHeader::new("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"),
+    ]
+}
+
+/// Look up the subscribers still on the mailing list, keyed by email.
+#[tracing::instrument(skip_all)]
+async fn get_subscribers(
+    pool: &PgPool,
+    emails: &[String],
+) -> Result<HashMap<String, Subscriber>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, email, name
+        FROM subscriptions
+        WHERE email = ANY($1)
+        "#,
+        emails
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| {
+            (
+                r.email,
+                Subscriber {
+                    id: r.id,
+                    name: r.name,
+                },
+            )
+        })
+        .collect())
 }
 
 #[tracing::instrument(skip_all)]
 async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, anyhow::Error> {
-    let issue = sqlx::query_as!(
-        NewsletterIssue,
+    let issue = sqlx::query!(
         r#"
         SELECT title, text_content, html_content
         FROM newsletter_issues
@@ -141,58 +456,302 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
     )
     .fetch_one(pool)
     .await?;
-    Ok(issue)
+    Ok(NewsletterIssue {
+        title: issue.title,
+        text_content: parse_template(issue_id, &issue.text_content),
+        html_content: parse_template(issue_id, &issue.html_content),
+    })
+}
+
+/// Issues are validated when they are published, but ones published before placeholders
+/// existed may contain stray braces. Those go out exactly as they were written.
+fn parse_template(issue_id: Uuid, content: &str) -> IssueTemplate {
+    IssueTemplate::parse(content).unwrap_or_else(|e| {
+        tracing::warn!(
+            error.message = %e,
+            %issue_id,
+            "Sending a newsletter issue without filling in its placeholders."
+        );
+        IssueTemplate::verbatim(content)
+    })
 }
 
 #[tracing::instrument(skip_all)]
-async fn delete_task(mut task: EmailTask) -> Result<(), anyhow::Error> {
+async fn delete_tasks(
+    transaction: &mut PgTransaction,
+    tasks: &[EmailTask],
+) -> Result<(), anyhow::Error> {
+    if tasks.is_empty() {
+        return Ok(());
+    }
+    let (issue_ids, emails) = task_keys(tasks);
     sqlx::query!(
         r#"
         DELETE FROM issue_delivery_queue
-        WHERE
-            newsletter_issue_id = $1 AND
-            subscriber_email = $2
+        WHERE (newsletter_issue_id, subscriber_email) IN (
+            SELECT * FROM UNNEST($1::uuid[], $2::text[])
+        )
         "#,
-        task.issue_id,
-        task.email,
+        &issue_ids,
+        &emails,
     )
-    .execute(&mut task.transaction)
+    .execute(&mut *transaction)
     .await?;
-    task.transaction.commit().await?;
     Ok(())
 }
 
 #[tracing::instrument(skip_all)]
-async fn queue_retry_task(mut task: EmailTask) -> Result<ExecutionOutcome, anyhow::Error> {
+async fn queue_retry_tasks(
+    transaction: &mut PgTransaction,
+    tasks: &[(EmailTask, String)],
+    retry_policy: &RetryPolicy,
+) -> Result<(), anyhow::Error> {
+    if tasks.is_empty() {
+        return Ok(());
+    }
+    let (issue_ids, emails, retries, errors) = failed_task_keys(tasks);
+    let now = Utc::now();
+    let retry_after: Vec<DateTime<Utc>> = tasks
+        .iter()
+        .map(|(task, _)| {
+            let delay = retry_policy.delay_for(task.retries as u32);
+            now + chrono::Duration::milliseconds(delay.as_millis() as i64)
+        })
+        .collect();
     sqlx::query!(
         r#"
-        UPDATE issue_delivery_queue
+        UPDATE issue_delivery_queue q
         SET
-            retries = retries + 1,
-            retry_after = now() + ((interval '1 sec') * retries ^ 2)
+            retries = f.retries,
+            retry_after = f.retry_after,
+            last_error = f.last_error
+        FROM UNNEST($1::uuid[], $2::text[], $3::int[], $4::text[], $5::timestamptz[])
+            AS f(newsletter_issue_id, subscriber_email, retries, last_error, retry_after)
         WHERE
-            newsletter_issue_id = $1 AND
-            subscriber_email = $2
+            q.newsletter_issue_id = f.newsletter_issue_id AND
+            q.subscriber_email = f.subscriber_email
         "#,
-        task.issue_id,
-        task.email,
+        &issue_ids,
+        &emails,
+        &retries,
+        &errors,
+        &retry_after,
     )
-    .execute(&mut task.transaction)
+    .execute(&mut *transaction)
     .await?;
-    task.transaction.commit().await?;
-    Ok(ExecutionOutcome::TaskQueuedForRetry)
+    Ok(())
+}
+
+/// Move tasks that can never succeed out of the queue and into the dead-letter table.
+#[tracing::instrument(skip_all)]
+async fn move_tasks_to_failures(
+    transaction: &mut PgTransaction,
+    tasks: &[(EmailTask, String)],
+) -> Result<(), anyhow::Error> {
+    if tasks.is_empty() {
+        return Ok(());
+    }
+    let (issue_ids, emails, retries, errors) = failed_task_keys(tasks);
+    sqlx::query!(
+        r#"
+        WITH failed AS (
+            DELETE FROM issue_delivery_queue q
+            USING UNNEST($1::uuid[], $2::text[], $3::int[], $4::text[])
+                AS f(newsletter_issue_id, subscriber_email, retries, last_error)
+            WHERE
+                q.newsletter_issue_id = f.newsletter_issue_id AND
+                q.subscriber_email = f.subscriber_email
+            RETURNING q.newsletter_issue_id, q.subscriber_email, f.retries, f.last_error, q.queued_at
+        )
+        INSERT INTO issue_delivery_failures (
+            newsletter_issue_id,
+            subscriber_email,
+            retries,
+            last_error,
+            queued_at
+        )
+        SELECT newsletter_issue_id, subscriber_email, retries, last_error, queued_at
+        FROM failed
+        -- A requeued delivery that fails again replaces its previous failure
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET
+            retries = EXCLUDED.retries,
+            last_error = EXCLUDED.last_error,
+            queued_at = EXCLUDED.queued_at,
+            failed_at = now()
+        "#,
+        &issue_ids,
+        &emails,
+        &retries,
+        &errors,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
+
+/// Write how each delivery ended to the delivery log.
+#[tracing::instrument(skip_all)]
+async fn record_deliveries(
+    transaction: &mut PgTransaction,
+    deliveries: &[DeliveryRecord],
+) -> Result<(), anyhow::Error> {
+    if deliveries.is_empty() {
+        return Ok(());
+    }
+    let mut issue_ids = Vec::with_capacity(deliveries.len());
+    let mut emails = Vec::with_capacity(deliveries.len());
+    let mut outcomes = Vec::with_capacity(deliveries.len());
+    let mut message_ids = Vec::with_capacity(deliveries.len());
+    let mut errors = Vec::with_capacity(deliveries.len());
+    for delivery in deliveries {
+        issue_ids.push(delivery.issue_id);
+        emails.push(delivery.email.clone());
+        outcomes.push(delivery.outcome.to_string());
+        // UNNEST can't carry NULLs here, so missing values travel as empty strings
+        message_ids.push(delivery.message_id.clone().unwrap_or_default());
+        errors.push(delivery.error.clone().unwrap_or_default());
+    }
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_deliveries (
+            newsletter_issue_id,
+            subscriber_email,
+            outcome,
+            provider_message_id,
+            error,
+            sent_at
+        )
+        SELECT
+            newsletter_issue_id,
+            subscriber_email,
+            outcome,
+            NULLIF(provider_message_id, ''),
+            NULLIF(error, ''),
+            CASE WHEN outcome = 'sent' THEN now() END
+        FROM UNNEST($1::uuid[], $2::text[], $3::text[], $4::text[], $5::text[])
+            AS d(newsletter_issue_id, subscriber_email, outcome, provider_message_id, error)
+        -- A requeued delivery replaces the outcome of its earlier attempt
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET
+            outcome = EXCLUDED.outcome,
+            provider_message_id = EXCLUDED.provider_message_id,
+            error = EXCLUDED.error,
+            sent_at = EXCLUDED.sent_at,
+            recorded_at = now()
+        "#,
+        &issue_ids,
+        &emails,
+        &outcomes,
+        &message_ids,
+        &errors,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
+
+/// Split tasks into the parallel arrays of queue keys that `UNNEST` expects.
+fn task_keys(tasks: &[EmailTask]) -> (Vec<Uuid>, Vec<String>) {
+    tasks.iter().map(|t| (t.issue_id, t.email.clone())).unzip()
+}
+
+/// Split failed tasks into the parallel arrays of queue keys, attempts and errors that
+/// `UNNEST` expects.
+fn failed_task_keys(
+    tasks: &[(EmailTask, String)],
+) -> (Vec<Uuid>, Vec<String>, Vec<i32>, Vec<String>) {
+    let mut issue_ids = Vec::with_capacity(tasks.len());
+    let mut emails = Vec::with_capacity(tasks.len());
+    let mut retries = Vec::with_capacity(tasks.len());
+    let mut errors = Vec::with_capacity(tasks.len());
+    for (task, error) in tasks {
+        issue_ids.push(task.issue_id);
+        emails.push(task.email.clone());
+        retries.push(task.retries);
+        errors.push(error.clone());
+    }
+    (issue_ids, emails, retries, errors)
 }
 
 type PgTransaction = Transaction<'static, Postgres>;
-struct EmailTask {
+struct TaskBatch {
     transaction: PgTransaction,
+    tasks: Vec<EmailTask>,
+}
+
+struct EmailTask {
     issue_id: Uuid,
     email: String,
     retries: i32,
+    last_error: Option<String>,
+}
+
+/// How a delivery ended, as written to the delivery log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DeliveryOutcome {
+    Sent,
+    Failed,
+    /// The subscriber left the mailing list before their turn came.
+    Skipped,
+}
+
+impl std::fmt::Display for DeliveryOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let outcome = match self {
+            DeliveryOutcome::Sent => "sent",
+            DeliveryOutcome::Failed => "failed",
+            DeliveryOutcome::Skipped => "skipped",
+        };
+        f.write_str(outcome)
+    }
+}
+
+struct DeliveryRecord {
+    issue_id: Uuid,
+    email: String,
+    outcome: DeliveryOutcome,
+    message_id: Option<String>,
+    error: Option<String>,
+}
+
+impl DeliveryRecord {
+    fn new(task: &EmailTask, outcome: DeliveryOutcome) -> Self {
+        Self {
+            issue_id: task.issue_id,
+            email: task.email.clone(),
+            outcome,
+            message_id: None,
+            error: None,
+        }
+    }
+
+    fn sent(task: &EmailTask, sent: SentEmail) -> Self {
+        Self {
+            message_id: sent.message_id,
+            ..Self::new(task, DeliveryOutcome::Sent)
+        }
+    }
+
+    fn failed(task: &EmailTask, error: &str) -> Self {
+        Self {
+            error: Some(error.to_owned()),
+            ..Self::new(task, DeliveryOutcome::Failed)
+        }
+    }
+
+    fn skipped(task: &EmailTask) -> Self {
+        Self::new(task, DeliveryOutcome::Skipped)
+    }
 }
 
 struct NewsletterIssue {
     title: String,
-    text_content: String,
-    html_content: String,
+    text_content: IssueTemplate,
+    html_content: IssueTemplate,
+}
+
+struct Subscriber {
+    id: Uuid,
+    name: String,
 }
@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    configuration::Settings, domain::check_ready_to_publish, routes::mark_issue_published,
+    shutdown::Shutdown, startup::get_db_pool,
+};
+
+/// How often the scheduler looks for issues that are due.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    // Set up the worker
+    let connection_pool = get_db_pool(&configuration.database);
+    worker_loop(connection_pool, shutdown).await
+}
+
+async fn worker_loop(pool: PgPool, shutdown: Shutdown) -> Result<(), anyhow::Error> {
+    while !shutdown.is_requested() {
+        if let Err(e) = publish_due_issues(&pool).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to publish the scheduled issues. Trying again later.",
+            );
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(POLL_INTERVAL) => {}
+            _ = shutdown.clone().requested() => {}
+        }
+    }
+    Ok(())
+}
+
+/// Publish every scheduled issue whose time has come and return how many were published.
+pub async fn publish_due_issues(pool: &PgPool) -> Result<usize, anyhow::Error> {
+    let mut published = 0;
+    // Incomplete issues stay scheduled until they are fixed, and are skipped until then
+    let mut skipped = Vec::new();
+    loop {
+        match try_publish_due_issue(pool, &skipped).await? {
+            DueIssue::Published => published += 1,
+            DueIssue::NotReady(issue_id) => skipped.push(issue_id),
+            DueIssue::None => return Ok(published),
+        }
+    }
+}
+
+enum DueIssue {
+    Published,
+    NotReady(Uuid),
+    None,
+}
+
+/// Publish one due issue, if there is any.
+///
+/// The issue stays locked until its deliveries are queued, so schedulers running in other
+/// instances skip it rather than queueing it a second time.
+#[tracing::instrument(skip_all, fields(newsletter_issue_id=tracing::field::Empty), err)]
+async fn try_publish_due_issue(pool: &PgPool, skipped: &[Uuid]) -> Result<DueIssue, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let issue = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id, title, html_content, text_content
+        FROM newsletter_issues
+        WHERE
+            status = 'scheduled' AND
+            scheduled_for <= now() AND
+            newsletter_issue_id <> ALL($1)
+        ORDER BY scheduled_for
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+        skipped
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    let Some(issue) = issue else {
+        return Ok(DueIssue::None);
+    };
+    tracing::Span::current().record(
+        "newsletter_issue_id",
+        tracing::field::display(issue.newsletter_issue_id),
+    );
+
+    if let Err(e) = check_ready_to_publish(&issue.title, &issue.html_content, &issue.text_content) {
+        tracing::error!(
+            error.message = %e,
+            "A scheduled issue is due but can't be published. Leaving it unpublished.",
+        );
+        return Ok(DueIssue::NotReady(issue.newsletter_issue_id));
+    }
+    mark_issue_published(&mut transaction, issue.newsletter_issue_id).await?;
+    transaction.commit().await?;
+    tracing::info!("Published a scheduled issue.");
+    Ok(DueIssue::Published)
+}
@@ -9,9 +9,13 @@ pub mod error;
 pub mod idempotency;
 pub mod idempotency_remover_worker;
 pub mod issue_delivery_worker;
+pub mod issue_scheduler_worker;
+pub mod retry_policy;
 pub mod routes;
 pub mod session_state;
+pub mod shutdown;
 pub mod startup;
+pub mod subscription_token_remover_worker;
 pub mod telemetry;
 
 pub fn error_chain_fmt(
@@ -36,6 +40,15 @@ where
     ResponseError::from(e).set_status(StatusCode::BAD_REQUEST)
 }
 
+pub fn e404<T>(e: T) -> ResponseError
+where
+    T: std::fmt::Debug,
+    T: std::fmt::Display + 'static,
+    T: Into<Box<dyn std::error::Error>>,
+{
+    ResponseError::from(e).set_status(StatusCode::NOT_FOUND)
+}
+
 pub fn e500<T>(e: T) -> ResponseError
 where
     T: std::fmt::Debug,
@@ -1,9 +1,10 @@
-use std::fmt::{Debug, Display};
+use std::future::Future;
 
-use tokio::task::JoinError;
+use tokio::task::{JoinError, JoinSet};
 use zero2prod::{
     configuration::get_configuration, idempotency_remover_worker, issue_delivery_worker,
-    startup::Application, telemetry,
+    issue_scheduler_worker, shutdown, startup::Application, subscription_token_remover_worker,
+    telemetry,
 };
 
 #[tokio::main]
@@ -14,31 +15,84 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Set up configuration
     let configuration = get_configuration().expect("failed to read configuration");
+    let shutdown_deadline = configuration.application.shutdown_deadline();
+    let (shutdown_trigger, shutdown) = shutdown::channel();
 
     let app = Application::build(configuration.clone()).await?;
-    let app_task = tokio::spawn(app.run_until_stopped());
-    let email_delivery_worker_task = tokio::spawn(issue_delivery_worker::run_worker_until_stopped(
-        configuration.clone(),
+    let mut tasks = JoinSet::new();
+    tasks.spawn(named("API", app.run_until_stopped(shutdown.clone())));
+    tasks.spawn(named(
+        "Email Delivery Worker",
+        issue_delivery_worker::run_worker_until_stopped(configuration.clone(), shutdown.clone()),
+    ));
+    tasks.spawn(named(
+        "Newsletter Scheduler Worker",
+        issue_scheduler_worker::run_worker_until_stopped(configuration.clone(), shutdown.clone()),
+    ));
+    tasks.spawn(named(
+        "Idempotency Cleaner Worker",
+        idempotency_remover_worker::run_worker_until_stopped(
+            configuration.clone(),
+            shutdown.clone(),
+        ),
+    ));
+    tasks.spawn(named(
+        "Subscription Token Cleaner Worker",
+        subscription_token_remover_worker::run_worker_until_stopped(configuration, shutdown),
     ));
-    let idempotency_cleaner_task = tokio::spawn(
-        idempotency_remover_worker::run_worker_until_stopped(configuration),
-    );
 
+    // Stop everything as soon as we are asked to, or as soon as any task exits on its own
     tokio::select! {
-        o = app_task => report_exit("API", o),
-        o = email_delivery_worker_task => report_exit("Email Delivery Worker", o),
-        o = idempotency_cleaner_task => report_exit("Idempotency Cleaner Worker", o)
+        Some(outcome) = tasks.join_next() => report_exit(outcome),
+        signal = shutdown::wait_for_signal() => {
+            if let Err(e) = signal {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to listen for shutdown signals"
+                );
+            }
+            tracing::info!("Shutdown requested");
+        }
     };
+    shutdown_trigger.trigger();
+
+    let drain = async {
+        while let Some(outcome) = tasks.join_next().await {
+            report_exit(outcome);
+        }
+    };
+    if tokio::time::timeout(shutdown_deadline, drain)
+        .await
+        .is_err()
+    {
+        tracing::warn!(
+            "Tasks did not stop within {} seconds. Aborting them.",
+            shutdown_deadline.as_secs()
+        );
+        tasks.shutdown().await;
+    }
 
     Ok(())
 }
 
-fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
+/// Tag a task's outcome with its name so it can be reported once it exits.
+async fn named<E>(
+    task_name: &'static str,
+    task: impl Future<Output = Result<(), E>>,
+) -> (&'static str, Result<(), anyhow::Error>)
+where
+    E: Into<anyhow::Error>,
+{
+    (task_name, task.await.map_err(Into::into))
+}
+
+fn report_exit(outcome: Result<(&str, Result<(), anyhow::Error>), JoinError>) {
     match outcome {
-        Ok(Ok(())) => {
+        Ok((task_name, Ok(()))) => {
             tracing::info!("{} has exited", task_name)
         }
-        Ok(Err(e)) => {
+        Ok((task_name, Err(e))) => {
             tracing::error!(
                 error.cause_chain = ?e,
                 error.message = %e,
@@ -50,8 +104,7 @@ fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>
             tracing::error!(
                 error.cause_chain = ?e,
                 error.message = %e,
-                "{} task failed to complete",
-                task_name
+                "A task failed to complete",
             )
         }
     }
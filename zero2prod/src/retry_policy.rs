@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+use rand::{thread_rng, Rng};
+use serde::Deserialize;
+use serde_aux::field_attributes::deserialize_number_from_string;
+
+/// How often, and how far apart, a failed delivery is attempted again.
+///
+/// The delay grows exponentially from `base_delay_milliseconds` by `multiplier` on every
+/// attempt, is capped at `max_delay_milliseconds`, and is then spread by up to `jitter`
+/// (a fraction of the delay) in either direction so retries from one batch don't all land
+/// at the same moment.
+#[derive(Clone, Debug, Deserialize)]
+pub struct RetryPolicy {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub base_delay_milliseconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub multiplier: f64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_delay_milliseconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub jitter: f64,
+    /// How many attempts a delivery gets in total before it is given up on.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_attempts: u32,
+}
+
+impl RetryPolicy {
+    /// How long to wait before the next attempt, given how many attempts already failed.
+    pub fn delay_for(&self, failed_attempts: u32) -> Duration {
+        let max_delay = self.max_delay_milliseconds as f64;
+        let exponent = failed_attempts.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay =
+            (self.base_delay_milliseconds as f64 * self.multiplier.powi(exponent)).min(max_delay);
+        let jitter = if self.jitter > 0.0 {
+            delay * self.jitter * thread_rng().gen_range(-1.0..=1.0)
+        } else {
+            0.0
+        };
+        Duration::from_millis((delay + jitter).clamp(0.0, max_delay) as u64)
+    }
+
+    /// Whether a delivery that failed this many times should not be attempted again.
+    pub fn is_exhausted(&self, failed_attempts: u32) -> bool {
+        failed_attempts >= self.max_attempts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RetryPolicy;
+
+    fn policy(jitter: f64) -> RetryPolicy {
+        RetryPolicy {
+            base_delay_milliseconds: 1_000,
+            multiplier: 2.0,
+            max_delay_milliseconds: 60_000,
+            jitter,
+            max_attempts: 5,
+        }
+    }
+
+    #[test]
+    fn the_delay_grows_exponentially() {
+        let policy = policy(0.0);
+        assert_eq!(policy.delay_for(1), Duration::from_secs(1));
+        assert_eq!(policy.delay_for(2), Duration::from_secs(2));
+        assert_eq!(policy.delay_for(3), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn the_delay_is_capped() {
+        let policy = policy(0.0);
+        assert_eq!(policy.delay_for(10), Duration::from_secs(60));
+        assert_eq!(policy.delay_for(u32::MAX), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let policy = policy(0.5);
+        for _ in 0..100 {
+            let delay = policy.delay_for(3);
+            assert!(delay >= Duration::from_secs(2) && delay <= Duration::from_secs(6));
+            assert!(policy.delay_for(10) <= Duration::from_secs(60));
+        }
+    }
+
+    #[test]
+    fn a_delivery_is_exhausted_after_max_attempts() {
+        let policy = policy(0.0);
+        assert!(!policy.is_exhausted(4));
+        assert!(policy.is_exhausted(5));
+    }
+}
@@ -1,13 +1,42 @@
+use std::fmt::Write;
+
+use axum_flash::IncomingFlashes;
+
 mod admin;
+mod feeds;
 mod health_check;
 mod home;
+mod invitations;
+mod issues;
 mod login;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
+mod webhooks;
 
 pub use admin::*;
+pub use feeds::*;
 pub use health_check::*;
 pub use home::*;
+pub use invitations::{accept_invitation, accept_invitation_form};
+pub use issues::*;
 pub use login::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_unsubscribe::*;
+pub use webhooks::*;
+
+/// Render flash messages for a page, escaping them since they can echo what the user typed.
+fn flash_messages_html(flashes: &IncomingFlashes) -> String {
+    let mut msg_html = String::new();
+    for (level, text) in flashes.iter() {
+        writeln!(
+            msg_html,
+            "<p><strong>{:?}</strong> - <i>{}</i></p>",
+            level,
+            html_escape::encode_text(text)
+        )
+        .unwrap();
+    }
+    msg_html
+}
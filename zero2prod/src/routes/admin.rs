@@ -1,9 +1,25 @@
+use super::flash_messages_html;
+
 pub mod newsletters;
 
 mod dashboard;
+mod deliveries;
+mod email;
+mod issues;
+mod login_locks;
 mod logout;
 mod password;
+mod two_factor;
+mod users;
 
 pub use dashboard::admin_dashboard;
+pub use deliveries::*;
+pub use email::{change_email, change_email_form};
+pub use issues::*;
+pub use login_locks::{clear_login_lock, list_login_locks};
 pub use logout::log_out;
 pub use password::*;
+pub use two_factor::{
+    confirm_two_factor, start_two_factor, turn_off_two_factor, two_factor_settings,
+};
+pub use users::*;
@@ -5,7 +5,7 @@ use http::StatusCode;
 use sqlx::PgPool;
 
 use crate::{
-    authentication::{get_username, UserId},
+    authentication::{get_username, Role, UserId},
     e500,
     error::ResponseError,
 };
@@ -14,9 +14,23 @@ use crate::{
 #[tracing::instrument(name = "Admin Dashboard", skip(pool, user_id))]
 pub async fn admin_dashboard(
     Extension(user_id): Extension<UserId>,
+    Extension(role): Extension<Role>,
     State(pool): State<PgPool>,
 ) -> Result<impl IntoResponse, ResponseError> {
     let username = get_username(*user_id, &pool).await.map_err(e500)?;
+    // Only link to what the user is allowed to do
+    let issues_link = if role >= Role::Editor {
+        r#"<li><a href="/admin/issues">Write and publish newsletter issues</a></li>
+        <li><a href="/admin/newsletters">Send a newsletter issue</li>"#
+    } else {
+        r#"<li><a href="/admin/issues">Read newsletter issues</a></li>"#
+    };
+    let users_link = if role == Role::Owner {
+        r#"<li><a href="/admin/users">Manage users</a></li>
+        <li><a href="/admin/login-locks">Review login lockouts</a></li>"#
+    } else {
+        ""
+    };
 
     let response = Html((
         StatusCode::OK,
@@ -28,11 +42,16 @@ pub async fn admin_dashboard(
     <title>Admin dashboard</title>
 </head>
 <body>
-    <p>Welcome {username}!</p>
+    <p>Welcome {username}! You are logged in as {role}.</p>
     <p>Available actions:</p>
     <ol>
-        <li><a href="/admin/newsletters">Send a newsletter issue</li>
+        {issues_link}
+        <li><a href="/admin/deliveries">Check delivery status</a></li>
+        <li><a href="/admin/deliveries/failures">Review failed deliveries</a></li>
+        {users_link}
         <li><a href="/admin/password">Change password</a></li>
+        <li><a href="/admin/email">Change email address</a></li>
+        <li><a href="/admin/two-factor">Two-factor authentication</a></li>
         <li>
             <form name="logoutForm" action="/admin/logout" method="post">
                 <input type="submit" value="Logout">
@@ -0,0 +1,7 @@
+mod get;
+mod post;
+mod status;
+
+pub use get::delivery_failures;
+pub use post::requeue_delivery_failures;
+pub use status::{delivery_status, issue_delivery_status};
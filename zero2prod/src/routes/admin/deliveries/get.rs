@@ -0,0 +1,122 @@
+use std::fmt::Write;
+
+use anyhow::Context;
+use axum::{extract::State, response::IntoResponse};
+use axum_extra::response::Html;
+use axum_flash::IncomingFlashes;
+use chrono::{DateTime, Utc};
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{e500, error::ResponseError, routes::flash_messages_html};
+
+#[tracing::instrument(name = "Delivery failures", skip(flashes, pool))]
+pub async fn delivery_failures(
+    flashes: IncomingFlashes,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let msg_html = flash_messages_html(&flashes);
+
+    let failures = get_delivery_failures(&pool)
+        .await
+        .context("Failed to fetch delivery failures")
+        .map_err(e500)?;
+
+    let mut failures_html = String::new();
+    let mut current_issue = None;
+    for failure in &failures {
+        if current_issue != Some(failure.newsletter_issue_id) {
+            if current_issue.is_some() {
+                failures_html.push_str("    </table>\n");
+            }
+            current_issue = Some(failure.newsletter_issue_id);
+            writeln!(
+                failures_html,
+                r#"    <h2>{}</h2>
+    <table>
+        <tr><th></th><th>Subscriber</th><th>Attempts</th><th>Last error</th><th>Queued at</th><th>Failed at</th></tr>"#,
+                encode_text(&failure.title)
+            )
+            .unwrap();
+        }
+        writeln!(
+            failures_html,
+            r#"        <tr>
+            <td><input type="checkbox" name="failure" value="{issue_id}:{email_attribute}"></td>
+            <td>{email}</td>
+            <td>{retries}</td>
+            <td>{last_error}</td>
+            <td>{queued_at}</td>
+            <td>{failed_at}</td>
+        </tr>"#,
+            issue_id = failure.newsletter_issue_id,
+            email_attribute = encode_double_quoted_attribute(&failure.subscriber_email),
+            email = encode_text(&failure.subscriber_email),
+            retries = failure.retries,
+            last_error = encode_text(&failure.last_error),
+            queued_at = failure.queued_at.to_rfc3339(),
+            failed_at = failure.failed_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+    let body = if failures.is_empty() {
+        "<p>There are no failed deliveries.</p>".to_string()
+    } else {
+        format!(
+            r#"<form action="/admin/deliveries/failures" method="post">
+{failures_html}    </table>
+    <button type="submit">Requeue selected</button>
+    </form>"#
+        )
+    };
+
+    let page = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Delivery failures</title>
+</head>
+<body>
+    {msg_html}
+    <h1>Delivery failures</h1>
+    {body}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#
+    );
+    Ok((flashes, Html(page)))
+}
+
+struct DeliveryFailure {
+    newsletter_issue_id: Uuid,
+    title: String,
+    subscriber_email: String,
+    retries: i32,
+    last_error: String,
+    queued_at: DateTime<Utc>,
+    failed_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_delivery_failures(pool: &PgPool) -> Result<Vec<DeliveryFailure>, sqlx::Error> {
+    sqlx::query_as!(
+        DeliveryFailure,
+        r#"
+        SELECT
+            f.newsletter_issue_id,
+            i.title,
+            f.subscriber_email,
+            f.retries,
+            f.last_error,
+            f.queued_at,
+            f.failed_at
+        FROM issue_delivery_failures f
+        JOIN newsletter_issues i ON i.newsletter_issue_id = f.newsletter_issue_id
+        ORDER BY i.published_at DESC, f.newsletter_issue_id, f.subscriber_email
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,88 @@
+use anyhow::Context;
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect},
+};
+use axum_extra::extract::Form;
+use axum_flash::Flash;
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{e400, e500, error::ResponseError, issue_delivery_worker::notify_delivery_workers};
+
+#[tracing::instrument(name = "Requeue delivery failures", skip(flash, pool, form))]
+pub async fn requeue_delivery_failures(
+    flash: Flash,
+    State(pool): State<PgPool>,
+    Form(form): Form<FormData>,
+) -> Result<impl IntoResponse, ResponseError> {
+    if form.failure.is_empty() {
+        let flash = flash.error("Select at least one failed delivery to requeue.");
+        return Ok((flash, Redirect::to("/admin/deliveries/failures")).into_response());
+    }
+
+    let mut issue_ids = Vec::with_capacity(form.failure.len());
+    let mut emails = Vec::with_capacity(form.failure.len());
+    for failure in form.failure {
+        let (issue_id, email) = parse_failure_key(&failure).map_err(e400)?;
+        issue_ids.push(issue_id);
+        emails.push(email);
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let requeued = sqlx::query!(
+        r#"
+        WITH requeued AS (
+            DELETE FROM issue_delivery_failures f
+            USING UNNEST($1::uuid[], $2::text[]) AS s(newsletter_issue_id, subscriber_email)
+            WHERE
+                f.newsletter_issue_id = s.newsletter_issue_id AND
+                f.subscriber_email = s.subscriber_email
+            RETURNING f.newsletter_issue_id, f.subscriber_email
+        )
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+        SELECT newsletter_issue_id, subscriber_email
+        FROM requeued
+        ON CONFLICT DO NOTHING
+        "#,
+        &issue_ids,
+        &emails,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to requeue delivery failures")
+    .map_err(e500)?
+    .rows_affected();
+    notify_delivery_workers(&mut transaction)
+        .await
+        .context("Failed to notify the delivery workers")
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to requeue delivery failures.")
+        .map_err(e500)?;
+
+    let flash = flash.info(format!("Requeued {} failed deliveries.", requeued));
+    Ok((flash, Redirect::to("/admin/deliveries/failures")).into_response())
+}
+
+/// Split a `<issue id>:<subscriber email>` checkbox value.
+fn parse_failure_key(key: &str) -> Result<(Uuid, String), anyhow::Error> {
+    let (issue_id, email) = key
+        .split_once(':')
+        .context("A failed delivery is missing its subscriber email.")?;
+    let issue_id = Uuid::parse_str(issue_id).context("Invalid newsletter issue id.")?;
+    Ok((issue_id, email.to_owned()))
+}
+
+#[derive(Deserialize)]
+pub struct FormData {
+    #[serde(default)]
+    failure: Vec<String>,
+}
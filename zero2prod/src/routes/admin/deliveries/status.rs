@@ -0,0 +1,291 @@
+use std::fmt::Write;
+
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+};
+use axum_extra::response::Html;
+use chrono::{DateTime, Utc};
+use html_escape::encode_text;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{e404, e500, error::ResponseError};
+
+#[tracing::instrument(name = "Delivery status", skip(pool))]
+pub async fn delivery_status(
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let issues = get_delivery_stats(&pool)
+        .await
+        .context("Failed to fetch delivery statistics")
+        .map_err(e500)?;
+
+    let body = if issues.is_empty() {
+        "<p>No newsletter issues have been published yet.</p>".to_string()
+    } else {
+        let mut rows = String::new();
+        for issue in &issues {
+            writeln!(
+                rows,
+                r#"        <tr>
+            <td><a href="/admin/deliveries/{issue_id}">{title}</a></td>
+            <td>{published_at}</td>
+            <td>{progress}</td>
+        </tr>"#,
+                issue_id = issue.newsletter_issue_id,
+                title = encode_text(&issue.title),
+                published_at = issue.published_at(),
+                progress = issue.progress_html(),
+            )
+            .unwrap();
+        }
+        format!(
+            r#"<table>
+        <tr><th>Issue</th><th>Published at</th><th>Progress</th></tr>
+{rows}    </table>"#
+        )
+    };
+
+    let page = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Delivery status</title>
+</head>
+<body>
+    <h1>Delivery status</h1>
+    {body}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#
+    );
+    Ok(Html(page))
+}
+
+#[tracing::instrument(name = "Issue delivery status", skip(pool))]
+pub async fn issue_delivery_status(
+    Path(issue_id): Path<Uuid>,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let stats = get_issue_delivery_stats(&pool, issue_id)
+        .await
+        .context("Failed to fetch delivery statistics")
+        .map_err(e500)?
+        .ok_or_else(|| anyhow::anyhow!("There is no newsletter issue with id {}.", issue_id))
+        .map_err(e404)?;
+    let deliveries = get_latest_deliveries(&pool, issue_id)
+        .await
+        .context("Failed to fetch the delivery log")
+        .map_err(e500)?;
+
+    let mut deliveries_html = String::new();
+    for delivery in &deliveries {
+        writeln!(
+            deliveries_html,
+            r#"        <tr>
+            <td>{email}</td>
+            <td>{outcome}</td>
+            <td>{sent_at}</td>
+            <td>{message_id}</td>
+            <td>{error}</td>
+        </tr>"#,
+            email = encode_text(&delivery.subscriber_email),
+            outcome = encode_text(&delivery.outcome),
+            sent_at = delivery
+                .sent_at
+                .map(|sent_at| sent_at.to_rfc3339())
+                .unwrap_or_default(),
+            message_id = encode_text(delivery.provider_message_id.as_deref().unwrap_or("")),
+            error = encode_text(delivery.error.as_deref().unwrap_or("")),
+        )
+        .unwrap();
+    }
+    let log_html = if deliveries.is_empty() {
+        "<p>No deliveries have been recorded yet.</p>".to_string()
+    } else {
+        format!(
+            r#"<table>
+        <tr><th>Subscriber</th><th>Outcome</th><th>Sent at</th><th>Message id</th><th>Error</th></tr>
+{deliveries_html}    </table>"#
+        )
+    };
+
+    let page = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Delivery status</title>
+</head>
+<body>
+    <h1>{title}</h1>
+    <p>Published at {published_at}</p>
+    <p>{progress}</p>
+    <table>
+        <tr><th>Queued</th><td>{queued}</td></tr>
+        <tr><th>Retrying</th><td>{retrying}</td></tr>
+        <tr><th>Sent</th><td>{sent}</td></tr>
+        <tr><th>Failed</th><td>{failed}</td></tr>
+        <tr><th>Skipped</th><td>{skipped}</td></tr>
+    </table>
+    <h2>Latest deliveries</h2>
+    {log_html}
+    <p><a href="/admin/deliveries">&lt;- Back</a></p>
+</body>
+</html>"#,
+        title = encode_text(&stats.title),
+        published_at = stats.published_at(),
+        progress = stats.progress_html(),
+        queued = stats.queued,
+        retrying = stats.retrying,
+        sent = stats.sent,
+        failed = stats.failed,
+        skipped = stats.skipped,
+    );
+    Ok(Html(page))
+}
+
+/// Where the deliveries of one issue stand.
+struct DeliveryStats {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: Option<DateTime<Utc>>,
+    /// Waiting for their first attempt.
+    queued: i64,
+    /// Back in the queue after a transient failure.
+    retrying: i64,
+    sent: i64,
+    /// Sitting in the dead-letter table.
+    failed: i64,
+    /// Dropped because the subscriber left before their turn.
+    skipped: i64,
+}
+
+impl DeliveryStats {
+    fn published_at(&self) -> String {
+        self.published_at
+            .map(|published_at| published_at.to_rfc3339())
+            .unwrap_or_default()
+    }
+
+    fn total(&self) -> i64 {
+        self.queued + self.retrying + self.sent + self.failed + self.skipped
+    }
+
+    /// Deliveries that will not be attempted again.
+    fn finished(&self) -> i64 {
+        self.sent + self.failed + self.skipped
+    }
+
+    fn progress_html(&self) -> String {
+        format!(
+            r#"<progress max="{total}" value="{finished}"></progress> {finished} of {total} done"#,
+            total = self.total(),
+            finished = self.finished(),
+        )
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_delivery_stats(pool: &PgPool) -> Result<Vec<DeliveryStats>, sqlx::Error> {
+    sqlx::query_as!(
+        DeliveryStats,
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            i.published_at,
+            (SELECT COUNT(*) FROM issue_delivery_queue q
+                WHERE q.newsletter_issue_id = i.newsletter_issue_id AND q.retries = 0
+            ) AS "queued!",
+            (SELECT COUNT(*) FROM issue_delivery_queue q
+                WHERE q.newsletter_issue_id = i.newsletter_issue_id AND q.retries > 0
+            ) AS "retrying!",
+            (SELECT COUNT(*) FROM issue_deliveries d
+                WHERE d.newsletter_issue_id = i.newsletter_issue_id AND d.outcome = 'sent'
+            ) AS "sent!",
+            (SELECT COUNT(*) FROM issue_delivery_failures f
+                WHERE f.newsletter_issue_id = i.newsletter_issue_id
+            ) AS "failed!",
+            (SELECT COUNT(*) FROM issue_deliveries d
+                WHERE d.newsletter_issue_id = i.newsletter_issue_id AND d.outcome = 'skipped'
+            ) AS "skipped!"
+        FROM newsletter_issues i
+        WHERE i.status = 'published'
+        ORDER BY i.published_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_issue_delivery_stats(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Option<DeliveryStats>, sqlx::Error> {
+    sqlx::query_as!(
+        DeliveryStats,
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            i.published_at,
+            (SELECT COUNT(*) FROM issue_delivery_queue q
+                WHERE q.newsletter_issue_id = i.newsletter_issue_id AND q.retries = 0
+            ) AS "queued!",
+            (SELECT COUNT(*) FROM issue_delivery_queue q
+                WHERE q.newsletter_issue_id = i.newsletter_issue_id AND q.retries > 0
+            ) AS "retrying!",
+            (SELECT COUNT(*) FROM issue_deliveries d
+                WHERE d.newsletter_issue_id = i.newsletter_issue_id AND d.outcome = 'sent'
+            ) AS "sent!",
+            (SELECT COUNT(*) FROM issue_delivery_failures f
+                WHERE f.newsletter_issue_id = i.newsletter_issue_id
+            ) AS "failed!",
+            (SELECT COUNT(*) FROM issue_deliveries d
+                WHERE d.newsletter_issue_id = i.newsletter_issue_id AND d.outcome = 'skipped'
+            ) AS "skipped!"
+        FROM newsletter_issues i
+        WHERE i.newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+struct Delivery {
+    subscriber_email: String,
+    outcome: String,
+    provider_message_id: Option<String>,
+    error: Option<String>,
+    sent_at: Option<DateTime<Utc>>,
+}
+
+/// How many entries of the delivery log are shown on an issue's page.
+const LATEST_DELIVERIES: i64 = 100;
+
+#[tracing::instrument(skip(pool))]
+async fn get_latest_deliveries(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Vec<Delivery>, sqlx::Error> {
+    sqlx::query_as!(
+        Delivery,
+        r#"
+        SELECT subscriber_email, outcome, provider_message_id, error, sent_at
+        FROM issue_deliveries
+        WHERE newsletter_issue_id = $1
+        ORDER BY recorded_at DESC, subscriber_email
+        LIMIT $2
+        "#,
+        issue_id,
+        LATEST_DELIVERIES
+    )
+    .fetch_all(pool)
+    .await
+}
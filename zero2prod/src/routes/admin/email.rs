@@ -0,0 +1,148 @@
+use anyhow::Context;
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect},
+    Extension, Form,
+};
+use axum_extra::response::Html;
+use axum_flash::{Flash, IncomingFlashes};
+use html_escape::encode_text;
+use secrecy::Secret;
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::flash_messages_html;
+use crate::{
+    authentication::{
+        get_username, validate_credentials, AuthError, Credentials, PasswordHashing, UserId,
+    },
+    domain::SubscriberEmail,
+    e500,
+    error::ResponseError,
+};
+
+#[tracing::instrument(name = "Change email form", skip(flashes, pool))]
+pub async fn change_email_form(
+    flashes: IncomingFlashes,
+    Extension(user_id): Extension<UserId>,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let msg_html = flash_messages_html(&flashes);
+    let current = match get_email(*user_id, &pool).await.map_err(e500)? {
+        Some(email) => format!(
+            "<p>Password reset links are sent to <code>{}</code>.</p>",
+            encode_text(&email)
+        ),
+        None => "<p>You have no email address yet, so you can't reset a forgotten password.</p>"
+            .to_string(),
+    };
+
+    let body = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Change email</title>
+</head>
+<body>
+    {msg_html}
+    {current}
+    <form action="/admin/email" method="post">
+        <label>New email address
+            <input type="email" placeholder="Enter email address" name="email">
+        </label>
+        <br>
+        <label>Current password
+            <input type="password" placeholder="Enter current password" name="current_password">
+        </label>
+        <br>
+        <button type="submit">Change email</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#
+    );
+    Ok((flashes, Html(body)))
+}
+
+/// Set the address password reset links go to. Takes the password, so an unattended
+/// session isn't enough to take over the account through a reset.
+#[tracing::instrument(name = "Change email", skip(flash, pool, hashing, form))]
+pub async fn change_email(
+    flash: Flash,
+    Extension(user_id): Extension<UserId>,
+    State(pool): State<PgPool>,
+    State(hashing): State<PasswordHashing>,
+    Form(form): Form<FormData>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let email_page = Redirect::to("/admin/email");
+    let email = match SubscriberEmail::parse(form.email.trim().to_string()) {
+        Ok(email) => email,
+        Err(e) => return Ok((flash.error(e), email_page)),
+    };
+
+    let username = get_username(*user_id, &pool).await.map_err(e500)?;
+    let credentials = Credentials {
+        username,
+        password: form.current_password,
+    };
+    if let Err(e) = validate_credentials(credentials, &hashing, &pool).await {
+        return match e {
+            AuthError::InvalidCredentials(_) => {
+                let flash = flash.error("The current password is incorrect.");
+                Ok((flash, email_page))
+            }
+            AuthError::UnexpectedError(_) => Err(e500(e)),
+        };
+    }
+
+    let updated = sqlx::query!(
+        r#"
+        UPDATE users
+        SET email = $2
+        WHERE user_id = $1
+        "#,
+        *user_id,
+        email.as_ref()
+    )
+    .execute(&pool)
+    .await;
+    if let Err(e) = updated {
+        if let sqlx::Error::Database(db_error) = &e {
+            // Two users can't share an email address
+            if db_error.code().as_deref() == Some("23505") {
+                let flash = flash.error("Another user already has that email address.");
+                return Ok((flash, email_page));
+            }
+        }
+        return Err(e)
+            .context("Failed to update the email address")
+            .map_err(e500);
+    }
+
+    let flash = flash.info("Your email address has been changed.");
+    Ok((flash, email_page))
+}
+
+#[tracing::instrument(name = "Get email", skip(pool))]
+async fn get_email(user_id: Uuid, pool: &PgPool) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT email
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to retrieve the email address")?;
+    Ok(row.email)
+}
+
+#[derive(Deserialize)]
+pub struct FormData {
+    email: String,
+    current_password: Secret<String>,
+}
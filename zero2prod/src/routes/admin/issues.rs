@@ -0,0 +1,69 @@
+mod delete;
+mod edit;
+mod list;
+mod preview;
+mod publish;
+mod send_test;
+
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::flash_messages_html;
+
+pub use delete::delete_issue;
+pub use edit::{create_issue, edit_issue_form, new_issue_form, update_issue};
+pub use list::list_issues;
+pub use preview::preview_issue;
+pub use publish::{mark_issue_published, publish_issue};
+pub use send_test::send_test_email;
+
+/// A newsletter issue, whatever its state.
+struct NewsletterIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    text_content: String,
+    html_content: String,
+    /// Set when the bodies were generated from Markdown
+    markdown_content: Option<String>,
+    html_overridden: bool,
+    text_overridden: bool,
+    /// 'draft', 'scheduled' or 'published'
+    status: String,
+    published_at: Option<DateTime<Utc>>,
+    scheduled_for: Option<DateTime<Utc>>,
+    updated_at: DateTime<Utc>,
+}
+
+impl NewsletterIssue {
+    /// Published issues have gone out to subscribers and can no longer change.
+    fn is_published(&self) -> bool {
+        self.status == "published"
+    }
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<Option<NewsletterIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            markdown_content,
+            html_overridden,
+            text_overridden,
+            status,
+            published_at,
+            scheduled_for,
+            updated_at
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await
+}
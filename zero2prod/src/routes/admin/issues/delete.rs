@@ -0,0 +1,40 @@
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Redirect},
+};
+use axum_flash::Flash;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{e500, error::ResponseError};
+
+#[tracing::instrument(name = "Delete a draft newsletter issue", skip(flash, pool))]
+pub async fn delete_issue(
+    flash: Flash,
+    Path(issue_id): Path<Uuid>,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM newsletter_issues
+        WHERE
+            newsletter_issue_id = $1 AND
+            -- Published issues are referenced by their deliveries
+            status <> 'published'
+        "#,
+        issue_id
+    )
+    .execute(&pool)
+    .await
+    .context("Failed to delete the draft newsletter issue")
+    .map_err(e500)?
+    .rows_affected();
+
+    let flash = if deleted == 0 {
+        flash.error("Only draft issues can be deleted.")
+    } else {
+        flash.info("The draft has been deleted.")
+    };
+    Ok((flash, Redirect::to("/admin/issues")))
+}
@@ -0,0 +1,263 @@
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Redirect},
+    Form,
+};
+use axum_extra::response::Html;
+use axum_flash::{Flash, IncomingFlashes};
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    domain::{check_ready_to_publish, EmailLayout, IssueBody},
+    e404, e500,
+    error::ResponseError,
+};
+
+use super::{flash_messages_html, get_issue, NewsletterIssue};
+
+pub static DRAFT_SAVED_INFO_MESSAGE: &str = "The draft has been saved.";
+
+#[tracing::instrument(name = "New newsletter issue form", skip(flashes))]
+pub async fn new_issue_form(flashes: IncomingFlashes) -> impl IntoResponse {
+    let page = issue_form_page(
+        &flash_messages_html(&flashes),
+        "New newsletter issue",
+        "/admin/issues",
+        &FormData::default(),
+    );
+    (flashes, Html(page))
+}
+
+#[tracing::instrument(name = "Edit newsletter issue form", skip(flashes, pool))]
+pub async fn edit_issue_form(
+    flashes: IncomingFlashes,
+    Path(issue_id): Path<Uuid>,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let issue = get_issue(&pool, issue_id)
+        .await
+        .context("Failed to fetch the newsletter issue")
+        .map_err(e500)?
+        .ok_or_else(|| anyhow::anyhow!("There is no newsletter issue with id {}.", issue_id))
+        .map_err(e404)?;
+
+    let page = issue_form_page(
+        &flash_messages_html(&flashes),
+        "Edit newsletter issue",
+        &format!("/admin/issues/{}/edit", issue_id),
+        &FormData::from_issue(issue),
+    );
+    Ok((flashes, Html(page)))
+}
+
+#[tracing::instrument(
+    name = "Create a draft newsletter issue",
+    skip(flash, pool, layout, form)
+)]
+pub async fn create_issue(
+    flash: Flash,
+    State(pool): State<PgPool>,
+    State(layout): State<EmailLayout>,
+    Form(form): Form<FormData>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let body = form.body(&layout);
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            markdown_content,
+            html_overridden,
+            text_overridden,
+            status
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, 'draft')
+        "#,
+        issue_id,
+        form.title,
+        body.text_content,
+        body.html_content,
+        form.markdown_content(),
+        body.html_overridden,
+        body.text_overridden
+    )
+    .execute(&pool)
+    .await
+    .context("Failed to store the draft newsletter issue")
+    .map_err(e500)?;
+
+    let flash = flash.info(DRAFT_SAVED_INFO_MESSAGE);
+    Ok((
+        flash,
+        Redirect::to(&format!("/admin/issues/{}/edit", issue_id)),
+    ))
+}
+
+#[tracing::instrument(
+    name = "Update a draft newsletter issue",
+    skip(flash, pool, layout, form)
+)]
+pub async fn update_issue(
+    flash: Flash,
+    Path(issue_id): Path<Uuid>,
+    State(pool): State<PgPool>,
+    State(layout): State<EmailLayout>,
+    Form(form): Form<FormData>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let issue = get_issue(&pool, issue_id)
+        .await
+        .context("Failed to fetch the newsletter issue")
+        .map_err(e500)?
+        .ok_or_else(|| anyhow::anyhow!("There is no newsletter issue with id {}.", issue_id))
+        .map_err(e404)?;
+    if issue.is_published() {
+        let flash = flash.error("A published issue can no longer be edited.");
+        return Ok((flash, Redirect::to("/admin/issues")));
+    }
+
+    let body = form.body(&layout);
+    // Scheduled issues go out without anyone looking at them again
+    if issue.status == "scheduled" {
+        if let Err(e) = check_ready_to_publish(&form.title, &body.html_content, &body.text_content)
+        {
+            let flash = flash.error(format!("A scheduled issue can't be saved like this. {}", e));
+            return Ok((
+                flash,
+                Redirect::to(&format!("/admin/issues/{}/edit", issue_id)),
+            ));
+        }
+    }
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            title = $2,
+            text_content = $3,
+            html_content = $4,
+            markdown_content = $5,
+            html_overridden = $6,
+            text_overridden = $7,
+            updated_at = now()
+        WHERE
+            newsletter_issue_id = $1 AND
+            status <> 'published'
+        "#,
+        issue_id,
+        form.title,
+        body.text_content,
+        body.html_content,
+        form.markdown_content(),
+        body.html_overridden,
+        body.text_overridden
+    )
+    .execute(&pool)
+    .await
+    .context("Failed to update the draft newsletter issue")
+    .map_err(e500)?;
+
+    let flash = flash.info(DRAFT_SAVED_INFO_MESSAGE);
+    Ok((
+        flash,
+        Redirect::to(&format!("/admin/issues/{}/edit", issue_id)),
+    ))
+}
+
+fn issue_form_page(msg_html: &str, heading: &str, action: &str, issue: &FormData) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{heading}</title>
+</head>
+<body>
+    {msg_html}
+    <h1>{heading}</h1>
+    <form action="{action}" method="post" enctype="application/x-www-form-urlencoded">
+        <label>Newsletter Title
+            <input type="text" placeholder="Enter newsletter title" name="title" value="{title}">
+        </label>
+        <br>
+        <label>Markdown body
+            <textarea placeholder="Write the issue in Markdown" name="markdown_content">{markdown_content}</textarea>
+        </label>
+        <br>
+        <p>The html and plain text bodies are generated from the Markdown. Fill them in to
+            write either of them by hand instead.</p>
+        <label>Plain text body
+            <textarea placeholder="Enter plain text body" name="text_content">{text_content}</textarea>
+        </label>
+        <br>
+        <label>Html body
+            <textarea placeholder="Enter html body" name="html_content">{html_content}</textarea>
+        </label>
+        <br>
+        <p>All bodies can use {{{{ name }}}}, {{{{ unsubscribe_url }}}} and {{{{ web_view_url }}}}.
+            Put a fallback for a missing value after a pipe, e.g. {{{{ name | reader }}}}.</p>
+        <button type="submit">Save draft</button>
+    </form>
+    <p><a href="/admin/issues">&lt;- Back</a></p>
+</body>
+</html>"#,
+        title = encode_double_quoted_attribute(&issue.title),
+        markdown_content = encode_text(&issue.markdown_content),
+        text_content = encode_text(&issue.text_content),
+        html_content = encode_text(&issue.html_content),
+    )
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct FormData {
+    title: String,
+    #[serde(default)]
+    markdown_content: String,
+    #[serde(default)]
+    text_content: String,
+    #[serde(default)]
+    html_content: String,
+}
+
+impl FormData {
+    /// Fill the form with a stored issue. Bodies generated from its Markdown are left empty,
+    /// so only the ones written by hand show up as overrides.
+    fn from_issue(issue: NewsletterIssue) -> Self {
+        let markdown_content = issue.markdown_content.unwrap_or_default();
+        let written_by_hand = |overridden: bool, content: String| {
+            if markdown_content.is_empty() || overridden {
+                content
+            } else {
+                String::new()
+            }
+        };
+        Self {
+            title: issue.title,
+            text_content: written_by_hand(issue.text_overridden, issue.text_content),
+            html_content: written_by_hand(issue.html_overridden, issue.html_content),
+            markdown_content,
+        }
+    }
+
+    /// The bodies to store. Unlike a published issue, a draft may still be incomplete.
+    fn body(&self, layout: &EmailLayout) -> IssueBody {
+        match self.markdown_content() {
+            Some(markdown_content) => IssueBody::from_markdown(
+                markdown_content,
+                &self.html_content,
+                &self.text_content,
+                layout,
+            ),
+            None => IssueBody::hand_written(&self.html_content, &self.text_content),
+        }
+    }
+
+    fn markdown_content(&self) -> Option<&str> {
+        Some(self.markdown_content.as_str()).filter(|markdown| !markdown.trim().is_empty())
+    }
+}
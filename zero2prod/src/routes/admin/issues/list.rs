@@ -0,0 +1,116 @@
+use std::fmt::Write;
+
+use anyhow::Context;
+use axum::{extract::State, response::IntoResponse};
+use axum_extra::response::Html;
+use axum_flash::IncomingFlashes;
+use html_escape::encode_text;
+use sqlx::PgPool;
+
+use crate::{e500, error::ResponseError};
+
+use super::{flash_messages_html, NewsletterIssue};
+
+#[tracing::instrument(name = "List newsletter issues", skip(flashes, pool))]
+pub async fn list_issues(
+    flashes: IncomingFlashes,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let msg_html = flash_messages_html(&flashes);
+    let issues = get_issues(&pool)
+        .await
+        .context("Failed to fetch newsletter issues")
+        .map_err(e500)?;
+
+    let body = if issues.is_empty() {
+        "<p>There are no newsletter issues yet.</p>".to_string()
+    } else {
+        let mut rows = String::new();
+        for issue in &issues {
+            let issue_id = issue.newsletter_issue_id;
+            let actions = if issue.is_published() {
+                format!(
+                    r#"<a href="/admin/issues/{issue_id}/preview">Preview</a>
+                <a href="/admin/deliveries/{issue_id}">Delivery status</a>"#
+                )
+            } else {
+                format!(
+                    r#"<a href="/admin/issues/{issue_id}/edit">Edit</a>
+                <a href="/admin/issues/{issue_id}/preview">Preview and publish</a>
+                <form action="/admin/issues/{issue_id}/delete" method="post">
+                    <button type="submit">Delete</button>
+                </form>"#
+                )
+            };
+            writeln!(
+                rows,
+                r#"        <tr>
+            <td>{title}</td>
+            <td>{status}</td>
+            <td>{updated_at}</td>
+            <td>
+                {actions}
+            </td>
+        </tr>"#,
+                title = encode_text(&issue.title),
+                status = match issue.scheduled_for {
+                    Some(scheduled_for) if issue.status == "scheduled" => {
+                        format!("scheduled for {}", scheduled_for.to_rfc3339())
+                    }
+                    _ => issue.status.clone(),
+                },
+                updated_at = issue.updated_at.to_rfc3339(),
+            )
+            .unwrap();
+        }
+        format!(
+            r#"<table>
+        <tr><th>Title</th><th>Status</th><th>Last changed</th><th></th></tr>
+{rows}    </table>"#
+        )
+    };
+
+    let page = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Newsletter issues</title>
+</head>
+<body>
+    {msg_html}
+    <h1>Newsletter issues</h1>
+    <p><a href="/admin/issues/new">Write a new issue</a></p>
+    {body}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#
+    );
+    Ok((flashes, Html(page)))
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_issues(pool: &PgPool) -> Result<Vec<NewsletterIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            markdown_content,
+            html_overridden,
+            text_overridden,
+            status,
+            published_at,
+            scheduled_for,
+            updated_at
+        FROM newsletter_issues
+        -- Work in progress first, then the most recent issues
+        ORDER BY status = 'published', updated_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,85 @@
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+};
+use axum_extra::response::Html;
+use axum_flash::IncomingFlashes;
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{e404, e500, error::ResponseError};
+
+use super::{flash_messages_html, get_issue};
+
+/// Show both bodies of an issue side by side, as subscribers will see them.
+///
+/// The html body is rendered in a sandboxed frame so its markup and styles can't leak into
+/// the admin page.
+#[tracing::instrument(name = "Preview a newsletter issue", skip(flashes, pool))]
+pub async fn preview_issue(
+    flashes: IncomingFlashes,
+    Path(issue_id): Path<Uuid>,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let issue = get_issue(&pool, issue_id)
+        .await
+        .context("Failed to fetch the newsletter issue")
+        .map_err(e500)?
+        .ok_or_else(|| anyhow::anyhow!("There is no newsletter issue with id {}.", issue_id))
+        .map_err(e404)?;
+    let msg_html = flash_messages_html(&flashes);
+
+    let actions = if issue.is_published() {
+        format!(
+            r#"<p>Published at {}.</p>"#,
+            issue
+                .published_at
+                .map(|published_at| published_at.to_rfc3339())
+                .unwrap_or_default()
+        )
+    } else {
+        let idempotency_key = Uuid::new_v4();
+        format!(
+            r#"<p><a href="/admin/issues/{issue_id}/edit">Edit</a></p>
+    <form action="/admin/issues/{issue_id}/publish" method="post">
+        <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
+        <button type="submit">Publish to all subscribers</button>
+    </form>"#
+        )
+    };
+
+    let page = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Preview: {title}</title>
+</head>
+<body>
+    {msg_html}
+    <h1>{title}</h1>
+    <table>
+        <tr><th>Html body</th><th>Plain text body</th></tr>
+        <tr>
+            <td><iframe sandbox srcdoc="{html_content}" width="600" height="800"></iframe></td>
+            <td><pre>{text_content}</pre></td>
+        </tr>
+    </table>
+    <form action="/admin/issues/{issue_id}/test" method="post">
+        <label>Send a test email to
+            <input type="text" placeholder="Addresses, separated by commas" name="recipients">
+        </label>
+        <button type="submit">Send test email</button>
+    </form>
+    {actions}
+    <p><a href="/admin/issues">&lt;- Back</a></p>
+</body>
+</html>"#,
+        title = encode_text(&issue.title),
+        html_content = encode_double_quoted_attribute(&issue.html_content),
+        text_content = encode_text(&issue.text_content),
+    );
+    Ok((flashes, Html(page)))
+}
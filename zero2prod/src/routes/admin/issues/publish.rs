@@ -0,0 +1,113 @@
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Redirect},
+    Extension, Form,
+};
+use axum_flash::Flash;
+use serde::Deserialize;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    authentication::UserId,
+    domain::check_ready_to_publish,
+    e400, e500,
+    error::ResponseError,
+    idempotency::{save_response, try_processing, IdempotencyKey, NextAction},
+    issue_delivery_worker::enqueue_delivery_tasks,
+    routes::admin::newsletters::PUBLISH_SUCCESS_INFO_MESSAGE,
+};
+
+use super::get_issue;
+
+/// Publish a draft and queue its delivery to every confirmed subscriber.
+#[tracing::instrument(name = "Publish a draft newsletter issue", skip(flash, pool, form))]
+pub async fn publish_issue(
+    flash: Flash,
+    Extension(user_id): Extension<UserId>,
+    Path(issue_id): Path<Uuid>,
+    State(pool): State<PgPool>,
+    Form(form): Form<FormData>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let idempotency_key: IdempotencyKey = form.idempotency_key.try_into().map_err(e400)?;
+    if let Some(issue) = get_issue(&pool, issue_id)
+        .await
+        .context("Failed to fetch the newsletter issue")
+        .map_err(e500)?
+        .filter(|issue| !issue.is_published())
+    {
+        if let Err(e) =
+            check_ready_to_publish(&issue.title, &issue.html_content, &issue.text_content)
+        {
+            let flash = flash.error(format!("The issue can't be published. {}", e));
+            let preview = Redirect::to(&format!("/admin/issues/{}/preview", issue_id));
+            return Ok((flash, preview).into_response());
+        }
+    }
+    // A double submission waits for the first one and gets the same response back
+    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
+        .await
+        .map_err(e500)?
+    {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_response) => {
+            let flash = flash.info(PUBLISH_SUCCESS_INFO_MESSAGE);
+            return Ok((flash, saved_response).into_response());
+        }
+    };
+
+    let published = mark_issue_published(&mut transaction, issue_id)
+        .await
+        .context("Failed to publish the newsletter issue")
+        .map_err(e500)?;
+    if !published {
+        // Dropping the transaction forgets the idempotency key along with everything else
+        let flash = flash.error("This issue does not exist or has already been published.");
+        return Ok((flash, Redirect::to("/admin/issues")).into_response());
+    }
+
+    let flash = flash.info(PUBLISH_SUCCESS_INFO_MESSAGE);
+    let response = (flash, Redirect::to("/admin/issues")).into_response();
+    let response = save_response(transaction, &idempotency_key, *user_id, response)
+        .await
+        .map_err(e500)?;
+    Ok(response)
+}
+
+/// Flip an unpublished issue to published and queue its deliveries.
+///
+/// Returns `false` if the issue doesn't exist or was already published, in which case
+/// nothing is queued.
+#[tracing::instrument(skip(transaction))]
+pub async fn mark_issue_published(
+    transaction: &mut Transaction<'static, Postgres>,
+    issue_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let published = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            status = 'published',
+            published_at = now(),
+            updated_at = now()
+        WHERE
+            newsletter_issue_id = $1 AND
+            status <> 'published'
+        "#,
+        issue_id
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected()
+        > 0;
+    if published {
+        enqueue_delivery_tasks(transaction, issue_id).await?;
+    }
+    Ok(published)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FormData {
+    idempotency_key: String,
+}
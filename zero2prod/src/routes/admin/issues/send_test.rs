@@ -0,0 +1,166 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Redirect},
+    Form,
+};
+use axum_flash::Flash;
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    domain::{IssueTemplate, SubscriberEmail, TemplateValues},
+    e404, e500,
+    email_client::EmailSender,
+    error::ResponseError,
+    routes::build_web_view_link,
+    startup::ApplicationBaseUrl,
+};
+
+use super::get_issue;
+
+/// Keeps the test action from turning into a way to mail a whole list.
+const MAX_TEST_RECIPIENTS: usize = 10;
+
+/// Send an issue, exactly as subscribers would get it, to a few addresses chosen by the admin.
+///
+/// Test emails bypass the delivery queue and leave the issue untouched, so they can be sent
+/// for drafts as often as needed. Placeholders about the subscriber render their fallback.
+#[tracing::instrument(
+    name = "Send a test email of a newsletter issue",
+    skip(flash, pool, email_client, base_url, form)
+)]
+pub async fn send_test_email(
+    flash: Flash,
+    Path(issue_id): Path<Uuid>,
+    State(pool): State<PgPool>,
+    State(email_client): State<Arc<dyn EmailSender>>,
+    State(base_url): State<ApplicationBaseUrl>,
+    Form(form): Form<FormData>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let issue = get_issue(&pool, issue_id)
+        .await
+        .context("Failed to fetch the newsletter issue")
+        .map_err(e500)?
+        .ok_or_else(|| anyhow::anyhow!("There is no newsletter issue with id {}.", issue_id))
+        .map_err(e404)?;
+    let preview = Redirect::to(&format!("/admin/issues/{}/preview", issue_id));
+
+    let recipients = match parse_recipients(&form.recipients) {
+        Ok(recipients) => recipients,
+        Err(e) => return Ok((flash.error(e), preview)),
+    };
+
+    let (html_content, text_content) = match IssueTemplate::parse(&issue.html_content)
+        .and_then(|html| Ok((html, IssueTemplate::parse(&issue.text_content)?)))
+    {
+        Ok((html, text)) => {
+            let web_view_url = build_web_view_link(&base_url.0, issue_id);
+            let values = TemplateValues {
+                web_view_url: Some(&web_view_url),
+                ..TemplateValues::default()
+            };
+            (html.render_html(&values), text.render_text(&values))
+        }
+        Err(e) => return Ok((flash.error(e.to_string()), preview)),
+    };
+
+    let subject = format!("[TEST] {}", issue.title);
+    let mut failed = Vec::new();
+    for recipient in &recipients {
+        if let Err(e) = email_client
+            .send_email(recipient, &subject, &html_content, &text_content)
+            .await
+        {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send a test email to {}.",
+                recipient
+            );
+            failed.push(recipient.as_ref());
+        }
+    }
+
+    let flash = if failed.is_empty() {
+        flash.info(format!(
+            "A test email has been sent to {}.",
+            join(&recipients)
+        ))
+    } else {
+        flash.error(format!(
+            "Failed to send the test email to {}.",
+            failed.join(", ")
+        ))
+    };
+    Ok((flash, preview))
+}
+
+/// Split the recipients field on commas and whitespace and validate every address.
+fn parse_recipients(recipients: &str) -> Result<Vec<SubscriberEmail>, String> {
+    let recipients = recipients
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|recipient| !recipient.is_empty())
+        .map(|recipient| SubscriberEmail::parse(recipient.to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if recipients.is_empty() {
+        return Err("Enter at least one address to send the test email to.".into());
+    }
+    if recipients.len() > MAX_TEST_RECIPIENTS {
+        return Err(format!(
+            "A test email can be sent to at most {} addresses.",
+            MAX_TEST_RECIPIENTS
+        ));
+    }
+    Ok(recipients)
+}
+
+fn join(recipients: &[SubscriberEmail]) -> String {
+    recipients
+        .iter()
+        .map(AsRef::as_ref)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FormData {
+    recipients: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use super::{parse_recipients, MAX_TEST_RECIPIENTS};
+
+    #[test]
+    fn recipients_can_be_separated_by_commas_and_whitespace() {
+        let recipients = assert_ok!(parse_recipients(
+            "ursula@example.com, le.guin@example.com\nearthsea@example.com"
+        ));
+        assert_eq!(recipients.len(), 3);
+    }
+
+    #[test]
+    fn an_empty_recipient_list_is_rejected() {
+        assert_err!(parse_recipients(" , \n"));
+    }
+
+    #[test]
+    fn an_invalid_address_is_rejected() {
+        assert_err!(parse_recipients("ursula@example.com, ursula.example.com"));
+    }
+
+    #[test]
+    fn too_many_recipients_are_rejected() {
+        let recipients = (0..=MAX_TEST_RECIPIENTS)
+            .map(|i| format!("admin{}@example.com", i))
+            .collect::<Vec<_>>()
+            .join(",");
+        assert_err!(parse_recipients(&recipients));
+    }
+}
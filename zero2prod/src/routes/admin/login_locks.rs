@@ -0,0 +1,85 @@
+use std::fmt::Write;
+
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect},
+    Form,
+};
+use axum_extra::response::Html;
+use axum_flash::{Flash, IncomingFlashes};
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use serde::Deserialize;
+
+use super::flash_messages_html;
+use crate::{authentication::LoginThrottle, e500, error::ResponseError};
+
+/// Usernames and addresses with failed logins, and whether they are locked out.
+#[tracing::instrument(name = "List login locks", skip(flashes, throttle))]
+pub async fn list_login_locks(
+    flashes: IncomingFlashes,
+    State(throttle): State<LoginThrottle>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let msg_html = flash_messages_html(&flashes);
+    let locks = throttle.locks().await.map_err(e500)?;
+
+    let mut rows = String::new();
+    for lock in &locks {
+        writeln!(
+            rows,
+            r#"<tr><td>{key}</td><td>{failures}</td><td>{status}</td><td>{expires_in}s</td><td>
+    <form action="/admin/login-locks/clear" method="post">
+        <input hidden type="text" name="key" value="{key_value}">
+        <button type="submit">Clear</button>
+    </form></td></tr>"#,
+            key = encode_text(&lock.key),
+            failures = lock.failures,
+            status = if lock.locked { "locked" } else { "counting" },
+            expires_in = lock.expires_in_seconds,
+            key_value = encode_double_quoted_attribute(&lock.key),
+        )
+        .unwrap();
+    }
+    let table = if locks.is_empty() {
+        "<p>No failed logins on record.</p>".to_string()
+    } else {
+        format!(
+            r#"<table>
+    <tr><th>Username or address</th><th>Failed logins</th><th>Status</th><th>Forgotten in</th><th></th></tr>
+    {rows}
+    </table>"#
+        )
+    };
+
+    let body = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Login lockouts</title>
+</head>
+<body>
+    {msg_html}
+    {table}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#
+    );
+    Ok((flashes, Html(body)))
+}
+
+/// Lift a lock, e.g. for a user who mistyped their password too often.
+#[tracing::instrument(name = "Clear login lock", skip(flash, throttle))]
+pub async fn clear_login_lock(
+    flash: Flash,
+    State(throttle): State<LoginThrottle>,
+    Form(form): Form<FormData>,
+) -> Result<impl IntoResponse, ResponseError> {
+    throttle.clear(&form.key).await.map_err(e500)?;
+    let flash = flash.info(format!("The failed logins of {} were cleared.", form.key));
+    Ok((flash, Redirect::to("/admin/login-locks")))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FormData {
+    key: String,
+}
@@ -1,25 +1,15 @@
+use crate::{error::ResponseError, routes::flash_messages_html};
 use axum::response::IntoResponse;
 use axum_extra::response::Html;
 use axum_flash::IncomingFlashes;
 use axum_macros::debug_handler;
-use std::fmt::Write;
-
-use crate::error::ResponseError;
 
 #[debug_handler(state = axum_flash::Config)]
 #[tracing::instrument(name = "Publish newsletter issue", skip(flashes))]
 pub async fn newsletters_publish_form(
     flashes: IncomingFlashes,
 ) -> Result<impl IntoResponse, ResponseError> {
-    let mut msg_html = String::new();
-    for (level, text) in flashes.iter() {
-        writeln!(
-            msg_html,
-            "<p><strong>{:?}</strong> - <i>{}</i></p>",
-            level, text
-        )
-        .unwrap();
-    }
+    let msg_html = flash_messages_html(&flashes);
 
     let idempotency_key = uuid::Uuid::new_v4();
 
@@ -37,6 +27,12 @@ pub async fn newsletters_publish_form(
             <input type="text" placeholder="Enter newsletter title" name="title">
         </label>
         <br>
+        <label>Markdown body
+            <textarea placeholder="Write the issue in Markdown" name="markdown_content"></textarea>
+        </label>
+        <br>
+        <p>The html and plain text bodies are generated from the Markdown. Fill them in to
+            write either of them by hand instead.</p>
         <label>Plain text body
             <input type="textarea" placeholder="Enter plain text body" name="text_content">
         </label>
@@ -45,6 +41,12 @@ pub async fn newsletters_publish_form(
             <input type="textarea" placeholder="Enter html body" name="html_content">
         </label>
         <br>
+        <p>All bodies can use {{{{ name }}}}, {{{{ unsubscribe_url }}}} and {{{{ web_view_url }}}}.
+            Put a fallback for a missing value after a pipe, e.g. {{{{ name | reader }}}}.</p>
+        <label>Send at (optional)
+            <input type="text" placeholder="2023-10-30T09:00:00+02:00" name="send_at">
+        </label>
+        <br>
         <input hidden type="text" name="idempotency_key" value="{idempotency_key}"> 
         <button type="submit">Send newsletter</button>
     </form>
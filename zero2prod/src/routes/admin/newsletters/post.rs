@@ -6,14 +6,17 @@ use axum::{
 };
 use axum_flash::Flash;
 use axum_macros::debug_handler;
+use chrono::{DateTime, Utc};
 use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::{
     authentication::{get_username, UserId},
+    domain::{check_ready_to_publish, EmailLayout, IssueBody},
     e400, e500,
     error::ResponseError,
     idempotency::{save_response, try_processing, IdempotencyKey, NextAction},
+    issue_delivery_worker::enqueue_delivery_tasks,
 };
 
 use newsletter_types::*;
@@ -24,19 +27,20 @@ pub static PUBLISH_SUCCESS_INFO_MESSAGE: &str = "The newsletter issue has been a
 #[cfg_attr(any(test, debug_assertions), debug_handler(state = crate::startup::AppState ))]
 #[tracing::instrument(
     name = "Publish a newsletter",
-    skip(flash, db_pool, body),
+    skip(flash, db_pool, layout, body),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 pub async fn publish_newsletter(
     flash: Flash,
     Extension(user_id): Extension<UserId>,
     State(db_pool): State<PgPool>,
+    State(layout): State<EmailLayout>,
     body: Result<Form<FormData>, FormRejection>,
 ) -> Result<impl IntoResponse, ResponseError> {
-    tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
     let username = get_username(*user_id, &db_pool).await;
     if let Ok(username) = username {
-        tracing::Span::current().record("username", &tracing::field::display(username));
+        tracing::Span::current().record("username", tracing::field::display(username));
     }
 
     let body = if let Ok(body) = body {
@@ -51,11 +55,32 @@ pub async fn publish_newsletter(
 
     let FormData {
         title,
+        markdown_content,
         text_content,
         html_content,
         idempotency_key,
+        send_at,
     } = body.0;
 
+    let send_at = match parse_send_at(send_at.as_deref(), Utc::now()) {
+        Ok(send_at) => send_at,
+        Err(e) => {
+            let flash = flash.error(e);
+            return Ok((flash, Redirect::to("/admin/newsletters")).into_response());
+        }
+    };
+    let body = match IssueBody::compose(&markdown_content, &html_content, &text_content, &layout) {
+        Ok(body) => body,
+        Err(e) => {
+            let flash = flash.error(e);
+            return Ok((flash, Redirect::to("/admin/newsletters")).into_response());
+        }
+    };
+    if let Err(e) = check_ready_to_publish(&title, &body.html_content, &body.text_content) {
+        let flash = flash.error(format!("The issue can't be published. {}", e));
+        return Ok((flash, Redirect::to("/admin/newsletters")).into_response());
+    }
+
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
     // Concurrent idempotency requests wait for first to finish and then
     // Return early if we have a cached response
@@ -70,18 +95,31 @@ pub async fn publish_newsletter(
         }
     };
 
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
-        .await
-        .context("Failed to store newsletter issue details")
-        .map_err(e500)?;
-
-    enqueue_delivery_tasks(&mut transaction, issue_id)
-        .await
-        .context("Failed to enqueue delivery tasks")
-        .map_err(e500)?;
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &title,
+        &body,
+        Some(markdown_content.as_str()).filter(|markdown| !markdown.trim().is_empty()),
+        send_at,
+    )
+    .await
+    .context("Failed to store newsletter issue details")
+    .map_err(e500)?;
 
-    // Continue to make full email request if we did not have a cached response
-    let flash = flash.info(PUBLISH_SUCCESS_INFO_MESSAGE);
+    // Scheduled issues are queued by the scheduler worker once they are due
+    let flash = match send_at {
+        Some(send_at) => flash.info(format!(
+            "The newsletter issue has been scheduled for {}.",
+            send_at.to_rfc3339()
+        )),
+        None => {
+            enqueue_delivery_tasks(&mut transaction, issue_id)
+                .await
+                .context("Failed to enqueue delivery tasks")
+                .map_err(e500)?;
+            flash.info(PUBLISH_SUCCESS_INFO_MESSAGE)
+        }
+    };
     let response = (flash, Redirect::to("/admin/newsletters")).into_response();
     let response = save_response(transaction, &idempotency_key, *user_id, response)
         .await
@@ -93,10 +131,16 @@ pub async fn publish_newsletter(
 async fn insert_newsletter_issue(
     transaction: &mut Transaction<'static, Postgres>,
     title: &str,
-    text_content: &str,
-    html_content: &str,
+    body: &IssueBody,
+    markdown_content: Option<&str>,
+    scheduled_for: Option<DateTime<Utc>>,
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
+    let status = if scheduled_for.is_some() {
+        "scheduled"
+    } else {
+        "published"
+    };
     sqlx::query!(
         r#"
         INSERT INTO newsletter_issues (
@@ -104,14 +148,24 @@ async fn insert_newsletter_issue(
             title,
             text_content,
             html_content,
-            published_at
+            markdown_content,
+            html_overridden,
+            text_overridden,
+            status,
+            published_at,
+            scheduled_for
         )
-        VALUES ($1, $2, $3, $4, now())
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, CASE WHEN $8 = 'published' THEN now() END, $9)
         "#,
         newsletter_issue_id,
         title,
-        text_content,
-        html_content
+        body.text_content,
+        body.html_content,
+        markdown_content,
+        body.html_overridden,
+        body.text_overridden,
+        status,
+        scheduled_for
     )
     .execute(transaction)
     .await?;
@@ -119,26 +173,22 @@ async fn insert_newsletter_issue(
     Ok(newsletter_issue_id)
 }
 
-#[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
-    transaction: &mut Transaction<'static, Postgres>,
-    newsletter_issue_id: Uuid,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"
-        INSERT INTO issue_delivery_queue (
-            newsletter_issue_id,
-            subscriber_email
-        )
-        SELECT $1, email
-        FROM subscriptions
-        WHERE status = 'confirmed'
-        "#,
-        newsletter_issue_id
-    )
-    .execute(transaction)
-    .await?;
-    Ok(())
+/// Parse the optional send time of the form, where an empty field means "send now".
+fn parse_send_at(
+    send_at: Option<&str>,
+    now: DateTime<Utc>,
+) -> Result<Option<DateTime<Utc>>, &'static str> {
+    let send_at = match send_at.map(str::trim) {
+        None | Some("") => return Ok(None),
+        Some(send_at) => send_at,
+    };
+    let send_at = DateTime::parse_from_rfc3339(send_at)
+        .map_err(|_| "The send time must include a time zone, e.g. 2023-10-30T09:00:00+02:00")?
+        .with_timezone(&Utc);
+    if send_at <= now {
+        return Err("The send time must be in the future.");
+    }
+    Ok(Some(send_at))
 }
 
 mod newsletter_types {
@@ -146,9 +196,17 @@ mod newsletter_types {
     #[derive(Debug, serde::Deserialize, serde::Serialize)]
     pub struct FormData {
         pub title: String,
+        /// Generates both bodies below, unless they are filled in as well.
+        #[serde(default)]
+        pub markdown_content: String,
+        #[serde(default)]
         pub html_content: String,
+        #[serde(default)]
         pub text_content: String,
         pub idempotency_key: String,
+        /// When to send the issue, with its time zone. Sent right away when left empty.
+        #[serde(default)]
+        pub send_at: Option<String>,
     }
 }
 
@@ -158,7 +216,7 @@ mod newsletter_errors {
 
     use crate::error_chain_fmt;
 
-    #[allow(clippy::enum_variant_names)]
+    #[allow(clippy::enum_variant_names, dead_code)]
     #[derive(thiserror::Error)]
     pub enum PublishError {
         // #[error("Authentication failed")]
@@ -197,3 +255,39 @@ mod newsletter_errors {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use claims::{assert_err, assert_ok_eq};
+
+    use super::parse_send_at;
+
+    #[test]
+    fn an_empty_send_time_means_send_now() {
+        assert_ok_eq!(parse_send_at(None, Utc::now()), None);
+        assert_ok_eq!(parse_send_at(Some("  "), Utc::now()), None);
+    }
+
+    #[test]
+    fn send_times_are_converted_to_utc() {
+        let now = Utc.with_ymd_and_hms(2023, 10, 27, 12, 0, 0).unwrap();
+        let send_at = parse_send_at(Some("2023-10-30T09:00:00+02:00"), now);
+        assert_ok_eq!(
+            send_at,
+            Some(Utc.with_ymd_and_hms(2023, 10, 30, 7, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn send_times_without_a_time_zone_are_rejected() {
+        let now = Utc.with_ymd_and_hms(2023, 10, 27, 12, 0, 0).unwrap();
+        assert_err!(parse_send_at(Some("2023-10-30T09:00:00"), now));
+    }
+
+    #[test]
+    fn send_times_in_the_past_are_rejected() {
+        let now = Utc.with_ymd_and_hms(2023, 10, 27, 12, 0, 0).unwrap();
+        assert_err!(parse_send_at(Some("2023-10-27T11:59:59Z"), now));
+    }
+}
@@ -16,7 +16,8 @@ pub async fn change_password_form(
         writeln!(
             msg_html,
             "<p><strong>{:?}</strong>: <i>{}</i></p>\n",
-            level, text
+            level,
+            html_escape::encode_text(text)
         )
         .unwrap();
     }
@@ -9,23 +9,25 @@ use serde::Deserialize;
 use sqlx::PgPool;
 
 use crate::{
-    authentication::{get_username, validate_credentials, AuthError, Credentials, UserId},
+    authentication::{
+        get_username, validate_credentials, validate_new_password, AuthError, Credentials,
+        PasswordHashing, UserId,
+    },
     e500,
     error::ResponseError,
 };
 
-#[tracing::instrument(name = "Change password", skip(user_id, form))]
+#[tracing::instrument(name = "Change password", skip(user_id, hashing, form))]
 pub async fn change_password(
     flash: Flash,
     Extension(user_id): Extension<UserId>,
     State(pool): State<PgPool>,
+    State(hashing): State<PasswordHashing>,
     Form(form): Form<FormData>,
 ) -> Result<impl IntoResponse, ResponseError> {
     // Ensure the new password is the correct length
-    if form.new_password.expose_secret().len() < 12 || form.new_password.expose_secret().len() > 128
-    {
-        let flash = flash.error("The new password should be between 12 and 128 characters long.");
-        return Ok((flash, Redirect::to("/admin/password")).into_response());
+    if let Err(e) = validate_new_password(&form.new_password) {
+        return Ok((flash.error(e), Redirect::to("/admin/password")).into_response());
     }
 
     // Ensure the new password and confirmation match
@@ -43,7 +45,7 @@ pub async fn change_password(
         password: form.current_password,
     };
 
-    if let Err(e) = validate_credentials(credentials, &pool).await {
+    if let Err(e) = validate_credentials(credentials, &hashing, &pool).await {
         return match e {
             AuthError::InvalidCredentials(_) => {
                 let flash = flash.error("The current password is incorrect.");
@@ -53,7 +55,7 @@ pub async fn change_password(
         };
     }
 
-    crate::authentication::change_password(*user_id, form.new_password, &pool)
+    crate::authentication::change_password(*user_id, form.new_password, &hashing, &pool)
         .await
         .map_err(e500)?;
 
@@ -0,0 +1,186 @@
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect, Response},
+    Extension, Form,
+};
+use axum_extra::response::Html;
+use axum_flash::{Flash, IncomingFlashes};
+use html_escape::encode_text;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use super::flash_messages_html;
+use crate::{
+    authentication::{
+        confirm_two_factor_enrollment, disable_two_factor, get_two_factor_status, get_username,
+        qr_code_svg, remaining_recovery_codes, start_two_factor_enrollment, validate_credentials,
+        AuthError, Credentials, PasswordHashing, TwoFactorStatus, UserId, RECOVERY_CODE_COUNT,
+    },
+    e500,
+    error::ResponseError,
+};
+
+/// The name authenticator apps list the codes under.
+const TOTP_ISSUER: &str = "zero2prod";
+
+#[tracing::instrument(name = "Two-factor settings", skip(flashes, pool))]
+pub async fn two_factor_settings(
+    flashes: IncomingFlashes,
+    Extension(user_id): Extension<UserId>,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let msg_html = flash_messages_html(&flashes);
+    let status = get_two_factor_status(*user_id, &pool).await.map_err(e500)?;
+    let content = match status {
+        TwoFactorStatus::Disabled => r#"<p>Two-factor authentication is off.</p>
+    <p>Once it is on, logging in also takes a code from an authenticator app.</p>
+    <form action="/admin/two-factor/setup" method="post">
+        <button type="submit">Set up two-factor authentication</button>
+    </form>"#
+            .to_string(),
+        TwoFactorStatus::Pending(secret) => {
+            let username = get_username(*user_id, &pool).await.map_err(e500)?;
+            let uri = secret.otpauth_uri(TOTP_ISSUER, &username);
+            format!(
+                r#"<p>Scan this code with your authenticator app:</p>
+    {qr_code}
+    <p>Or enter this key by hand: <code>{secret}</code></p>
+    <form action="/admin/two-factor/confirm" method="post">
+        <label>Then enter the code the app shows
+            <input type="text" name="code" autocomplete="one-time-code">
+        </label>
+        <button type="submit">Turn on two-factor authentication</button>
+    </form>"#,
+                qr_code = qr_code_svg(&uri),
+                secret = encode_text(secret.expose_secret()),
+            )
+        }
+        TwoFactorStatus::Enabled => {
+            let remaining = remaining_recovery_codes(*user_id, &pool)
+                .await
+                .map_err(e500)?;
+            format!(
+                r#"<p>Two-factor authentication is on. You have {remaining} unused recovery codes left.</p>
+    <form action="/admin/two-factor/disable" method="post">
+        <label>Current password
+            <input type="password" name="current_password">
+        </label>
+        <button type="submit">Turn off two-factor authentication</button>
+    </form>"#
+            )
+        }
+    };
+
+    let body = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Two-factor authentication</title>
+</head>
+<body>
+    {msg_html}
+    {content}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#
+    );
+    Ok((flashes, Html(body)))
+}
+
+/// Generate a secret for the user to scan. Nothing changes at login until it is confirmed.
+#[tracing::instrument(name = "Set up two-factor authentication", skip(pool))]
+pub async fn start_two_factor(
+    Extension(user_id): Extension<UserId>,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ResponseError> {
+    start_two_factor_enrollment(*user_id, &pool)
+        .await
+        .map_err(e500)?;
+    Ok(Redirect::to("/admin/two-factor"))
+}
+
+/// Turn two-factor authentication on and show the recovery codes, once.
+#[tracing::instrument(name = "Confirm two-factor authentication", skip(flash, pool, form))]
+pub async fn confirm_two_factor(
+    flash: Flash,
+    Extension(user_id): Extension<UserId>,
+    State(pool): State<PgPool>,
+    Form(form): Form<ConfirmFormData>,
+) -> Result<Response, ResponseError> {
+    let Some(recovery_codes) = confirm_two_factor_enrollment(*user_id, &form.code, &pool)
+        .await
+        .map_err(e500)?
+    else {
+        let flash = flash.error("The code is invalid. Check the clock of your device.");
+        return Ok((flash, Redirect::to("/admin/two-factor")).into_response());
+    };
+
+    let codes_html: String = recovery_codes
+        .iter()
+        .map(|code| format!("<li><code>{}</code></li>", code))
+        .collect();
+    let body = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Recovery codes</title>
+</head>
+<body>
+    <p>Two-factor authentication is on.</p>
+    <p>Keep these {RECOVERY_CODE_COUNT} recovery codes somewhere safe. Each of them logs you in
+    once if you lose your authenticator app. They won't be shown again.</p>
+    <ul>
+        {codes_html}
+    </ul>
+    <p><a href="/admin/dashboard">Back to the dashboard</a></p>
+</body>
+</html>"#
+    );
+    Ok(Html(body).into_response())
+}
+
+/// Turn two-factor authentication off. Takes the password, so an unattended session
+/// isn't enough to weaken the account.
+#[tracing::instrument(
+    name = "Disable two-factor authentication",
+    skip(flash, pool, hashing, form)
+)]
+pub async fn turn_off_two_factor(
+    flash: Flash,
+    Extension(user_id): Extension<UserId>,
+    State(pool): State<PgPool>,
+    State(hashing): State<PasswordHashing>,
+    Form(form): Form<DisableFormData>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let username = get_username(*user_id, &pool).await.map_err(e500)?;
+    let credentials = Credentials {
+        username,
+        password: form.current_password,
+    };
+    if let Err(e) = validate_credentials(credentials, &hashing, &pool).await {
+        return match e {
+            AuthError::InvalidCredentials(_) => {
+                let flash = flash.error("The current password is incorrect.");
+                Ok((flash, Redirect::to("/admin/two-factor")))
+            }
+            AuthError::UnexpectedError(_) => Err(e500(e)),
+        };
+    }
+
+    disable_two_factor(*user_id, &pool).await.map_err(e500)?;
+    let flash = flash.info("Two-factor authentication is off.");
+    Ok((flash, Redirect::to("/admin/two-factor")))
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmFormData {
+    code: String,
+}
+
+#[derive(Deserialize)]
+pub struct DisableFormData {
+    current_password: Secret<String>,
+}
@@ -0,0 +1,7 @@
+mod invite;
+mod list;
+mod manage;
+
+pub use invite::invite_user;
+pub use list::list_users;
+pub use manage::{delete_user, disable_user, enable_user};
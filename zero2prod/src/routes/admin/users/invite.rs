@@ -0,0 +1,152 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect},
+    Extension, Form,
+};
+use axum_flash::Flash;
+use html_escape::encode_text;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{
+    authentication::{Role, UserId},
+    domain::SubscriberEmail,
+    e500,
+    email_client::EmailSender,
+    error::ResponseError,
+    startup::ApplicationBaseUrl,
+};
+
+/// How long an invitation can be accepted for.
+const INVITATION_TTL_HOURS: i32 = 72;
+
+/// Invite someone to the admin section by email. They pick their own password when they
+/// accept, so no password ever travels by email.
+#[tracing::instrument(
+    name = "Invite a user",
+    skip(flash, pool, email_client, base_url, form),
+    fields(username = %form.username, role = %form.role)
+)]
+pub async fn invite_user(
+    flash: Flash,
+    Extension(user_id): Extension<UserId>,
+    State(pool): State<PgPool>,
+    State(email_client): State<Arc<dyn EmailSender>>,
+    State(base_url): State<ApplicationBaseUrl>,
+    Form(form): Form<FormData>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let users = Redirect::to("/admin/users");
+    let username = form.username.trim().to_string();
+    if username.is_empty() || username.chars().count() > 64 {
+        let flash = flash.error("A username must be between 1 and 64 characters long.");
+        return Ok((flash, users));
+    }
+    let email = match SubscriberEmail::parse(form.email.trim().to_string()) {
+        Ok(email) => email,
+        Err(e) => return Ok((flash.error(e), users)),
+    };
+    let role = match Role::try_from(form.role) {
+        Ok(role) => role,
+        Err(e) => return Ok((flash.error(e), users)),
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let taken = sqlx::query!(
+        r#"
+        SELECT
+            EXISTS (SELECT 1 FROM users WHERE username = $1) OR
+            EXISTS (SELECT 1 FROM user_invitations WHERE username = $1 AND expires_at > now())
+            AS "taken!"
+        "#,
+        username
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .context("Failed to check whether the username is taken")
+    .map_err(e500)?
+    .taken;
+    if taken {
+        let flash = flash.error(format!("The username {} is already taken.", username));
+        return Ok((flash, users));
+    }
+
+    let invitation_token = generate_invitation_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_invitations (
+            invitation_token, username, email, role, invited_by, expires_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now() + make_interval(hours => $6))
+        "#,
+        invitation_token,
+        username,
+        email.as_ref(),
+        role.as_str(),
+        *user_id,
+        INVITATION_TTL_HOURS
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store the invitation")
+    .map_err(e500)?;
+
+    let invitation_link = format!(
+        "{}/invitations/accept?invitation_token={}",
+        base_url.0, invitation_token
+    );
+    let html_body = format!(
+        "You have been invited to help run our newsletter as {username}, with the {role} role.<br />\
+        Click <a href=\"{invitation_link}\">here</a> to choose your password. \
+        The link expires in {INVITATION_TTL_HOURS} hours.",
+        username = encode_text(&username),
+    );
+    let text_body = format!(
+        "You have been invited to help run our newsletter as {username}, with the {role} role.\n\
+        Visit {invitation_link} to choose your password. \
+        The link expires in {INVITATION_TTL_HOURS} hours."
+    );
+    if let Err(e) = email_client
+        .send_email(&email, "You have been invited", &html_body, &text_body)
+        .await
+    {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to send an invitation."
+        );
+        // Dropping the transaction forgets the invitation nobody received
+        let flash = flash.error(format!("Failed to send the invitation to {}.", email));
+        return Ok((flash, users));
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the invitation")
+        .map_err(e500)?;
+
+    let flash = flash.info(format!("An invitation has been sent to {}.", email));
+    Ok((flash, users))
+}
+
+fn generate_invitation_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FormData {
+    username: String,
+    email: String,
+    role: String,
+}
@@ -0,0 +1,193 @@
+use std::fmt::Write;
+
+use anyhow::Context;
+use axum::{extract::State, response::IntoResponse, Extension};
+use axum_extra::response::Html;
+use axum_flash::IncomingFlashes;
+use chrono::{DateTime, Utc};
+use html_escape::encode_text;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authentication::{Role, UserId},
+    e500,
+    error::ResponseError,
+    routes::admin::flash_messages_html,
+};
+
+#[tracing::instrument(name = "List users", skip(flashes, pool))]
+pub async fn list_users(
+    flashes: IncomingFlashes,
+    Extension(user_id): Extension<UserId>,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let msg_html = flash_messages_html(&flashes);
+    let users = get_users(&pool)
+        .await
+        .context("Failed to fetch the users")
+        .map_err(e500)?;
+    let invitations = get_pending_invitations(&pool)
+        .await
+        .context("Failed to fetch the pending invitations")
+        .map_err(e500)?;
+
+    let mut user_rows = String::new();
+    for user in &users {
+        let id = user.user_id;
+        let actions = if id == *user_id {
+            "(you)".to_string()
+        } else {
+            let toggle = if user.disabled_at.is_some() {
+                "enable"
+            } else {
+                "disable"
+            };
+            format!(
+                r#"<form action="/admin/users/{id}/{toggle}" method="post">
+                    <button type="submit">{toggle}</button>
+                </form>
+                <form action="/admin/users/{id}/delete" method="post">
+                    <button type="submit">delete</button>
+                </form>"#
+            )
+        };
+        writeln!(
+            user_rows,
+            r#"        <tr>
+            <td>{username}</td>
+            <td>{email}</td>
+            <td>{role}</td>
+            <td>{status}</td>
+            <td>
+                {actions}
+            </td>
+        </tr>"#,
+            username = encode_text(&user.username),
+            email = encode_text(user.email.as_deref().unwrap_or("")),
+            role = encode_text(&user.role),
+            status = if user.disabled_at.is_some() {
+                "disabled"
+            } else {
+                "active"
+            },
+        )
+        .unwrap();
+    }
+
+    let invitations_html = if invitations.is_empty() {
+        "<p>There are no pending invitations.</p>".to_string()
+    } else {
+        let mut rows = String::new();
+        for invitation in &invitations {
+            writeln!(
+                rows,
+                "        <tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                encode_text(&invitation.username),
+                encode_text(&invitation.email),
+                encode_text(&invitation.role),
+                invitation.expires_at.to_rfc3339(),
+            )
+            .unwrap();
+        }
+        format!(
+            r#"<table>
+        <tr><th>Username</th><th>Email</th><th>Role</th><th>Expires</th></tr>
+{rows}    </table>"#
+        )
+    };
+
+    let mut role_options = String::new();
+    for role in Role::ALL {
+        let selected = if role == Role::Editor {
+            " selected"
+        } else {
+            ""
+        };
+        write!(
+            role_options,
+            r#"<option value="{role}"{selected}>{role}</option>"#
+        )
+        .unwrap();
+    }
+
+    let page = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Users</title>
+</head>
+<body>
+    {msg_html}
+    <h1>Users</h1>
+    <table>
+        <tr><th>Username</th><th>Email</th><th>Role</th><th>Status</th><th></th></tr>
+{user_rows}    </table>
+    <h2>Pending invitations</h2>
+    {invitations_html}
+    <h2>Invite a user</h2>
+    <form action="/admin/users/invitations" method="post">
+        <label>Username
+            <input type="text" placeholder="Enter a username" name="username">
+        </label>
+        <label>Email
+            <input type="text" placeholder="Enter their email address" name="email">
+        </label>
+        <label>Role
+            <select name="role">{role_options}</select>
+        </label>
+        <button type="submit">Send invitation</button>
+    </form>
+    <p>Viewers can read issues and delivery reports, editors can also write and publish
+        issues, and owners can also manage users.</p>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#
+    );
+    Ok((flashes, Html(page)))
+}
+
+struct User {
+    user_id: Uuid,
+    username: String,
+    email: Option<String>,
+    role: String,
+    disabled_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_users(pool: &PgPool) -> Result<Vec<User>, sqlx::Error> {
+    sqlx::query_as!(
+        User,
+        r#"
+        SELECT user_id, username, email, role, disabled_at
+        FROM users
+        ORDER BY username
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+struct Invitation {
+    username: String,
+    email: String,
+    role: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_pending_invitations(pool: &PgPool) -> Result<Vec<Invitation>, sqlx::Error> {
+    sqlx::query_as!(
+        Invitation,
+        r#"
+        SELECT username, email, role, expires_at
+        FROM user_invitations
+        WHERE expires_at > now()
+        ORDER BY created_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,154 @@
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Redirect},
+    Extension,
+};
+use axum_flash::Flash;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{authentication::UserId, e500, error::ResponseError};
+
+static NOT_YOURSELF_ERROR_MESSAGE: &str = "You can't disable or delete your own account.";
+static LAST_OWNER_ERROR_MESSAGE: &str = "The last active owner can't be disabled or deleted.";
+
+/// Lock a user out without losing track of what they did.
+#[tracing::instrument(name = "Disable a user", skip(flash, pool))]
+pub async fn disable_user(
+    flash: Flash,
+    Extension(current_user_id): Extension<UserId>,
+    Path(user_id): Path<Uuid>,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ResponseError> {
+    if user_id == *current_user_id {
+        return Ok((
+            flash.error(NOT_YOURSELF_ERROR_MESSAGE),
+            Redirect::to("/admin/users"),
+        ));
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    if is_last_active_owner(&mut transaction, user_id)
+        .await
+        .context("Failed to lock the active owners")
+        .map_err(e500)?
+    {
+        return Ok((
+            flash.error(LAST_OWNER_ERROR_MESSAGE),
+            Redirect::to("/admin/users"),
+        ));
+    }
+    sqlx::query!(
+        "UPDATE users SET disabled_at = now() WHERE user_id = $1 AND disabled_at IS NULL",
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to disable the user")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit disabling the user")
+        .map_err(e500)?;
+    Ok((
+        flash.info("The user has been disabled."),
+        Redirect::to("/admin/users"),
+    ))
+}
+
+#[tracing::instrument(name = "Enable a user", skip(flash, pool))]
+pub async fn enable_user(
+    flash: Flash,
+    Path(user_id): Path<Uuid>,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ResponseError> {
+    sqlx::query!(
+        "UPDATE users SET disabled_at = NULL WHERE user_id = $1",
+        user_id
+    )
+    .execute(&pool)
+    .await
+    .context("Failed to enable the user")
+    .map_err(e500)?;
+    Ok((
+        flash.info("The user has been enabled."),
+        Redirect::to("/admin/users"),
+    ))
+}
+
+#[tracing::instrument(name = "Delete a user", skip(flash, pool))]
+pub async fn delete_user(
+    flash: Flash,
+    Extension(current_user_id): Extension<UserId>,
+    Path(user_id): Path<Uuid>,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ResponseError> {
+    if user_id == *current_user_id {
+        return Ok((
+            flash.error(NOT_YOURSELF_ERROR_MESSAGE),
+            Redirect::to("/admin/users"),
+        ));
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    if is_last_active_owner(&mut transaction, user_id)
+        .await
+        .context("Failed to lock the active owners")
+        .map_err(e500)?
+    {
+        return Ok((
+            flash.error(LAST_OWNER_ERROR_MESSAGE),
+            Redirect::to("/admin/users"),
+        ));
+    }
+    // Saved responses are only of use to the user who made the request
+    sqlx::query!("DELETE FROM idempotency WHERE user_id = $1", user_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete the saved responses of the user")
+        .map_err(e500)?;
+    sqlx::query!("DELETE FROM users WHERE user_id = $1", user_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete the user")
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the deletion of the user")
+        .map_err(e500)?;
+    Ok((
+        flash.info("The user has been deleted."),
+        Redirect::to("/admin/users"),
+    ))
+}
+
+/// Lock the rows of the active owners and tell whether `user_id` is the only one left.
+///
+/// Owners can't disable or delete themselves, but two of them could remove each other at the
+/// same time. The lock makes the second request wait and then see the first one's change.
+#[tracing::instrument(skip(transaction))]
+async fn is_last_active_owner(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let owners = sqlx::query!(
+        r#"
+        SELECT user_id
+        FROM users
+        WHERE role = 'owner' AND disabled_at IS NULL
+        FOR UPDATE
+        "#
+    )
+    .fetch_all(transaction)
+    .await?;
+    Ok(owners.len() == 1 && owners[0].user_id == user_id)
+}
@@ -0,0 +1,250 @@
+use std::fmt::Write;
+
+use anyhow::Context;
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use http::{header, HeaderMap, HeaderValue, StatusCode};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    domain::{IssueTemplate, TemplateValues},
+    e500,
+    error::ResponseError,
+    routes::build_web_view_link,
+    startup::ApplicationBaseUrl,
+};
+
+/// How many of the latest issues the feeds carry.
+const FEED_LENGTH: i64 = 20;
+const FEED_TITLE: &str = "Our newsletter";
+
+/// The latest issues as an RSS 2.0 feed.
+#[tracing::instrument(name = "RSS feed", skip(headers, pool, base_url))]
+pub async fn rss_feed(
+    headers: HeaderMap,
+    State(pool): State<PgPool>,
+    State(base_url): State<ApplicationBaseUrl>,
+) -> Result<Response, ResponseError> {
+    let issues = get_feed_issues(&pool, &base_url.0)
+        .await
+        .context("Failed to fetch the issues for the feed")
+        .map_err(e500)?;
+
+    let mut items = String::new();
+    for issue in &issues {
+        write!(
+            items,
+            r#"
+    <item>
+      <title>{title}</title>
+      <link>{link}</link>
+      <guid isPermaLink="false">urn:uuid:{issue_id}</guid>
+      <pubDate>{published_at}</pubDate>
+      <description>{content}</description>
+    </item>"#,
+            title = encode_text(&issue.title),
+            link = encode_text(&issue.link),
+            issue_id = issue.newsletter_issue_id,
+            published_at = issue.published_at.to_rfc2822(),
+            content = encode_text(&issue.html_content),
+        )
+        .unwrap();
+    }
+    let last_build_date = issues
+        .first()
+        .map(|issue| {
+            format!(
+                "\n    <lastBuildDate>{}</lastBuildDate>",
+                issue.published_at.to_rfc2822()
+            )
+        })
+        .unwrap_or_default();
+    let feed = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+  <channel>
+    <title>{FEED_TITLE}</title>
+    <link>{archive}</link>
+    <description>Every issue of {FEED_TITLE}, as sent to subscribers.</description>
+    <atom:link href="{self_link}" rel="self" type="application/rss+xml"/>{last_build_date}{items}
+  </channel>
+</rss>
+"#,
+        archive = encode_text(&format!("{}/issues", base_url.0)),
+        self_link = encode_double_quoted_attribute(&format!("{}/feed.xml", base_url.0)),
+    );
+    Ok(feed_response(&headers, "application/rss+xml", feed))
+}
+
+/// The latest issues as an Atom feed.
+#[tracing::instrument(name = "Atom feed", skip(headers, pool, base_url))]
+pub async fn atom_feed(
+    headers: HeaderMap,
+    State(pool): State<PgPool>,
+    State(base_url): State<ApplicationBaseUrl>,
+) -> Result<Response, ResponseError> {
+    let issues = get_feed_issues(&pool, &base_url.0)
+        .await
+        .context("Failed to fetch the issues for the feed")
+        .map_err(e500)?;
+
+    let mut entries = String::new();
+    for issue in &issues {
+        write!(
+            entries,
+            r#"
+  <entry>
+    <title>{title}</title>
+    <link rel="alternate" type="text/html" href="{link}"/>
+    <id>urn:uuid:{issue_id}</id>
+    <published>{published_at}</published>
+    <updated>{published_at}</updated>
+    <content type="html">{content}</content>
+  </entry>"#,
+            title = encode_text(&issue.title),
+            link = encode_double_quoted_attribute(&issue.link),
+            issue_id = issue.newsletter_issue_id,
+            published_at = issue.published_at.to_rfc3339(),
+            content = encode_text(&issue.html_content),
+        )
+        .unwrap();
+    }
+    // A feed without entries has nothing newer than the epoch to report
+    let updated = issues
+        .first()
+        .map(|issue| issue.published_at)
+        .unwrap_or_default()
+        .to_rfc3339();
+    let feed = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>{FEED_TITLE}</title>
+  <id>{archive}</id>
+  <link rel="alternate" type="text/html" href="{archive}"/>
+  <link rel="self" type="application/atom+xml" href="{self_link}"/>
+  <author><name>{FEED_TITLE}</name></author>
+  <updated>{updated}</updated>{entries}
+</feed>
+"#,
+        archive = encode_double_quoted_attribute(&format!("{}/issues", base_url.0)),
+        self_link = encode_double_quoted_attribute(&format!("{}/atom.xml", base_url.0)),
+    );
+    Ok(feed_response(&headers, "application/atom+xml", feed))
+}
+
+/// Serve a feed with an `ETag`, or just `304 Not Modified` if the reader already has it.
+///
+/// Feed readers poll often, so most requests end here without sending the feed again.
+fn feed_response(headers: &HeaderMap, content_type: &'static str, feed: String) -> Response {
+    let etag = format!("\"{:x}\"", Sha256::digest(feed.as_bytes()));
+    let etag = HeaderValue::from_str(&etag).expect("A hex digest is a valid header value");
+    if if_none_match(headers, &etag) {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+    }
+    let content_type = HeaderValue::from_str(&format!("{}; charset=utf-8", content_type))
+        .expect("A feed content type is a valid header value");
+    (
+        [(header::CONTENT_TYPE, content_type), (header::ETAG, etag)],
+        feed,
+    )
+        .into_response()
+}
+
+/// Whether any of the tags in `If-None-Match` matches the current one.
+fn if_none_match(headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    let Some(etag) = etag.to_str().ok() else {
+        return false;
+    };
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+struct FeedIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    link: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+/// Fetch the latest published issues, rendered as they appear on their web view pages.
+#[tracing::instrument(skip(pool))]
+async fn get_feed_issues(pool: &PgPool, base_url: &str) -> Result<Vec<FeedIssue>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            newsletter_issue_id,
+            title,
+            html_content,
+            published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE status = 'published'
+        ORDER BY published_at DESC, newsletter_issue_id
+        LIMIT $1
+        "#,
+        FEED_LENGTH
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let link = build_web_view_link(base_url, row.newsletter_issue_id);
+            let values = TemplateValues {
+                web_view_url: Some(&link),
+                ..TemplateValues::default()
+            };
+            let html_content =
+                IssueTemplate::parse_or_verbatim(&row.html_content).render_html(&values);
+            FeedIssue {
+                newsletter_issue_id: row.newsletter_issue_id,
+                title: row.title,
+                link,
+                html_content,
+                published_at: row.published_at,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use http::{header, HeaderMap, HeaderValue};
+
+    use super::if_none_match;
+
+    fn headers(if_none_match: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::IF_NONE_MATCH,
+            HeaderValue::from_static(if_none_match),
+        );
+        headers
+    }
+
+    #[test]
+    fn a_matching_etag_is_found_in_a_list() {
+        let etag = HeaderValue::from_static("\"abc\"");
+        assert!(if_none_match(&headers("\"xyz\", W/\"abc\""), &etag));
+        assert!(if_none_match(&headers("*"), &etag));
+    }
+
+    #[test]
+    fn a_different_or_missing_etag_does_not_match() {
+        let etag = HeaderValue::from_static("\"abc\"");
+        assert!(!if_none_match(&headers("\"xyz\""), &etag));
+        assert!(!if_none_match(&HeaderMap::new(), &etag));
+    }
+}
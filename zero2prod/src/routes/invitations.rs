@@ -0,0 +1,207 @@
+use anyhow::Context;
+use axum::{
+    extract::{Query, State},
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_extra::response::Html;
+use axum_flash::{Flash, IncomingFlashes};
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use http::StatusCode;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authentication::{compute_password_hash, validate_new_password, PasswordHashing},
+    e500,
+    error::ResponseError,
+    routes::flash_messages_html,
+    telemetry::spawn_blocking_with_tracing,
+};
+
+/// Let an invited user choose their password.
+#[tracing::instrument(name = "Accept invitation form", skip(flashes, pool, parameters))]
+pub async fn accept_invitation_form(
+    flashes: IncomingFlashes,
+    State(pool): State<PgPool>,
+    Query(parameters): Query<Parameters>,
+) -> Result<Response, ResponseError> {
+    let Some(invitation) = get_invitation(&pool, &parameters.invitation_token)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok((flashes, invalid_invitation()).into_response());
+    };
+
+    let msg_html = flash_messages_html(&flashes);
+    let body = Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Accept invitation</title>
+</head>
+<body>
+    {msg_html}
+    <p>Welcome, {username}. Choose a password to finish setting up your account.</p>
+    <form action="/invitations/accept" method="post">
+        <input hidden type="text" name="invitation_token" value="{invitation_token}">
+        <label>Password
+            <input type="password" placeholder="Enter password" name="password">
+        </label>
+        <br>
+        <label>Confirm password
+            <input type="password" placeholder="Type the password again" name="password_check">
+        </label>
+        <br>
+        <button type="submit">Create account</button>
+    </form>
+</body>
+</html>"#,
+        username = encode_text(&invitation.username),
+        invitation_token = encode_double_quoted_attribute(&parameters.invitation_token),
+    ));
+    Ok((flashes, body).into_response())
+}
+
+/// Turn an invitation into a user who can log in.
+#[tracing::instrument(name = "Accept invitation", skip(flash, pool, hashing, form))]
+pub async fn accept_invitation(
+    flash: Flash,
+    State(pool): State<PgPool>,
+    State(hashing): State<PasswordHashing>,
+    Form(form): Form<FormData>,
+) -> Result<Response, ResponseError> {
+    // Tokens are alphanumeric, so anything else can't be a pending invitation
+    if !form
+        .invitation_token
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric())
+    {
+        return Ok(invalid_invitation().into_response());
+    }
+    let retry = Redirect::to(&format!(
+        "/invitations/accept?invitation_token={}",
+        form.invitation_token
+    ));
+    if let Err(e) = validate_new_password(&form.password) {
+        return Ok((flash.error(e), retry).into_response());
+    }
+    if form.password.expose_secret() != form.password_check.expose_secret() {
+        let flash =
+            flash.error("You entered two different passwords - the field values must match.");
+        return Ok((flash, retry).into_response());
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    // Deleting the invitation up front makes sure it can only be accepted once
+    let Some(invitation) = sqlx::query!(
+        r#"
+        DELETE FROM user_invitations
+        WHERE invitation_token = $1 AND expires_at > now()
+        RETURNING username, email, role
+        "#,
+        form.invitation_token
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to claim the invitation")
+    .map_err(e500)?
+    else {
+        return Ok(invalid_invitation().into_response());
+    };
+
+    let params = hashing.params();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(form.password, params))
+            .await
+            .context("Failed to hash password")
+            .map_err(e500)?
+            .map_err(e500)?;
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash, role, email)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        invitation.username,
+        password_hash.expose_secret(),
+        invitation.role,
+        invitation.email
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to create the invited user")
+    .map_err(e500)?;
+    if inserted.rows_affected() == 0 {
+        // The username or email address was taken after the invitation went out
+        return Ok((
+            StatusCode::CONFLICT,
+            Html(
+                "<p>An account with this username or email address already exists. \
+                Ask for a new invitation.</p>"
+                    .to_string(),
+            ),
+        )
+            .into_response());
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the new user")
+        .map_err(e500)?;
+
+    let flash = flash.info("Your account is ready. You can now log in.");
+    Ok((flash, Redirect::to("/login")).into_response())
+}
+
+fn invalid_invitation() -> impl IntoResponse {
+    (
+        StatusCode::NOT_FOUND,
+        Html(
+            "<p>This invitation is invalid, has expired or has already been accepted.</p>"
+                .to_string(),
+        ),
+    )
+}
+
+struct Invitation {
+    username: String,
+}
+
+#[tracing::instrument(skip(pool, invitation_token))]
+async fn get_invitation(
+    pool: &PgPool,
+    invitation_token: &str,
+) -> Result<Option<Invitation>, sqlx::Error> {
+    sqlx::query_as!(
+        Invitation,
+        r#"
+        SELECT username
+        FROM user_invitations
+        WHERE invitation_token = $1 AND expires_at > now()
+        "#,
+        invitation_token
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct Parameters {
+    invitation_token: String,
+}
+
+#[derive(Deserialize)]
+pub struct FormData {
+    invitation_token: String,
+    password: Secret<String>,
+    password_check: Secret<String>,
+}
@@ -0,0 +1,191 @@
+use std::fmt::Write;
+
+use anyhow::Context;
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+};
+use axum_extra::response::Html;
+use chrono::{DateTime, Utc};
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    domain::{IssueTemplate, TemplateValues},
+    e404, e500,
+    error::ResponseError,
+    startup::ApplicationBaseUrl,
+};
+
+/// How many issues a page of the archive lists.
+const ISSUES_PER_PAGE: i64 = 20;
+
+/// Build the link to the copy of an issue anyone can read in a browser.
+pub fn build_web_view_link(base_url: &str, issue_id: Uuid) -> String {
+    format!("{}/issues/{}", base_url, issue_id)
+}
+
+/// Show a published issue in the browser, for readers whose email client mangles it.
+///
+/// There is no subscriber to personalize for, so placeholders other than the link to this
+/// page render their fallback. The issue is a whole html document of its own and is shown in
+/// a sandboxed frame, so nothing in it can run on our origin. Links in it still open when
+/// clicked.
+#[tracing::instrument(name = "View a published newsletter issue", skip(pool, base_url))]
+pub async fn view_issue(
+    Path(issue_id): Path<Uuid>,
+    State(pool): State<PgPool>,
+    State(base_url): State<ApplicationBaseUrl>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let issue = sqlx::query!(
+        r#"
+        SELECT title, html_content, published_at
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1 AND status = 'published'
+        "#,
+        issue_id
+    )
+    .fetch_optional(&pool)
+    .await
+    .context("Failed to fetch the newsletter issue")
+    .map_err(e500)?
+    .ok_or_else(|| {
+        anyhow::anyhow!(
+            "There is no published newsletter issue with id {}.",
+            issue_id
+        )
+    })
+    .map_err(e404)?;
+
+    let web_view_url = build_web_view_link(&base_url.0, issue_id);
+    let values = TemplateValues {
+        web_view_url: Some(&web_view_url),
+        ..TemplateValues::default()
+    };
+    let html_content = IssueTemplate::parse_or_verbatim(&issue.html_content).render_html(&values);
+
+    let page = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    <p><a href="/issues">&lt;- All issues</a> &middot; Published {published_at}</p>
+    <iframe sandbox="allow-popups allow-popups-to-escape-sandbox allow-top-navigation-by-user-activation" srcdoc="{html_content}" title="{frame_title}" width="100%" height="800"></iframe>
+</body>
+</html>"#,
+        title = encode_text(&issue.title),
+        frame_title = encode_double_quoted_attribute(&issue.title),
+        html_content = encode_double_quoted_attribute(&html_content),
+        published_at = issue
+            .published_at
+            .map(|published_at| published_at.format("%B %-d, %Y").to_string())
+            .unwrap_or_default(),
+    );
+    Ok(Html(page))
+}
+
+/// List every published issue, newest first, so new readers can see what they sign up for.
+#[tracing::instrument(name = "Newsletter archive", skip(pool))]
+pub async fn list_published_issues(
+    Query(parameters): Query<ArchiveParameters>,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let page = parameters.page.unwrap_or(1).max(1);
+    let mut issues = get_published_issues(&pool, page)
+        .await
+        .context("Failed to fetch the published newsletter issues")
+        .map_err(e500)?;
+    // One issue past the page tells whether there are older ones
+    let has_older = issues.len() as i64 > ISSUES_PER_PAGE;
+    issues.truncate(ISSUES_PER_PAGE as usize);
+
+    let body = if issues.is_empty() {
+        "<p>No issues have been published yet.</p>".to_string()
+    } else {
+        let mut items = String::new();
+        for issue in &issues {
+            writeln!(
+                items,
+                r#"        <li><a href="/issues/{issue_id}">{title}</a> - {published_at}</li>"#,
+                issue_id = issue.newsletter_issue_id,
+                title = encode_text(&issue.title),
+                published_at = issue.published_at.format("%B %-d, %Y"),
+            )
+            .unwrap();
+        }
+        format!("<ul>\n{items}    </ul>")
+    };
+    let mut pagination = Vec::new();
+    if page > 1 {
+        pagination.push(format!(
+            r#"<a href="/issues?page={}">&lt;- Newer issues</a>"#,
+            page - 1
+        ));
+    }
+    if has_older {
+        pagination.push(format!(
+            r#"<a href="/issues?page={}">Older issues -&gt;</a>"#,
+            page + 1
+        ));
+    }
+
+    let page = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Newsletter archive</title>
+    <link rel="alternate" type="application/rss+xml" title="RSS" href="/feed.xml">
+    <link rel="alternate" type="application/atom+xml" title="Atom" href="/atom.xml">
+</head>
+<body>
+    <h1>Newsletter archive</h1>
+    {body}
+    <p>{pagination}</p>
+    <p><a href="/">&lt;- Home</a></p>
+</body>
+</html>"#,
+        pagination = pagination.join(" &middot; "),
+    );
+    Ok(Html(page))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArchiveParameters {
+    page: Option<i64>,
+}
+
+struct PublishedIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_published_issues(
+    pool: &PgPool,
+    page: i64,
+) -> Result<Vec<PublishedIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        PublishedIssue,
+        r#"
+        SELECT
+            newsletter_issue_id,
+            title,
+            published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE status = 'published'
+        ORDER BY published_at DESC, newsletter_issue_id
+        LIMIT $1 OFFSET $2
+        "#,
+        ISSUES_PER_PAGE + 1,
+        (page - 1).saturating_mul(ISSUES_PER_PAGE)
+    )
+    .fetch_all(pool)
+    .await
+}
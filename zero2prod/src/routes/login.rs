@@ -1,5 +1,11 @@
+mod forgot_password;
 mod get;
 mod post;
+mod reset_password;
+mod two_factor;
 
+pub use forgot_password::{forgot_password_form, request_password_reset};
 pub use get::login_form;
 pub use post::login;
+pub use reset_password::{reset_password, reset_password_form};
+pub use two_factor::{two_factor_form, verify_two_factor};
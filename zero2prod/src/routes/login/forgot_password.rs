@@ -0,0 +1,182 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect},
+    Form,
+};
+use axum_extra::response::Html;
+use axum_flash::{Flash, IncomingFlashes};
+use http::StatusCode;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use tracing::Instrument;
+
+use crate::{
+    authentication::{ClientIp, LoginThrottle},
+    domain::SubscriberEmail,
+    e500,
+    email_client::EmailSender,
+    error::ResponseError,
+    routes::flash_messages_html,
+    startup::ApplicationBaseUrl,
+};
+
+/// How long a reset link works for.
+const RESET_TOKEN_TTL_MINUTES: i32 = 60;
+
+#[tracing::instrument(name = "Forgot password form", skip(flashes))]
+pub async fn forgot_password_form(flashes: IncomingFlashes) -> impl IntoResponse {
+    let msg_html = flash_messages_html(&flashes);
+
+    let body_response = Html((
+        StatusCode::OK,
+        format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Forgot password</title>
+</head>
+<body>
+    {msg_html}
+    <p>Enter your username and we'll email you a link to choose a new password.</p>
+    <form action="/login/forgot-password" method="post">
+        <label>Username
+            <input type="text" placeholder="Enter Username" name="username">
+        </label>
+        <button type="submit">Send reset link</button>
+    </form>
+    <p><a href="/login">&lt;- Back to login</a></p>
+</body>
+</html>
+"#
+        ),
+    ));
+    (flashes, body_response)
+}
+
+/// Email a reset link to the user, if there is one with an email address.
+///
+/// The answer is the same whether the username exists or not, and the email goes out in
+/// the background so the response time doesn't give it away either. Requests are counted
+/// per username and per address, so the form can't be used to flood someone's inbox.
+#[tracing::instrument(
+    name = "Request password reset",
+    skip(flash, pool, email_client, base_url, throttle, form)
+)]
+pub async fn request_password_reset(
+    flash: Flash,
+    client_ip: ClientIp,
+    State(throttle): State<LoginThrottle>,
+    State(pool): State<PgPool>,
+    State(email_client): State<Arc<dyn EmailSender>>,
+    State(base_url): State<ApplicationBaseUrl>,
+    Form(form): Form<FormData>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let flash = flash.info(
+        "If that account exists and has an email address, a link to reset its password \
+        has been sent to it.",
+    );
+    let response = (flash, Redirect::to("/login"));
+    if !throttle
+        .allow_password_reset(&form.username, &client_ip)
+        .await
+    {
+        return Ok(response);
+    }
+
+    let Some(user) = sqlx::query!(
+        r#"
+        SELECT user_id, email
+        FROM users
+        WHERE username = $1 AND disabled_at IS NULL
+        "#,
+        form.username
+    )
+    .fetch_optional(&pool)
+    .await
+    .context("Failed to look up the user")
+    .map_err(e500)?
+    else {
+        tracing::info!("Password reset requested for an unknown username.");
+        return Ok(response);
+    };
+    let Some(email) = user
+        .email
+        .and_then(|email| SubscriberEmail::parse(email).ok())
+    else {
+        tracing::warn!(user_id = %user.user_id, "Password reset requested for a user without an email address.");
+        return Ok(response);
+    };
+
+    let reset_token = generate_reset_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO password_reset_tokens (token_hash, user_id, expires_at)
+        VALUES ($1, $2, now() + make_interval(mins => $3))
+        "#,
+        hash_reset_token(&reset_token),
+        user.user_id,
+        RESET_TOKEN_TTL_MINUTES
+    )
+    .execute(&pool)
+    .await
+    .context("Failed to store the reset token")
+    .map_err(e500)?;
+
+    let reset_link = format!(
+        "{}/login/reset-password?reset_token={}",
+        base_url.0, reset_token
+    );
+    let html_body = format!(
+        "Someone asked to reset the password of your account.<br />\
+        Click <a href=\"{reset_link}\">here</a> to choose a new one. \
+        The link works once, for {RESET_TOKEN_TTL_MINUTES} minutes.<br />\
+        If it wasn't you, you can ignore this email."
+    );
+    let text_body = format!(
+        "Someone asked to reset the password of your account.\n\
+        Visit {reset_link} to choose a new one. \
+        The link works once, for {RESET_TOKEN_TTL_MINUTES} minutes.\n\
+        If it wasn't you, you can ignore this email."
+    );
+    tokio::spawn(
+        async move {
+            if let Err(e) = email_client
+                .send_email(&email, "Reset your password", &html_body, &text_body)
+                .await
+            {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to send a password reset email."
+                );
+            }
+        }
+        .in_current_span(),
+    );
+
+    Ok(response)
+}
+
+fn generate_reset_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
+
+/// Reset tokens are stored hashed. They are long and random, so a fast hash is enough.
+pub(super) fn hash_reset_token(reset_token: &str) -> String {
+    format!("{:x}", Sha256::digest(reset_token.as_bytes()))
+}
+
+#[derive(Deserialize)]
+pub struct FormData {
+    username: String,
+}
@@ -3,22 +3,14 @@ use axum_extra::response::Html;
 use axum_flash::IncomingFlashes;
 use axum_macros::debug_handler;
 use http::StatusCode;
-use std::fmt::Write;
+
+use crate::routes::flash_messages_html;
 
 #[allow(clippy::let_with_type_underscore)]
 #[debug_handler(state = axum_flash::Config)]
 #[tracing::instrument(name = "Login form", skip(flashes))]
 pub async fn login_form(flashes: IncomingFlashes) -> impl IntoResponse {
-    let mut error_html = String::new();
-
-    for (level, text) in flashes.iter() {
-        writeln!(
-            error_html,
-            "<p><strong>{:?}</strong>: <i>{}</i></p>\n",
-            level, text
-        )
-        .unwrap();
-    }
+    let error_html = flash_messages_html(&flashes);
 
     let body_response = Html((
         StatusCode::OK,
@@ -43,6 +35,7 @@ pub async fn login_form(flashes: IncomingFlashes) -> impl IntoResponse {
                     </label>
                     <button type="submit">Login</button>
                 </form>
+                <p><a href="/login/forgot-password">Forgot your password?</a></p>
             </body>
             
             </html>
@@ -13,7 +13,10 @@ use serde::Deserialize;
 use sqlx::PgPool;
 
 use crate::{
-    authentication::{validate_credentials, AuthError, Credentials},
+    authentication::{
+        get_session_generation, get_two_factor_status, validate_credentials, AuthError, ClientIp,
+        Credentials, LoginThrottle, PasswordHashing, TwoFactorStatus,
+    },
     error_chain_fmt,
     session_state::TypedSession,
 };
@@ -21,34 +24,56 @@ use crate::{
 #[debug_handler(state = crate::startup::AppState)]
 #[tracing::instrument(
     name = "Login posted"
-    skip(form, flash, session, pool),
+    skip(form, flash, session, pool, throttle, hashing),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 pub async fn login(
     State(pool): State<PgPool>,
+    State(throttle): State<LoginThrottle>,
+    State(hashing): State<PasswordHashing>,
+    client_ip: ClientIp,
     flash: Flash,
     session: TypedSession<SessionRedisPool>,
     Form(form): Form<FormData>,
 ) -> Result<impl IntoResponse, LoginError> {
+    let username = form.username;
+    tracing::Span::current().record("username", tracing::field::display(&username));
+
+    // Refuse guesses without spending a password hash on them
+    if let Err(lockout) = throttle.count_attempt(&username, &client_ip).await {
+        let flash = flash.error(lockout.to_string());
+        return Ok((flash, Redirect::to("/login")).into_response());
+    }
+
     let credentials = Credentials {
-        username: form.username,
+        username: username.clone(),
         password: form.password,
     };
-
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
-
-    let response = match validate_credentials(credentials, &pool).await {
+    let response = match validate_credentials(credentials, &hashing, &pool).await {
         Ok(user_id) => {
-            tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+            tracing::Span::current().record("user_id", tracing::field::display(&user_id));
             // In actix_web, it would be necessary to handle serialization failure here. Somehow axum gets around that.
             session.renew();
+            throttle.forgive_attempt(&username, &client_ip).await;
+            // A password alone doesn't log in a user who turned on two-factor authentication
+            if let TwoFactorStatus::Enabled = get_two_factor_status(user_id, &pool).await? {
+                session.insert_pending_user_id(user_id);
+                return Ok(Redirect::to("/login/two-factor").into_response());
+            }
+            throttle.record_success(&username).await;
+            let session_generation = get_session_generation(user_id, &pool).await?;
             session.insert_user_id(user_id);
+            session.insert_session_generation(session_generation);
             Redirect::to("/admin/dashboard").into_response()
         }
         Err(e) => {
             let e = match e {
+                // The attempt was counted already
                 AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
-                AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
+                AuthError::UnexpectedError(_) => {
+                    throttle.forgive_attempt(&username, &client_ip).await;
+                    LoginError::UnexpectedError(e.into())
+                }
             };
             tracing::error!("{:?}", &e);
 
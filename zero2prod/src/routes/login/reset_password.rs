@@ -0,0 +1,194 @@
+use super::forgot_password::hash_reset_token;
+use crate::{
+    authentication::{
+        compute_password_hash, validate_new_password, LoginThrottle, PasswordHashing,
+    },
+    e500,
+    error::ResponseError,
+    routes::flash_messages_html,
+    telemetry::spawn_blocking_with_tracing,
+};
+use anyhow::Context;
+use axum::{
+    extract::{Query, State},
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_extra::response::Html;
+use axum_flash::{Flash, IncomingFlashes};
+use html_escape::encode_double_quoted_attribute;
+use http::StatusCode;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+#[tracing::instrument(name = "Reset password form", skip(flashes, pool, parameters))]
+pub async fn reset_password_form(
+    flashes: IncomingFlashes,
+    State(pool): State<PgPool>,
+    Query(parameters): Query<Parameters>,
+) -> Result<Response, ResponseError> {
+    let valid = sqlx::query!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM password_reset_tokens
+            WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now()
+        ) AS "valid!"
+        "#,
+        hash_reset_token(&parameters.reset_token)
+    )
+    .fetch_one(&pool)
+    .await
+    .context("Failed to look up the reset token")
+    .map_err(e500)?
+    .valid;
+    if !valid {
+        return Ok((flashes, invalid_reset_token()).into_response());
+    }
+
+    let msg_html = flash_messages_html(&flashes);
+    let body = Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Reset password</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/login/reset-password" method="post">
+        <input hidden type="text" name="reset_token" value="{reset_token}">
+        <label>New password
+            <input type="password" placeholder="Enter new password" name="new_password">
+        </label>
+        <br>
+        <label>Confirm new password
+            <input type="password" placeholder="Type the new password again" name="new_password_check">
+        </label>
+        <br>
+        <button type="submit">Reset password</button>
+    </form>
+</body>
+</html>"#,
+        reset_token = encode_double_quoted_attribute(&parameters.reset_token),
+    ));
+    Ok((flashes, body).into_response())
+}
+
+/// Set a new password and log the user out everywhere, in case someone else was in.
+///
+/// Having the reset link proves who they are, so a lock on their username is lifted too.
+#[tracing::instrument(name = "Reset password", skip(flash, pool, hashing, throttle, form))]
+pub async fn reset_password(
+    flash: Flash,
+    State(pool): State<PgPool>,
+    State(hashing): State<PasswordHashing>,
+    State(throttle): State<LoginThrottle>,
+    Form(form): Form<FormData>,
+) -> Result<Response, ResponseError> {
+    // Tokens are alphanumeric, so anything else can't be a valid one
+    if !form.reset_token.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Ok(invalid_reset_token().into_response());
+    }
+    let retry = Redirect::to(&format!(
+        "/login/reset-password?reset_token={}",
+        form.reset_token
+    ));
+    if let Err(e) = validate_new_password(&form.new_password) {
+        return Ok((flash.error(e), retry).into_response());
+    }
+    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
+        let flash =
+            flash.error("You entered two different new passwords - the field values must match.");
+        return Ok((flash, retry).into_response());
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let Some(token) = sqlx::query!(
+        r#"
+        UPDATE password_reset_tokens
+        SET used_at = now()
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now()
+        RETURNING user_id
+        "#,
+        hash_reset_token(&form.reset_token)
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to claim the reset token")
+    .map_err(e500)?
+    else {
+        return Ok(invalid_reset_token().into_response());
+    };
+
+    let params = hashing.params();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(form.new_password, params))
+            .await
+            .context("Failed to hash password")
+            .map_err(e500)?
+            .map_err(e500)?;
+    let user = sqlx::query!(
+        r#"
+        UPDATE users
+        SET password_hash = $1, session_generation = session_generation + 1
+        WHERE user_id = $2
+        RETURNING username
+        "#,
+        password_hash.expose_secret(),
+        token.user_id
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .context("Failed to change the password of the user")
+    .map_err(e500)?;
+    // Any other link that went out is no longer needed
+    sqlx::query!(
+        r#"
+        UPDATE password_reset_tokens
+        SET used_at = now()
+        WHERE user_id = $1 AND used_at IS NULL
+        "#,
+        token.user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to invalidate the other reset tokens")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the password reset")
+        .map_err(e500)?;
+    throttle.record_success(&user.username).await;
+
+    let flash = flash.info("Your password has been reset. You can now log in.");
+    Ok((flash, Redirect::to("/login")).into_response())
+}
+
+fn invalid_reset_token() -> impl IntoResponse {
+    (
+        StatusCode::NOT_FOUND,
+        Html(
+            "<p>This link is invalid, has expired or has already been used. \
+            <a href=\"/login/forgot-password\">Ask for a new one</a>.</p>"
+                .to_string(),
+        ),
+    )
+}
+
+#[derive(Deserialize)]
+pub struct Parameters {
+    reset_token: String,
+}
+
+#[derive(Deserialize)]
+pub struct FormData {
+    reset_token: String,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
@@ -0,0 +1,102 @@
+use super::post::LoginError;
+use crate::{
+    authentication::{
+        get_session_generation, get_username, verify_second_factor, ClientIp, LoginThrottle,
+    },
+    routes::flash_messages_html,
+    session_state::TypedSession,
+};
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect},
+    Form,
+};
+use axum_extra::response::Html;
+use axum_flash::{Flash, IncomingFlashes};
+use axum_session::SessionRedisPool;
+use http::StatusCode;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+/// The second step of logging in, for users with two-factor authentication.
+#[tracing::instrument(name = "Two-factor form", skip(flashes, session))]
+pub async fn two_factor_form(
+    flashes: IncomingFlashes,
+    session: TypedSession<SessionRedisPool>,
+) -> impl IntoResponse {
+    if session.get_pending_user_id().is_none() {
+        return (flashes, Redirect::to("/login")).into_response();
+    }
+
+    let error_html = flash_messages_html(&flashes);
+
+    let body_response = Html((
+        StatusCode::OK,
+        format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Two-factor authentication</title>
+</head>
+<body>
+    {error_html}
+    <form action="/login/two-factor" method="post">
+        <label>Enter the code from your authenticator app, or one of your recovery codes
+            <input type="text" name="code" autocomplete="one-time-code" autofocus>
+        </label>
+        <button type="submit">Verify</button>
+    </form>
+</body>
+</html>
+"#
+        ),
+    ));
+    (flashes, body_response).into_response()
+}
+
+#[tracing::instrument(
+    name = "Two-factor code posted",
+    skip(flash, session, pool, throttle, form),
+    fields(user_id = tracing::field::Empty)
+)]
+pub async fn verify_two_factor(
+    State(pool): State<PgPool>,
+    State(throttle): State<LoginThrottle>,
+    client_ip: ClientIp,
+    flash: Flash,
+    session: TypedSession<SessionRedisPool>,
+    Form(form): Form<FormData>,
+) -> Result<impl IntoResponse, LoginError> {
+    let Some(user_id) = session.get_pending_user_id() else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    // Six digits don't take long to guess, so wrong codes count as failed logins too
+    let username = get_username(user_id, &pool).await?;
+    if let Err(lockout) = throttle.count_attempt(&username, &client_ip).await {
+        session.remove_pending_user_id();
+        let flash = flash.error(lockout.to_string());
+        return Ok((flash, Redirect::to("/login")).into_response());
+    }
+    if !verify_second_factor(user_id, &form.code, &pool).await? {
+        tracing::warn!("Invalid second factor.");
+        let flash = flash.error("The code is invalid or was already used.");
+        return Ok((flash, Redirect::to("/login/two-factor")).into_response());
+    }
+    throttle.forgive_attempt(&username, &client_ip).await;
+    throttle.record_success(&username).await;
+
+    session.renew();
+    session.remove_pending_user_id();
+    let session_generation = get_session_generation(user_id, &pool).await?;
+    session.insert_user_id(user_id);
+    session.insert_session_generation(session_generation);
+    Ok(Redirect::to("/admin/dashboard").into_response())
+}
+
+#[derive(Deserialize)]
+pub struct FormData {
+    code: String,
+}
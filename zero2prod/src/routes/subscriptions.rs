@@ -15,8 +15,8 @@ use serde::Deserialize;
 use sqlx::{types::Uuid, PgPool, Postgres, Transaction};
 
 use crate::{
-    domain::NewSubscriber,
-    email_client::EmailClient,
+    domain::{NewSubscriber, SubscriberEmail},
+    email_client::{EmailError, EmailSender},
     startup::{AppState, ApplicationBaseUrl},
 };
 
@@ -31,7 +31,7 @@ use crate::{
 #[cfg_attr(any(test, debug_assertions), debug_handler(state = AppState ))]
 pub async fn subscribe(
     State(db): State<PgPool>,
-    State(email_client): State<Arc<EmailClient>>,
+    State(email_client): State<Arc<dyn EmailSender>>,
     State(base_url): State<ApplicationBaseUrl>,
     WithRejection(Form(form), _): WithRejection<Form<FormData>, SubscribeError>,
 ) -> Result<impl IntoResponse, SubscribeError> {
@@ -46,12 +46,41 @@ pub async fn subscribe(
         .await
         .context("Failed to acquire a Postgres connection from the pool.")?;
 
-    let new_subscriber = form.try_into().map_err(SubscribeError::ValidationError)?;
+    let new_subscriber: NewSubscriber = form.try_into().map_err(SubscribeError::ValidationError)?;
 
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+    // Insert first so that two concurrent signups for the same address can't both miss the
+    // lookup and then race on the unique constraint
+    let inserted_subscriber = insert_subscriber(&mut transaction, &new_subscriber)
         .await
         .context("Failed to insert a new subscriber in the database.")?;
 
+    let subscriber_id = match inserted_subscriber {
+        Some(subscriber_id) => subscriber_id,
+        None => {
+            let (subscriber_id, status) =
+                get_subscriber_by_email(&mut transaction, &new_subscriber.email)
+                    .await
+                    .context("Failed to look up an existing subscriber in the database.")?
+                    .context("The existing subscriber was removed during signup.")?;
+            match status.as_str() {
+                // People lose the first email, so pending and former subscribers get a fresh link
+                "pending_confirmation" | "unsubscribed" => {
+                    mark_subscriber_pending(&mut transaction, subscriber_id)
+                        .await
+                        .context(
+                            "Failed to reset an existing subscriber to pending confirmation.",
+                        )?;
+                    subscriber_id
+                }
+                // Respond exactly as for a new subscriber to avoid revealing who is on the list
+                _ => {
+                    tracing::info!("The subscriber is already on the mailing list.");
+                    return Ok(StatusCode::OK);
+                }
+            }
+        }
+    };
+
     let subscription_token = generate_subscription_token();
 
     store_token(&mut transaction, subscriber_id, &subscription_token)
@@ -99,11 +128,11 @@ pub async fn store_token(
     skip(email_client, new_subscriber, base_url)
 )]
 async fn send_confirmation_email(
-    email_client: Arc<EmailClient>,
+    email_client: Arc<dyn EmailSender>,
     new_subscriber: NewSubscriber,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), EmailError> {
     // Build a confirmation link with a dynamic root
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
@@ -126,31 +155,77 @@ async fn send_confirmation_email(
             &html_body,
             &plain_body,
         )
-        .await
+        .await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "[Looking up an existing subscriber by email]",
+    skip(transaction, email)
+)]
+async fn get_subscriber_by_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<Option<(Uuid, String)>, sqlx::Error> {
+    let subscriber = sqlx::query!(
+        r#"
+    SELECT id, status
+    FROM subscriptions
+    WHERE email = $1
+    FOR UPDATE
+    "#,
+        email.as_ref()
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(subscriber.map(|s| (s.id, s.status)))
+}
+
+#[tracing::instrument(
+    name = "[Marking subscriber as pending confirmation]",
+    skip(transaction)
+)]
+async fn mark_subscriber_pending(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+    UPDATE subscriptions
+    SET status = 'pending_confirmation'
+    WHERE id = $1
+    "#,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
 }
 
 #[tracing::instrument(
     name = "[Saving new subscriber details in the database]",
     skip(transaction, new_subscriber)
 )]
+/// Returns `None` when a subscriber with the same email already exists.
 async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
-) -> Result<Uuid, sqlx::Error> {
-    let subscriber_id = Uuid::new_v4();
-    sqlx::query!(
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let subscriber = sqlx::query!(
         r#"
     INSERT INTO subscriptions (id, email, name, subscribed_at, status)
     VALUES ($1, $2, $3, $4, 'pending_confirmation')
+    ON CONFLICT (email) DO NOTHING
+    RETURNING id
     "#,
-        subscriber_id,
+        Uuid::new_v4(),
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
         Utc::now()
     )
-    .execute(transaction)
+    .fetch_optional(transaction)
     .await?;
-    Ok(subscriber_id)
+    Ok(subscriber.map(|s| s.id))
 }
 
 fn generate_subscription_token() -> String {
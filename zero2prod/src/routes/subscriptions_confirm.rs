@@ -3,58 +3,104 @@ use axum::{
     extract::{Query, State},
     response::IntoResponse,
 };
+use chrono::{DateTime, Utc};
 use http::StatusCode;
 use serde::Deserialize;
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
-#[tracing::instrument(name = "Confirm a pending subscription", skip(db_pool, parameters))]
+use crate::startup::SubscriptionTokenTtl;
+
+#[tracing::instrument(
+    name = "Confirm a pending subscription",
+    skip(db_pool, token_ttl, parameters)
+)]
 pub async fn confirm(
     State(db_pool): State<PgPool>,
+    State(token_ttl): State<SubscriptionTokenTtl>,
     parameters: Query<ConfirmParameters>,
 ) -> Result<impl IntoResponse, ConfirmError> {
-    let id = get_subscriber_id_from_token(&db_pool, &parameters.subscription_token)
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+
+    // Tokens are single use, so the token is removed whether or not it is still valid
+    let token = consume_subscription_token(&mut transaction, &parameters.subscription_token)
         .await
         .context("Failed to get subscriber id from token.")?;
 
-    match id {
-        None => Ok(StatusCode::UNAUTHORIZED),
-        Some(subscriber_id) => {
-            confirm_subscriber(&db_pool, subscriber_id)
+    let subscriber_id = match token {
+        Some((subscriber_id, created_at)) if created_at + token_ttl.0 > Utc::now() => subscriber_id,
+        Some(_) => {
+            tracing::warn!("The subscription token has expired.");
+            transaction
+                .commit()
                 .await
-                .context("Failed to set subscriber to 'confirmed' status.")?;
-            Ok(StatusCode::OK)
+                .context("Failed to commit SQL transaction to remove an expired token.")?;
+            return Ok(StatusCode::UNAUTHORIZED);
         }
-    }
+        None => return Ok(StatusCode::UNAUTHORIZED),
+    };
+
+    confirm_subscriber(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to set subscriber to 'confirmed' status.")?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber.")?;
+
+    Ok(StatusCode::OK)
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, db_pool))]
-pub async fn confirm_subscriber(db_pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed",
+    skip(subscriber_id, transaction)
+)]
+pub async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'confirmed'
+        WHERE id = $1 AND status = 'pending_confirmation'
+        "#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    // Any other confirmation links sent to this subscriber are now pointless
     sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
         subscriber_id
     )
-    .execute(db_pool)
+    .execute(&mut *transaction)
     .await?;
     Ok(())
 }
 
+/// Remove a subscription token, returning the subscriber it belonged to and when it was issued.
 #[tracing::instrument(
-    name = "Get subscriber_id from token",
-    skip(subscription_token, db_pool)
+    name = "Consume subscription token",
+    skip(subscription_token, transaction)
 )]
-pub async fn get_subscriber_id_from_token(
-    db_pool: &PgPool,
+pub async fn consume_subscription_token(
+    transaction: &mut Transaction<'_, Postgres>,
     subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
+) -> Result<Option<(Uuid, DateTime<Utc>)>, sqlx::Error> {
     let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens
-           WHERE subscription_token = $1"#,
+        r#"DELETE FROM subscription_tokens
+           WHERE subscription_token = $1
+           RETURNING subscriber_id, created_at"#,
         subscription_token
     )
-    .fetch_optional(db_pool)
+    .fetch_optional(&mut *transaction)
     .await?;
-    Ok(result.map(|r| r.subscriber_id))
+    Ok(result.map(|r| (r.subscriber_id, r.created_at)))
 }
 
 #[derive(Debug, Deserialize)]
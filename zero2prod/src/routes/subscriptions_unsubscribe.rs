@@ -0,0 +1,175 @@
+use anyhow::Context;
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+};
+use axum_extra::response::Html;
+use http::StatusCode;
+use secrecy::Secret;
+use serde::Deserialize;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{domain::UnsubscribeToken, startup::HmacSecret};
+
+/// Build the link a subscriber can follow to leave the mailing list.
+///
+/// The same link accepts an RFC 8058 one-click `POST`, so it is also used for the
+/// `List-Unsubscribe` header of newsletter emails.
+pub fn build_unsubscribe_link(
+    base_url: &str,
+    subscriber_id: Uuid,
+    hmac_secret: &Secret<String>,
+) -> String {
+    let token = UnsubscribeToken::generate(subscriber_id, hmac_secret);
+    format!(
+        "{}/subscriptions/unsubscribe?subscriber_id={}&token={}",
+        base_url,
+        subscriber_id,
+        token.as_ref()
+    )
+}
+
+#[tracing::instrument(name = "Unsubscribe form", skip(hmac_secret, parameters))]
+pub async fn unsubscribe_form(
+    State(hmac_secret): State<HmacSecret>,
+    Query(parameters): Query<UnsubscribeParameters>,
+) -> impl IntoResponse {
+    let UnsubscribeParameters {
+        subscriber_id,
+        token,
+    } = parameters;
+
+    if let Err(e) = UnsubscribeToken::from(token.clone()).verify(subscriber_id, &hmac_secret.0) {
+        tracing::warn!("{}", e);
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let body = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribe</title>
+</head>
+<body>
+    <p>Do you want to stop receiving our newsletter?</p>
+    <form action="/subscriptions/unsubscribe?subscriber_id={subscriber_id}&amp;token={token}" method="post">
+        <button type="submit">Unsubscribe</button>
+    </form>
+</body>
+</html>"#
+    );
+    Html((StatusCode::OK, body)).into_response()
+}
+
+/// Unsubscribe the reader identified by the signed link's query string.
+///
+/// This serves both the confirmation form and RFC 8058 one-click requests, which mailbox
+/// providers send as a `POST` of `List-Unsubscribe=One-Click` to the `List-Unsubscribe` URL.
+/// The request body carries nothing we need, so it is ignored.
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip(db_pool, hmac_secret, parameters)
+)]
+pub async fn unsubscribe(
+    State(db_pool): State<PgPool>,
+    State(hmac_secret): State<HmacSecret>,
+    Query(parameters): Query<UnsubscribeParameters>,
+) -> Result<impl IntoResponse, UnsubscribeError> {
+    let UnsubscribeParameters {
+        subscriber_id,
+        token,
+    } = parameters;
+
+    if let Err(e) = UnsubscribeToken::from(token).verify(subscriber_id, &hmac_secret.0) {
+        tracing::warn!("{}", e);
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+
+    unsubscribe_subscriber(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to set subscriber to 'unsubscribed' status.")?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe a subscriber.")?;
+
+    let body = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribed</title>
+</head>
+<body>
+    <p>You have been unsubscribed. You will not receive any further issues.</p>
+</body>
+</html>"#;
+    Ok(Html((StatusCode::OK, body)).into_response())
+}
+
+/// Mark a subscriber as unsubscribed and drop any deliveries still queued for them.
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(transaction))]
+pub async fn unsubscribe_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let subscriber = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'unsubscribed'
+        WHERE id = $1
+        RETURNING email
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+
+    if let Some(subscriber) = subscriber {
+        sqlx::query!(
+            r#"
+            DELETE FROM issue_delivery_queue
+            WHERE subscriber_email = $1
+            "#,
+            subscriber.email
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UnsubscribeParameters {
+    subscriber_id: Uuid,
+    token: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        crate::error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for UnsubscribeError {
+    fn into_response(self) -> axum::response::Response {
+        tracing::error!("{:?}", self);
+        match self {
+            UnsubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+        .into_response()
+    }
+}
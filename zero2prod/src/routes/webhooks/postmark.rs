@@ -0,0 +1,363 @@
+use anyhow::Context;
+use axum::{
+    extract::{rejection::JsonRejection, State},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use http::{header, HeaderMap, HeaderValue, StatusCode};
+use secrecy::ExposeSecret;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+use crate::{authentication::basic_authentication, startup::PostmarkWebhookCredentials};
+
+/// Record an event posted by one of Postmark's webhooks.
+///
+/// Hard bounces and spam complaints suppress the recipient, so no further issue is queued
+/// for them. Postmark retries anything but a 200, so event types we don't handle are
+/// acknowledged and dropped.
+#[tracing::instrument(
+    name = "Receive a Postmark webhook",
+    skip(headers, pool, credentials, body)
+)]
+pub async fn postmark_webhook(
+    headers: HeaderMap,
+    State(pool): State<PgPool>,
+    State(credentials): State<PostmarkWebhookCredentials>,
+    body: Result<Json<PostmarkEvent>, JsonRejection>,
+) -> Result<impl IntoResponse, WebhookError> {
+    let presented = basic_authentication(&headers).map_err(WebhookError::AuthError)?;
+    let password = credentials.password.as_ref().ok_or_else(|| {
+        WebhookError::AuthError(anyhow::anyhow!(
+            "The webhook password has not been configured."
+        ))
+    })?;
+    // Evaluate both comparisons so the response time gives nothing away
+    let username_matches = digests_match(&presented.username, &credentials.username);
+    let password_matches =
+        digests_match(presented.password.expose_secret(), password.expose_secret());
+    if !(username_matches & password_matches) {
+        return Err(WebhookError::AuthError(anyhow::anyhow!(
+            "Invalid webhook credentials."
+        )));
+    }
+    let Json(event) = body.map_err(|e| WebhookError::ValidationError(e.into()))?;
+
+    let event = match event.into_email_event() {
+        Some(event) => event,
+        None => {
+            tracing::info!("Ignoring a Postmark event we don't handle.");
+            return Ok(StatusCode::OK);
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let subscriber_id = store_email_event(&mut transaction, &event)
+        .await
+        .context("Failed to store the email event.")?;
+    update_delivery_log(&mut transaction, &event)
+        .await
+        .context("Failed to update the delivery log.")?;
+    if event.suppresses_recipient {
+        if let Some(subscriber_id) = subscriber_id {
+            suppress_subscriber(&mut transaction, subscriber_id)
+                .await
+                .context("Failed to suppress a subscriber.")?;
+        }
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store an email event.")?;
+
+    Ok(StatusCode::OK)
+}
+
+/// Compare two secrets in constant time, hashing first so their lengths don't leak either.
+fn digests_match(presented: &str, expected: &str) -> bool {
+    let presented = Sha256::digest(presented.as_bytes());
+    let expected = Sha256::digest(expected.as_bytes());
+    presented.as_slice().ct_eq(expected.as_slice()).into()
+}
+
+/// Store the event against the subscriber it is about, if there is one, and return their id.
+#[tracing::instrument(skip_all, fields(event_type = %event.event_type))]
+async fn store_email_event(
+    transaction: &mut Transaction<'_, Postgres>,
+    event: &EmailEvent,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let subscriber_id = sqlx::query!(
+        r#"
+        INSERT INTO email_events (
+            id,
+            subscriber_id,
+            recipient,
+            provider_message_id,
+            event_type,
+            description,
+            occurred_at
+        )
+        SELECT $1, (SELECT id FROM subscriptions WHERE email = $2), $2, $3, $4, $5, $6
+        RETURNING subscriber_id
+        "#,
+        Uuid::new_v4(),
+        event.recipient,
+        event.message_id,
+        event.event_type,
+        event.description,
+        event.occurred_at,
+    )
+    .fetch_one(&mut *transaction)
+    .await?
+    .subscriber_id;
+    Ok(subscriber_id)
+}
+
+#[tracing::instrument(skip_all)]
+async fn update_delivery_log(
+    transaction: &mut Transaction<'_, Postgres>,
+    event: &EmailEvent,
+) -> Result<(), sqlx::Error> {
+    let Some(message_id) = &event.message_id else {
+        return Ok(());
+    };
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET
+            provider_status = $2,
+            provider_status_at = $3
+        WHERE
+            provider_message_id = $1 AND
+            -- Webhooks can arrive out of order, so keep the latest event
+            (provider_status_at IS NULL OR provider_status_at <= $3)
+        "#,
+        message_id,
+        event.event_type,
+        event.occurred_at,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
+
+/// Stop sending to a subscriber the provider told us not to send to anymore.
+#[tracing::instrument(skip(transaction))]
+async fn suppress_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let subscriber = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'suppressed'
+        WHERE id = $1
+        RETURNING email
+        "#,
+        subscriber_id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    tracing::warn!(subscriber_email = %subscriber.email, "Suppressed a subscriber.");
+
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE subscriber_email = $1
+        "#,
+        subscriber.email
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
+
+/// Postmark bounce types after which sending again is pointless.
+///
+/// See <https://postmarkapp.com/developer/api/bounce-api#bounce-types>.
+const HARD_BOUNCE_TYPES: &[&str] = &["HardBounce", "BadEmailAddress", "ManuallyDeactivated"];
+
+/// The payload of a Postmark webhook, told apart by its `RecordType`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "RecordType")]
+pub enum PostmarkEvent {
+    #[serde(rename_all = "PascalCase")]
+    Bounce {
+        #[serde(rename = "MessageID")]
+        message_id: Option<String>,
+        email: String,
+        r#type: String,
+        description: String,
+        bounced_at: DateTime<Utc>,
+        #[serde(default)]
+        inactive: bool,
+    },
+    #[serde(rename_all = "PascalCase")]
+    SpamComplaint {
+        #[serde(rename = "MessageID")]
+        message_id: Option<String>,
+        email: String,
+        bounced_at: DateTime<Utc>,
+    },
+    #[serde(rename_all = "PascalCase")]
+    Delivery {
+        #[serde(rename = "MessageID")]
+        message_id: Option<String>,
+        recipient: String,
+        #[serde(default)]
+        details: String,
+        delivered_at: DateTime<Utc>,
+    },
+    #[serde(other)]
+    Unsupported,
+}
+
+/// A provider event, as stored in `email_events`.
+struct EmailEvent {
+    recipient: String,
+    message_id: Option<String>,
+    /// 'delivery', 'bounce' or 'spam_complaint'
+    event_type: &'static str,
+    description: String,
+    occurred_at: DateTime<Utc>,
+    suppresses_recipient: bool,
+}
+
+impl PostmarkEvent {
+    fn into_email_event(self) -> Option<EmailEvent> {
+        let event = match self {
+            PostmarkEvent::Bounce {
+                message_id,
+                email,
+                r#type,
+                description,
+                bounced_at,
+                inactive,
+            } => EmailEvent {
+                recipient: email,
+                message_id,
+                event_type: "bounce",
+                suppresses_recipient: inactive || HARD_BOUNCE_TYPES.contains(&r#type.as_str()),
+                description: format!("{}: {}", r#type, description),
+                occurred_at: bounced_at,
+            },
+            PostmarkEvent::SpamComplaint {
+                message_id,
+                email,
+                bounced_at,
+            } => EmailEvent {
+                recipient: email,
+                message_id,
+                event_type: "spam_complaint",
+                description: "The recipient marked the email as spam.".into(),
+                occurred_at: bounced_at,
+                suppresses_recipient: true,
+            },
+            PostmarkEvent::Delivery {
+                message_id,
+                recipient,
+                details,
+                delivered_at,
+            } => EmailEvent {
+                recipient,
+                message_id,
+                event_type: "delivery",
+                description: details,
+                occurred_at: delivered_at,
+                suppresses_recipient: false,
+            },
+            PostmarkEvent::Unsupported => return None,
+        };
+        Some(event)
+    }
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("Authentication failed")]
+    AuthError(#[source] anyhow::Error),
+    #[error("Invalid webhook payload")]
+    ValidationError(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        crate::error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for WebhookError {
+    fn into_response(self) -> axum::response::Response {
+        tracing::error!("{:?}", self);
+        match self {
+            WebhookError::AuthError(_) => {
+                let mut response = StatusCode::UNAUTHORIZED.into_response();
+                response.headers_mut().insert(
+                    header::WWW_AUTHENTICATE,
+                    HeaderValue::from_static(r#"Basic realm="webhooks""#),
+                );
+                response
+            }
+            WebhookError::ValidationError(_) => StatusCode::BAD_REQUEST.into_response(),
+            WebhookError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::assert_some;
+
+    use super::PostmarkEvent;
+
+    fn bounce(bounce_type: &str, inactive: bool) -> PostmarkEvent {
+        serde_json::from_value(serde_json::json!({
+            "RecordType": "Bounce",
+            "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+            "Type": bounce_type,
+            "Email": "ursula@example.com",
+            "Description": "The server was unable to deliver your message.",
+            "BouncedAt": "2023-10-23T16:33:54.9070259Z",
+            "Inactive": inactive,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn hard_bounces_suppress_the_recipient() {
+        let event = assert_some!(bounce("HardBounce", false).into_email_event());
+        assert!(event.suppresses_recipient);
+    }
+
+    #[test]
+    fn soft_bounces_do_not_suppress_the_recipient() {
+        let event = assert_some!(bounce("SoftBounce", false).into_email_event());
+        assert!(!event.suppresses_recipient);
+    }
+
+    #[test]
+    fn bounces_that_deactivated_the_recipient_suppress_them() {
+        let event = assert_some!(bounce("Transient", true).into_email_event());
+        assert!(event.suppresses_recipient);
+    }
+
+    #[test]
+    fn unsupported_record_types_are_ignored() {
+        let event: PostmarkEvent = serde_json::from_value(serde_json::json!({
+            "RecordType": "Open",
+            "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+        }))
+        .unwrap();
+        assert!(event.into_email_event().is_none());
+    }
+}
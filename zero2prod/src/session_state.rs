@@ -10,6 +10,8 @@ where
     T: DatabasePool + Clone + std::fmt::Debug + Sync + Send + 'static,
 {
     const USER_ID_KEY: &'static str = "user_id";
+    const PENDING_USER_ID_KEY: &'static str = "pending_user_id";
+    const SESSION_GENERATION_KEY: &'static str = "session_generation";
 
     pub fn get_user_id(&self) -> Option<Uuid> {
         self.0.get(Self::USER_ID_KEY)
@@ -19,6 +21,30 @@ where
         self.0.set(Self::USER_ID_KEY, user_id)
     }
 
+    /// The session generation of the user at login. Sessions from before the user's
+    /// generation was bumped are no longer valid.
+    pub fn get_session_generation(&self) -> i32 {
+        // Sessions from before generations existed count as the first one
+        self.0.get(Self::SESSION_GENERATION_KEY).unwrap_or(0)
+    }
+
+    pub fn insert_session_generation(&self, session_generation: i32) {
+        self.0.set(Self::SESSION_GENERATION_KEY, session_generation)
+    }
+
+    /// The user whose password checked out but who still owes a second factor.
+    pub fn get_pending_user_id(&self) -> Option<Uuid> {
+        self.0.get(Self::PENDING_USER_ID_KEY)
+    }
+
+    pub fn insert_pending_user_id(&self, user_id: Uuid) {
+        self.0.set(Self::PENDING_USER_ID_KEY, user_id)
+    }
+
+    pub fn remove_pending_user_id(&self) {
+        self.0.remove(Self::PENDING_USER_ID_KEY)
+    }
+
     pub fn log_out(self) {
         self.0.destroy();
     }
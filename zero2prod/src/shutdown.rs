@@ -0,0 +1,85 @@
+use tokio::sync::watch;
+
+/// Create a linked pair: the trigger requests a shutdown, every clone of the signal hears it.
+pub fn channel() -> (ShutdownTrigger, Shutdown) {
+    let (sender, receiver) = watch::channel(false);
+    (ShutdownTrigger(sender), Shutdown(receiver))
+}
+
+/// Requests a graceful shutdown of everything holding the matching [`Shutdown`].
+#[derive(Debug)]
+pub struct ShutdownTrigger(watch::Sender<bool>);
+
+impl ShutdownTrigger {
+    pub fn trigger(&self) {
+        self.0.send_replace(true);
+    }
+}
+
+/// Lets the server and the background workers find out that they should wind down.
+#[derive(Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+impl Shutdown {
+    pub fn is_requested(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Resolve once a shutdown has been requested.
+    ///
+    /// Dropping the trigger without calling it never counts as a request.
+    pub async fn requested(mut self) {
+        if self.0.wait_for(|requested| *requested).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+/// Wait for SIGTERM, which is how Kubernetes asks a pod to stop, or for Ctrl+C.
+pub async fn wait_for_signal() -> Result<(), std::io::Error> {
+    #[cfg(unix)]
+    {
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            _ = terminate.recv() => Ok(()),
+            result = tokio::signal::ctrl_c() => result,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use claims::{assert_err, assert_ok};
+
+    use super::channel;
+
+    #[tokio::test]
+    async fn requested_resolves_once_the_trigger_fires() {
+        let (trigger, shutdown) = channel();
+        assert!(!shutdown.is_requested());
+
+        trigger.trigger();
+
+        assert!(shutdown.is_requested());
+        assert_ok!(tokio::time::timeout(Duration::from_secs(1), shutdown.requested()).await);
+    }
+
+    #[tokio::test]
+    async fn requested_does_not_resolve_without_a_trigger() {
+        let (trigger, shutdown) = channel();
+        let waiting = tokio::time::timeout(Duration::from_millis(50), shutdown.clone().requested());
+        assert_err!(waiting.await);
+
+        // Dropping the trigger is not a request to shut down either
+        drop(trigger);
+        let waiting = tokio::time::timeout(Duration::from_millis(50), shutdown.requested());
+        assert_err!(waiting.await);
+    }
+}
@@ -1,9 +1,13 @@
-use std::{net::TcpListener, sync::Arc};
+use std::{
+    net::{SocketAddr, TcpListener},
+    sync::Arc,
+};
 
 use axum::{
+    extract::connect_info::IntoMakeServiceWithConnectInfo,
     extract::FromRef,
     middleware,
-    routing::{get, post, IntoMakeService},
+    routing::{get, post},
     Router, Server,
 };
 use axum_flash::Key;
@@ -13,21 +17,33 @@ use secrecy::{ExposeSecret, Secret};
 use sqlx::{postgres::PgPoolOptions, PgPool};
 
 use crate::{
-    authentication::reject_anonymous_users,
-    configuration::{DatabaseSettings, Settings},
+    authentication::{
+        reject_anonymous_users, reject_non_owners, reject_viewers, LoginThrottle, PasswordHashing,
+    },
+    configuration::{DatabaseSettings, Settings, WebhookSettings},
+    domain::EmailLayout,
     routes::{
-        admin_dashboard, change_password, change_password_form, confirm, home, log_out, login,
-        login_form,
+        accept_invitation, accept_invitation_form, admin_dashboard, atom_feed, change_email,
+        change_email_form, change_password, change_password_form, clear_login_lock, confirm,
+        confirm_two_factor, create_issue, delete_issue, delete_user, delivery_failures,
+        delivery_status, disable_user, edit_issue_form, enable_user, forgot_password_form, home,
+        invite_user, issue_delivery_status, list_issues, list_login_locks, list_published_issues,
+        list_users, log_out, login, login_form, new_issue_form,
         newsletters::{newsletters_publish_form, publish_newsletter},
+        postmark_webhook, preview_issue, publish_issue, request_password_reset,
+        requeue_delivery_failures, reset_password, reset_password_form, rss_feed, send_test_email,
+        start_two_factor, turn_off_two_factor, two_factor_form, two_factor_settings, unsubscribe,
+        unsubscribe_form, update_issue, verify_two_factor, view_issue,
     },
+    shutdown::Shutdown,
     telemetry::RouterExt,
 };
 use crate::{
-    email_client::EmailClient,
+    email_client::EmailSender,
     routes::{health_check, subscribe},
 };
 
-pub type AppServer = Server<AddrIncoming, IntoMakeService<Router>>;
+pub type AppServer = Server<AddrIncoming, IntoMakeServiceWithConnectInfo<Router, SocketAddr>>;
 
 pub struct Application {
     port: u16,
@@ -44,26 +60,34 @@ impl Application {
         // Create a session store
         let session_config = SessionConfig::new();
         let session_store =
-            SessionStore::<SessionRedisPool>::new(Some(redis.into()), session_config);
+            SessionStore::<SessionRedisPool>::new(Some(redis.clone().into()), session_config);
+        let login_throttle = LoginThrottle::new(redis, configuration.login_throttle);
+        let password_hashing = configuration.password_hashing.hashing()?;
 
         // Build an email client
-        let email_client = configuration.email_client.client();
+        let email_client = configuration.email_client.client()?;
+        let email_layout = configuration.newsletter.layout()?;
 
         let address = format!(
             "{}:{}",
             configuration.application.host, configuration.application.port
         );
-        let listener = TcpListener::bind(address.to_string()).map_err(|e| {
+        let listener = TcpListener::bind(address.to_string()).inspect_err(|_| {
             tracing::error!("failed to bind port {}", address);
-            e
         })?;
         let port = listener.local_addr().unwrap().port();
+        let subscription_token_ttl = configuration.application.subscription_token_ttl();
         let server = run(
             listener,
             db_pool,
             email_client,
             configuration.application.base_url,
             configuration.application.hmac_secret,
+            subscription_token_ttl,
+            configuration.webhooks,
+            email_layout,
+            login_throttle,
+            password_hashing,
             session_store,
         );
         Ok(Self { port, server })
@@ -73,8 +97,11 @@ impl Application {
         self.port
     }
 
-    pub async fn run_until_stopped(self) -> hyper::Result<()> {
-        self.server.await
+    /// Serve requests until a shutdown is requested, then let in-flight requests finish.
+    pub async fn run_until_stopped(self, shutdown: Shutdown) -> hyper::Result<()> {
+        self.server
+            .with_graceful_shutdown(shutdown.requested())
+            .await
     }
 }
 
@@ -85,42 +112,127 @@ pub fn get_db_pool(configuration: &DatabaseSettings) -> PgPool {
         .connect_lazy_with(configuration.with_db())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn run(
     listener: TcpListener,
     db_pool: PgPool,
-    email_client: EmailClient,
+    email_client: Arc<dyn EmailSender>,
     base_url: String,
     hmac_secret: Secret<String>,
+    subscription_token_ttl: chrono::Duration,
+    webhooks: WebhookSettings,
+    email_layout: EmailLayout,
+    login_throttle: LoginThrottle,
+    password_hashing: PasswordHashing,
     session_store: SessionStore<SessionRedisPool>,
 ) -> AppServer {
+    if webhooks.postmark_password().is_none() {
+        tracing::warn!(
+            "The Postmark webhook password is still the placeholder, so every webhook request \
+            will be refused."
+        );
+    }
+
     // Build app state
     let app_state = AppState {
         db_pool,
-        email_client: Arc::new(email_client),
+        email_client,
         base_url: ApplicationBaseUrl(base_url),
         flash_config: axum_flash::Config::new(Key::from(hmac_secret.expose_secret().as_bytes())),
+        hmac_secret: HmacSecret(hmac_secret),
+        subscription_token_ttl: SubscriptionTokenTtl(subscription_token_ttl),
+        postmark_webhook_credentials: PostmarkWebhookCredentials {
+            password: webhooks.postmark_password(),
+            username: webhooks.postmark_username,
+        },
+        email_layout,
+        login_throttle,
+        password_hashing,
     };
 
     // Routes that need to not have a session applied
-    let router_no_session = Router::new().route("/health_check", get(health_check));
+    let router_no_session = Router::new()
+        .route("/health_check", get(health_check))
+        // Mailbox providers post one-click unsubscribe requests without any cookies
+        .route("/subscriptions/unsubscribe", get(unsubscribe_form))
+        .route("/subscriptions/unsubscribe", post(unsubscribe))
+        .route("/webhooks/postmark", post(postmark_webhook))
+        .route("/issues", get(list_published_issues))
+        .route("/feed.xml", get(rss_feed))
+        .route("/atom.xml", get(atom_feed))
+        .route("/issues/:issue_id", get(view_issue));
 
-    // All admin section routes
-    let router_for_admin_section = Router::new()
+    // Admin routes every logged in user can see
+    let router_for_viewers = Router::new()
         .route("/admin/dashboard", get(admin_dashboard))
-        .route("/admin/newsletters", get(newsletters_publish_form))
-        .route("/admin/newsletters", post(publish_newsletter))
+        .route("/admin/issues", get(list_issues))
+        .route("/admin/issues/:issue_id/preview", get(preview_issue))
+        .route("/admin/deliveries", get(delivery_status))
+        .route("/admin/deliveries/:issue_id", get(issue_delivery_status))
+        .route("/admin/deliveries/failures", get(delivery_failures))
         .route("/admin/password", get(change_password_form))
         .route("/admin/password", post(change_password))
-        .route("/admin/logout", post(log_out))
-        .layer(middleware::from_fn(reject_anonymous_users));
+        .route("/admin/email", get(change_email_form))
+        .route("/admin/email", post(change_email))
+        .route("/admin/two-factor", get(two_factor_settings))
+        .route("/admin/two-factor/setup", post(start_two_factor))
+        .route("/admin/two-factor/confirm", post(confirm_two_factor))
+        .route("/admin/two-factor/disable", post(turn_off_two_factor))
+        .route("/admin/logout", post(log_out));
+
+    // Admin routes that write or send issues
+    let router_for_editors = Router::new()
+        .route("/admin/newsletters", get(newsletters_publish_form))
+        .route("/admin/newsletters", post(publish_newsletter))
+        .route("/admin/issues", post(create_issue))
+        .route("/admin/issues/new", get(new_issue_form))
+        .route("/admin/issues/:issue_id/edit", get(edit_issue_form))
+        .route("/admin/issues/:issue_id/edit", post(update_issue))
+        .route("/admin/issues/:issue_id/delete", post(delete_issue))
+        .route("/admin/issues/:issue_id/publish", post(publish_issue))
+        .route("/admin/issues/:issue_id/test", post(send_test_email))
+        .route(
+            "/admin/deliveries/failures",
+            post(requeue_delivery_failures),
+        )
+        .layer(middleware::from_fn(reject_viewers));
+
+    // Admin routes that manage who else has access
+    let router_for_owners = Router::new()
+        .route("/admin/users", get(list_users))
+        .route("/admin/users/invitations", post(invite_user))
+        .route("/admin/users/:user_id/disable", post(disable_user))
+        .route("/admin/users/:user_id/enable", post(enable_user))
+        .route("/admin/users/:user_id/delete", post(delete_user))
+        .route("/admin/login-locks", get(list_login_locks))
+        .route("/admin/login-locks/clear", post(clear_login_lock))
+        .layer(middleware::from_fn(reject_non_owners));
+
+    // All admin section routes
+    let router_for_admin_section = Router::new()
+        .merge(router_for_viewers)
+        .merge(router_for_editors)
+        .merge(router_for_owners)
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            reject_anonymous_users,
+        ));
 
     // All routes that should be a care about session
     let router_with_session = Router::new()
         .route("/", get(home))
         .route("/login", get(login_form))
         .route("/login", post(login))
+        .route("/login/forgot-password", get(forgot_password_form))
+        .route("/login/forgot-password", post(request_password_reset))
+        .route("/login/reset-password", get(reset_password_form))
+        .route("/login/reset-password", post(reset_password))
+        .route("/login/two-factor", get(two_factor_form))
+        .route("/login/two-factor", post(verify_two_factor))
         .route("/subscriptions", post(subscribe))
         .route("/subscriptions/confirm", get(confirm))
+        .route("/invitations/accept", get(accept_invitation_form))
+        .route("/invitations/accept", post(accept_invitation))
         .merge(router_for_admin_section)
         .layer(SessionLayer::new(session_store));
 
@@ -134,15 +246,22 @@ pub fn run(
     // Start the axum server and set up to use supplied listener
     axum::Server::from_tcp(listener)
         .expect("failed to create server from listener")
-        .serve(app.into_make_service())
+        // Login throttling falls back to the peer address when there's no proxy header
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
 }
 
 #[derive(Clone)]
 pub struct AppState {
     db_pool: PgPool,
-    email_client: Arc<EmailClient>,
+    email_client: Arc<dyn EmailSender>,
     base_url: ApplicationBaseUrl,
     flash_config: axum_flash::Config,
+    hmac_secret: HmacSecret,
+    subscription_token_ttl: SubscriptionTokenTtl,
+    postmark_webhook_credentials: PostmarkWebhookCredentials,
+    email_layout: EmailLayout,
+    login_throttle: LoginThrottle,
+    password_hashing: PasswordHashing,
 }
 
 impl FromRef<AppState> for PgPool {
@@ -151,7 +270,7 @@ impl FromRef<AppState> for PgPool {
     }
 }
 
-impl FromRef<AppState> for Arc<EmailClient> {
+impl FromRef<AppState> for Arc<dyn EmailSender> {
     fn from_ref(app_state: &AppState) -> Self {
         app_state.email_client.clone()
     }
@@ -169,8 +288,56 @@ impl FromRef<AppState> for axum_flash::Config {
     }
 }
 
+impl FromRef<AppState> for HmacSecret {
+    fn from_ref(app_state: &AppState) -> Self {
+        app_state.hmac_secret.clone()
+    }
+}
+
+impl FromRef<AppState> for SubscriptionTokenTtl {
+    fn from_ref(app_state: &AppState) -> Self {
+        app_state.subscription_token_ttl
+    }
+}
+
+impl FromRef<AppState> for PostmarkWebhookCredentials {
+    fn from_ref(app_state: &AppState) -> Self {
+        app_state.postmark_webhook_credentials.clone()
+    }
+}
+
+impl FromRef<AppState> for EmailLayout {
+    fn from_ref(app_state: &AppState) -> Self {
+        app_state.email_layout.clone()
+    }
+}
+
+impl FromRef<AppState> for LoginThrottle {
+    fn from_ref(app_state: &AppState) -> Self {
+        app_state.login_throttle.clone()
+    }
+}
+
+impl FromRef<AppState> for PasswordHashing {
+    fn from_ref(app_state: &AppState) -> Self {
+        app_state.password_hashing.clone()
+    }
+}
+
 #[derive(Clone)]
 pub struct ApplicationBaseUrl(pub String);
 
 #[derive(Clone)]
 pub struct HmacSecret(pub Secret<String>);
+
+/// How long a subscription confirmation token stays valid after it was issued.
+#[derive(Clone, Copy)]
+pub struct SubscriptionTokenTtl(pub chrono::Duration);
+
+/// The basic auth credentials Postmark presents when it posts to our webhook.
+#[derive(Clone)]
+pub struct PostmarkWebhookCredentials {
+    pub username: String,
+    /// `None` while the configured password is the placeholder, which refuses every request.
+    pub password: Option<Secret<String>>,
+}
@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use sqlx::PgPool;
+
+use crate::{configuration::Settings, shutdown::Shutdown, startup::get_db_pool};
+
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    // Set up the worker
+    let connection_pool = get_db_pool(&configuration.database);
+    let token_ttl = configuration.application.subscription_token_ttl();
+    worker_loop(connection_pool, token_ttl, shutdown).await
+}
+
+async fn worker_loop(
+    pool: PgPool,
+    token_ttl: chrono::Duration,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    while !shutdown.is_requested() {
+        // A failed sweep is retried on the next tick
+        if let Err(e) = remove_expired_subscription_tokens(&pool, token_ttl).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to remove expired subscription tokens.",
+            );
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(60 * 60)) => {}
+            _ = shutdown.clone().requested() => {}
+        }
+    }
+    Ok(())
+}
+
+/// Purge expired confirmation tokens, then any pending subscriber left without a valid one.
+#[tracing::instrument(skip(pool))]
+pub async fn remove_expired_subscription_tokens(
+    pool: &PgPool,
+    token_ttl: chrono::Duration,
+) -> Result<(), sqlx::Error> {
+    let cutoff = Utc::now() - token_ttl;
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM subscription_tokens
+        WHERE
+            created_at < $1
+        "#,
+        cutoff
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM subscriptions
+        WHERE
+            status = 'pending_confirmation' AND
+            subscribed_at < $1 AND
+            NOT EXISTS (
+                SELECT 1 FROM subscription_tokens
+                WHERE subscription_tokens.subscriber_id = subscriptions.id
+            )
+        "#,
+        cutoff
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
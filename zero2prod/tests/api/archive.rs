@@ -0,0 +1,140 @@
+use uuid::Uuid;
+
+use crate::helpers::{spawn_app, TestApp};
+
+/// Store an issue directly, published the given number of days ago.
+pub async fn insert_issue(app: &TestApp, title: &str, status: &str, days_ago: i32) -> Uuid {
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            status,
+            published_at
+        )
+        VALUES (
+            $1, $2, 'Plain text body', '<p>Html body</p>', $3,
+            CASE WHEN $3 = 'published' THEN now() - make_interval(days => $4) END
+        )
+        "#,
+        issue_id,
+        title,
+        status,
+        days_ago
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    issue_id
+}
+
+#[tokio::test]
+async fn the_archive_lists_published_issues_newest_first() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_issue(&app, "An older issue", "published", 7).await;
+    insert_issue(&app, "The latest issue", "published", 0).await;
+    insert_issue(&app, "A draft", "draft", 0).await;
+    insert_issue(&app, "A scheduled issue", "scheduled", 0).await;
+
+    // Act
+    let html_page = app.get_archive_html(1).await;
+
+    // Assert
+    let latest = html_page.find("The latest issue").unwrap();
+    let older = html_page.find("An older issue").unwrap();
+    assert!(latest < older);
+    assert!(!html_page.contains("A draft"));
+    assert!(!html_page.contains("A scheduled issue"));
+    assert!(!html_page.contains("Older issues"));
+}
+
+#[tokio::test]
+async fn the_archive_is_paginated() {
+    // Arrange
+    let app = spawn_app().await;
+    for days_ago in 0..21 {
+        insert_issue(
+            &app,
+            &format!("Issue #{}", 21 - days_ago),
+            "published",
+            days_ago,
+        )
+        .await;
+    }
+
+    // Act - Part 1 - First page
+    let html_page = app.get_archive_html(1).await;
+    assert!(html_page.contains("Issue #21<"));
+    assert!(html_page.contains("Issue #2<"));
+    assert!(!html_page.contains("Issue #1<"));
+    assert!(html_page.contains(r#"href="/issues?page=2""#));
+    assert!(!html_page.contains("Newer issues"));
+
+    // Act - Part 2 - Second page
+    let html_page = app.get_archive_html(2).await;
+    assert!(html_page.contains("Issue #1<"));
+    assert!(!html_page.contains("Issue #2<"));
+    assert!(html_page.contains(r#"href="/issues?page=1""#));
+    assert!(!html_page.contains("Older issues"));
+}
+
+#[tokio::test]
+async fn published_issues_can_be_read_in_the_browser() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = insert_issue(&app, "The latest issue", "published", 0).await;
+
+    // Act
+    let response = app.get_web_view(issue_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<title>The latest issue</title>"));
+    assert!(html_page.contains(r#"srcdoc="&lt;p&gt;Html body&lt;/p&gt;""#));
+    assert!(html_page.contains(r#"href="/issues""#));
+}
+
+#[tokio::test]
+async fn the_issue_is_shown_in_a_sandboxed_frame() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = insert_issue(&app, "The latest issue", "published", 0).await;
+    sqlx::query!(
+        "UPDATE newsletter_issues SET html_content = '<script>alert(1)</script>' \
+        WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = app.get_web_view(issue_id).await;
+
+    // Assert
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<iframe sandbox="));
+    assert!(!html_page.contains("allow-scripts"));
+    assert!(!html_page.contains("allow-same-origin"));
+    assert!(!html_page.contains("<script>"));
+}
+
+#[tokio::test]
+async fn unknown_and_unpublished_issues_are_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+    let scheduled_id = insert_issue(&app, "A scheduled issue", "scheduled", 0).await;
+
+    for issue_id in [Uuid::new_v4(), scheduled_id] {
+        // Act
+        let response = app.get_web_view(issue_id).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 404);
+    }
+}
@@ -0,0 +1,211 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::{
+    helpers::{create_confirmed_subscriber, spawn_app, PostmarkBatchResponder, TestApp},
+    login::assert_is_redirect_to,
+};
+
+/// Publish an issue to the existing subscribers and return its id.
+async fn publish_newsletter(app: &TestApp) -> uuid::Uuid {
+    app.test_user.login(app).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    }))
+    .await;
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_delivery_failures() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_delivery_failures().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn deliveries_that_exhaust_their_retries_are_moved_to_the_failures() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    publish_newsletter(&app).await;
+    sqlx::query!(
+        "UPDATE issue_delivery_queue SET retries = 100, last_error = '503 Service Unavailable'"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let queued = sqlx::query!("SELECT COUNT(*) AS count FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, Some(0));
+    let failure = sqlx::query!("SELECT retries, last_error FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(failure.retries, 100);
+    assert!(failure.last_error.contains("503 Service Unavailable"));
+
+    let html_page = app.get_delivery_failures_html().await;
+    assert!(html_page.contains("Newsletter title"));
+    assert!(html_page.contains("503 Service Unavailable"));
+}
+
+#[tokio::test]
+async fn deliveries_to_invalid_addresses_are_moved_to_the_failures() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    publish_newsletter(&app).await;
+    sqlx::query!("UPDATE issue_delivery_queue SET subscriber_email = 'not-an-email'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let failure = sqlx::query!("SELECT subscriber_email, last_error FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(failure.subscriber_email, "not-an-email");
+    assert!(failure.last_error.starts_with("Invalid subscriber email"));
+}
+
+#[tokio::test]
+async fn permanently_rejected_deliveries_are_not_retried() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    // Inactive recipient
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::failing_first(1).with_error_code(406))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let failure = sqlx::query!("SELECT retries, last_error FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(failure.retries, 1);
+    assert!(failure.last_error.contains("error code 406"));
+}
+
+#[tokio::test]
+async fn transient_failures_are_moved_to_the_failures_after_max_attempts() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(app.retry_policy.max_attempts as u64)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let failure = sqlx::query!("SELECT retries, last_error FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(failure.retries, app.retry_policy.max_attempts as i32);
+    assert!(failure.last_error.starts_with(&format!(
+        "Gave up after {} attempts",
+        app.retry_policy.max_attempts
+    )));
+}
+
+#[tokio::test]
+async fn requeued_failures_are_delivered_again() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let issue_id = publish_newsletter(&app).await;
+    sqlx::query!("UPDATE issue_delivery_queue SET retries = 100")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    let failure = sqlx::query!("SELECT subscriber_email FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::all_succeed())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Requeue the failure
+    let response = app
+        .post_requeue_delivery_failures(&[(
+            "failure",
+            format!("{}:{}", issue_id, failure.subscriber_email),
+        )])
+        .await;
+    assert_is_redirect_to(&response, "/admin/deliveries/failures");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_delivery_failures_html().await;
+    assert!(html_page.contains("Requeued 1 failed deliveries."));
+    assert!(html_page.contains("There are no failed deliveries."));
+
+    // Act - Part 3 - Deliver the requeued issue
+    app.dispatch_all_pending_emails().await;
+
+    // Mock verifies on Drop that the issue was delivered
+}
+
+#[tokio::test]
+async fn requeueing_nothing_shows_an_error() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_requeue_delivery_failures(&Vec::<(&str, String)>::new())
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/deliveries/failures");
+    let html_page = app.get_delivery_failures_html().await;
+    assert!(html_page.contains("Select at least one failed delivery to requeue."));
+}
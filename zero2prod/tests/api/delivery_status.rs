@@ -0,0 +1,138 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock,
+};
+
+use crate::{
+    helpers::{create_confirmed_subscriber, spawn_app, PostmarkBatchResponder, TestApp},
+    login::assert_is_redirect_to,
+};
+
+/// Publish an issue to the existing subscribers and return its id.
+async fn publish_newsletter(app: &TestApp) -> uuid::Uuid {
+    app.test_user.login(app).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    }))
+    .await;
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_delivery_status() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_issue_delivery_status(uuid::Uuid::new_v4()).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_delivery_status_of_an_unknown_issue_is_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_issue_delivery_status(uuid::Uuid::new_v4()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn delivered_emails_are_recorded_with_their_provider_message_id() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::all_succeed())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let issue_id = publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let delivery = sqlx::query!(
+        "SELECT outcome, provider_message_id, sent_at FROM issue_deliveries \
+        WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(delivery.outcome, "sent");
+    assert!(delivery.provider_message_id.is_some());
+    assert!(delivery.sent_at.is_some());
+}
+
+#[tokio::test]
+async fn permanent_failures_are_recorded_in_the_delivery_log() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::failing_first(1).with_error_code(406))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let delivery = sqlx::query!("SELECT outcome, error, sent_at FROM issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(delivery.outcome, "failed");
+    assert!(delivery.error.unwrap().contains("406"));
+    assert!(delivery.sent_at.is_none());
+}
+
+#[tokio::test]
+async fn the_delivery_status_page_shows_how_far_an_issue_has_gone_out() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::all_succeed())
+        .mount(&app.email_server)
+        .await;
+
+    let issue_id = publish_newsletter(&app).await;
+
+    // Act - Part 1 - Nothing has been sent yet
+    let html_page = app.get_issue_delivery_status_html(issue_id).await;
+    assert!(html_page.contains("Newsletter title"));
+    assert!(html_page.contains("<th>Queued</th><td>2</td>"));
+    assert!(html_page.contains("0 of 2 done"));
+
+    // Act - Part 2 - Everything went out
+    app.dispatch_all_pending_emails().await;
+    let html_page = app.get_issue_delivery_status_html(issue_id).await;
+    assert!(html_page.contains("<th>Queued</th><td>0</td>"));
+    assert!(html_page.contains("<th>Sent</th><td>2</td>"));
+    assert!(html_page.contains("2 of 2 done"));
+}
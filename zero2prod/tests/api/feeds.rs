@@ -0,0 +1,87 @@
+use crate::{archive::insert_issue, helpers::spawn_app};
+
+#[tokio::test]
+async fn the_rss_feed_lists_published_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = insert_issue(&app, "Fish & chips", "published", 1).await;
+    insert_issue(&app, "A draft", "draft", 0).await;
+
+    // Act
+    let response = app.get_feed("/feed.xml", None).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/rss+xml; charset=utf-8"
+    );
+    let feed = response.text().await.unwrap();
+    assert!(feed.contains(r#"<rss version="2.0""#));
+    assert!(feed.contains("<title>Fish &amp; chips</title>"));
+    assert!(feed.contains(&format!(
+        r#"<guid isPermaLink="false">urn:uuid:{}</guid>"#,
+        issue_id
+    )));
+    assert!(feed.contains(&format!(
+        "<link>{}/issues/{}</link>",
+        app.base_url, issue_id
+    )));
+    assert!(feed.contains("<description>&lt;p&gt;Html body&lt;/p&gt;</description>"));
+    assert!(feed.contains("<pubDate>"));
+    assert!(!feed.contains("A draft"));
+}
+
+#[tokio::test]
+async fn the_atom_feed_lists_published_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = insert_issue(&app, "The latest issue", "published", 0).await;
+    insert_issue(&app, "A scheduled issue", "scheduled", 0).await;
+
+    // Act
+    let response = app.get_feed("/atom.xml", None).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/atom+xml; charset=utf-8"
+    );
+    let feed = response.text().await.unwrap();
+    assert!(feed.contains(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#));
+    assert!(feed.contains(&format!("<id>urn:uuid:{}</id>", issue_id)));
+    assert!(feed.contains(r#"<content type="html">&lt;p&gt;Html body&lt;/p&gt;</content>"#));
+    assert!(!feed.contains("A scheduled issue"));
+}
+
+#[tokio::test]
+async fn feeds_are_not_sent_again_if_unchanged() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_issue(&app, "The latest issue", "published", 0).await;
+
+    for feed in ["/feed.xml", "/atom.xml"] {
+        let response = app.get_feed(feed, None).await;
+        let etag = response.headers()["ETag"].to_str().unwrap().to_owned();
+
+        // Act - Part 1 - Unchanged
+        let response = app.get_feed(feed, Some(&etag)).await;
+        assert_eq!(response.status().as_u16(), 304);
+        assert_eq!(response.headers()["ETag"], etag.as_str());
+        assert!(response.text().await.unwrap().is_empty());
+
+        // Act - Part 2 - Stale
+        let response = app.get_feed(feed, Some("\"stale\"")).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // Act - Part 3 - A new issue changes the feed
+    let etag = app.get_feed("/feed.xml", None).await.headers()["ETag"]
+        .to_str()
+        .unwrap()
+        .to_owned();
+    insert_issue(&app, "An even later issue", "published", 0).await;
+    let response = app.get_feed("/feed.xml", Some(&etag)).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
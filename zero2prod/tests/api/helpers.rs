@@ -1,15 +1,31 @@
+use std::sync::Arc;
+
 use argon2::{password_hash::SaltString, Algorithm, Argon2, Params, PasswordHasher, Version};
+use fake::{
+    faker::{internet::en::SafeEmail, name::en::Name},
+    Fake,
+};
 use once_cell::sync::Lazy;
 use reqwest::Response;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::{Connection, Executor, PgConnection, PgPool};
+use tokio::task::JoinHandle;
 use uuid::Uuid;
-use wiremock::MockServer;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, Request, Respond, ResponseTemplate,
+};
 use zero2prod::{
-    configuration::{get_configuration, DatabaseSettings},
-    email_client::EmailClient,
+    configuration::{get_configuration, DatabaseSettings, EmailTransport, Settings},
+    email_client::EmailSender,
     idempotency_remover_worker::remove_old_idempotency_entries,
     issue_delivery_worker::{try_execute_task, ExecutionOutcome},
-    startup::{get_db_pool, Application},
+    issue_scheduler_worker::publish_due_issues,
+    retry_policy::RetryPolicy,
+    routes::build_unsubscribe_link,
+    shutdown::{self, ShutdownTrigger},
+    startup::{get_db_pool, Application, ApplicationBaseUrl, HmacSecret},
+    subscription_token_remover_worker::remove_expired_subscription_tokens,
     telemetry::{get_subscriber, init_subscriber},
 };
 
@@ -29,6 +45,11 @@ static TRACING: Lazy<()> = Lazy::new(|| {
 });
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Spawn an app after adjusting the test configuration.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
     // Set up subscriber for logging, only first time per run. Other times use existing subscriber.
     Lazy::force(&TRACING);
 
@@ -40,7 +61,17 @@ pub async fn spawn_app() -> TestApp {
         c.database.database_name = Uuid::new_v4().to_string();
         // Use a random OS port
         c.application.port = 0;
+        // Always talk to the mock Postmark server, whatever transport is configured
+        c.email_client.transport = EmailTransport::Postmark;
         c.email_client.base_url = email_server.uri();
+        // Retry straight away so tests can drain the queue without waiting
+        c.delivery_worker.retry_policy.base_delay_milliseconds = 0;
+        // Every test app poses as its own client address, see `api_client` below
+        c.login_throttle.client_ip_header = Some("x-forwarded-for".into());
+        c.login_throttle.max_failures_per_ip = 10;
+        // The placeholder from base.yml refuses every webhook request
+        c.webhooks.postmark_password = Secret::new(Uuid::new_v4().to_string());
+        configure(&mut c);
         c
     };
 
@@ -53,11 +84,22 @@ pub async fn spawn_app() -> TestApp {
         .expect("Failed to build application");
     let port = app.port();
     let address = format!("http://127.0.0.1:{}", app.port());
-    tokio::spawn(app.run_until_stopped());
-
+    let (shutdown, shutdown_signal) = shutdown::channel();
+    let server = tokio::spawn(app.run_until_stopped(shutdown_signal));
+
+    // All tests share one redis, so failed logins would add up across them from 127.0.0.1
+    let client_ip = format!(
+        "10.{}.{}.{}",
+        rand::random::<u8>(),
+        rand::random::<u8>(),
+        rand::random::<u8>()
+    );
+    let mut default_headers = reqwest::header::HeaderMap::new();
+    default_headers.insert("x-forwarded-for", client_ip.parse().unwrap());
     let api_client = reqwest::Client::builder()
         .redirect(reqwest::redirect::Policy::none())
         .cookie_store(true)
+        .default_headers(default_headers)
         .build()
         .unwrap();
 
@@ -68,7 +110,14 @@ pub async fn spawn_app() -> TestApp {
         email_server,
         test_user: TestUser::generate(),
         api_client,
-        email_client: configuration.email_client.client(),
+        email_client: configuration.email_client.clone().client().unwrap(),
+        subscription_token_ttl: configuration.application.subscription_token_ttl(),
+        retry_policy: configuration.delivery_worker.retry_policy.clone(),
+        base_url: configuration.application.base_url.clone(),
+        hmac_secret: configuration.application.hmac_secret.clone(),
+        configuration,
+        shutdown,
+        server,
     };
 
     test_app.test_user.store(&test_app.db_pool).await;
@@ -104,14 +153,21 @@ pub struct TestUser {
     pub user_id: Uuid,
     pub username: String,
     pub password: String,
+    /// 'owner', 'editor' or 'viewer'
+    pub role: &'static str,
 }
 
 impl TestUser {
     pub fn generate() -> Self {
+        Self::generate_with_role("owner")
+    }
+
+    pub fn generate_with_role(role: &'static str) -> Self {
         Self {
             user_id: Uuid::new_v4(),
             username: Uuid::new_v4().to_string(),
             password: Uuid::new_v4().to_string(),
+            role,
         }
     }
 
@@ -122,7 +178,8 @@ impl TestUser {
         }))
         .await
     }
-    async fn store(&self, pool: &PgPool) {
+
+    pub async fn store(&self, pool: &PgPool) {
         let salt = SaltString::generate(&mut rand::thread_rng());
         let password_hash = Argon2::new(
             Algorithm::Argon2id,
@@ -135,12 +192,13 @@ impl TestUser {
 
         sqlx::query!(
             r#"
-            INSERT INTO users (user_id, username, password_hash)
-            VALUES ($1, $2, $3)
+            INSERT INTO users (user_id, username, password_hash, role)
+            VALUES ($1, $2, $3, $4)
             "#,
             self.user_id,
             self.username,
             password_hash,
+            self.role,
         )
         .execute(pool)
         .await
@@ -155,30 +213,56 @@ pub struct TestApp {
     pub email_server: MockServer,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
-    pub email_client: EmailClient,
+    pub email_client: Arc<dyn EmailSender>,
+    pub base_url: String,
+    pub hmac_secret: Secret<String>,
+    pub subscription_token_ttl: chrono::Duration,
+    pub retry_policy: RetryPolicy,
+    pub configuration: Settings,
+    pub shutdown: ShutdownTrigger,
+    pub server: JoinHandle<hyper::Result<()>>,
 }
 
 impl TestApp {
     pub async fn dispatch_all_pending_emails(&self) {
+        // Never triggered, so every batch is sent in full
+        let (_trigger, shutdown) = shutdown::channel();
         loop {
-            if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.db_pool, &self.email_client)
-                    .await
-                    .unwrap()
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.db_pool,
+                self.email_client.as_ref(),
+                &ApplicationBaseUrl(self.base_url.clone()),
+                &HmacSecret(self.hmac_secret.clone()),
+                &self.configuration.delivery_worker,
+                &shutdown,
+            )
+            .await
+            .unwrap()
             {
                 break;
             }
         }
     }
 
+    /// Publish every scheduled issue that has come due.
+    pub async fn publish_due_issues(&self) -> usize {
+        publish_due_issues(&self.db_pool).await.unwrap()
+    }
+
     pub async fn clean_up_idempotency(&self) {
         remove_old_idempotency_entries(&self.db_pool).await.unwrap();
     }
 
+    pub async fn clean_up_subscription_tokens(&self) {
+        remove_expired_subscription_tokens(&self.db_pool, self.subscription_token_ttl)
+            .await
+            .unwrap();
+    }
+
     /// Send a get request to the admin dashboard endpoint.
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/dashboard", &self.address))
+            .get(format!("{}/admin/dashboard", &self.address))
             .send()
             .await
             .expect("Failed to execute request")
@@ -192,7 +276,7 @@ impl TestApp {
     /// Send a get request to the change admin password endpoint
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/password", &self.address))
+            .get(format!("{}/admin/password", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -203,6 +287,109 @@ impl TestApp {
         self.get_change_password().await.text().await.unwrap()
     }
 
+    /// Send a get request to the delivery failures endpoint
+    pub async fn get_delivery_failures(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/deliveries/failures", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Return the html from the delivery failures page
+    pub async fn get_delivery_failures_html(&self) -> String {
+        self.get_delivery_failures().await.text().await.unwrap()
+    }
+
+    /// Send a get request to the delivery status page of an issue
+    pub async fn get_issue_delivery_status(&self, issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/deliveries/{}", &self.address, issue_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Return the html from the delivery status page of an issue
+    pub async fn get_issue_delivery_status_html(&self, issue_id: Uuid) -> String {
+        self.get_issue_delivery_status(issue_id)
+            .await
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Send a get request to the newsletter issues page
+    pub async fn get_issues(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/issues", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Return the html from the newsletter issues page
+    pub async fn get_issues_html(&self) -> String {
+        self.get_issues().await.text().await.unwrap()
+    }
+
+    /// Return the html from the edit page of an issue
+    pub async fn get_edit_issue_html(&self, issue_id: Uuid) -> String {
+        self.api_client
+            .get(format!("{}/admin/issues/{}/edit", &self.address, issue_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Return the html of a page of the public newsletter archive.
+    pub async fn get_archive_html(&self, page: u32) -> String {
+        self.api_client
+            .get(format!("{}/issues?page={}", &self.address, page))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Send a get request for one of the feeds, e.g. `/feed.xml`.
+    pub async fn get_feed(&self, feed: &str, if_none_match: Option<&str>) -> reqwest::Response {
+        let mut request = self.api_client.get(format!("{}{}", &self.address, feed));
+        if let Some(etag) = if_none_match {
+            request = request.header("If-None-Match", etag);
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+
+    /// Send a get request to the public web view of an issue.
+    pub async fn get_web_view(&self, issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/issues/{}", &self.address, issue_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Return the html from the preview page of an issue
+    pub async fn get_preview_issue_html(&self, issue_id: Uuid) -> String {
+        self.api_client
+            .get(format!(
+                "{}/admin/issues/{}/preview",
+                &self.address, issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
     /// Get the confirmation links from the mock email.
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
@@ -233,10 +420,16 @@ impl TestApp {
         ConfirmationLinks { html, plain_text }
     }
 
+    /// Build a signed unsubscribe link for a subscriber.
+    pub fn get_unsubscribe_link(&self, subscriber_id: Uuid) -> reqwest::Url {
+        let link = build_unsubscribe_link(&self.address, subscriber_id, &self.hmac_secret);
+        reqwest::Url::parse(&link).unwrap()
+    }
+
     /// Send a get request to the login endpoint.
     pub async fn get_login_html(&self) -> String {
         self.api_client
-            .get(&format!("{}/login", &self.address))
+            .get(format!("{}/login", &self.address))
             .send()
             .await
             .expect("Failed to execute request")
@@ -248,7 +441,7 @@ impl TestApp {
     /// Send a get request to the admin dashboard endpoint.
     pub async fn get_publish_newsletter(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/newsletters", &self.address))
+            .get(format!("{}/admin/newsletters", &self.address))
             .send()
             .await
             .expect("Failed to execute request")
@@ -265,7 +458,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/password", &self.address))
+            .post(format!("{}/admin/password", &self.address))
             .form(body)
             .send()
             .await
@@ -278,7 +471,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             .form(body)
             .send()
             .await
@@ -288,7 +481,274 @@ impl TestApp {
     /// Send a post request to the logout endpoint.
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/admin/logout", self.address))
+            .post(format!("{}/admin/logout", self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Send a post request to requeue failed deliveries
+    pub async fn post_requeue_delivery_failures<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/deliveries/failures", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Send a post request to create a draft issue.
+    pub async fn post_create_issue<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/issues", &self.address))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Send a post request to update a draft issue.
+    pub async fn post_update_issue<Body>(&self, issue_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/issues/{}/edit", &self.address, issue_id))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Send a post request to delete a draft issue.
+    pub async fn post_delete_issue(&self, issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/issues/{}/delete",
+                &self.address, issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Send a post request to publish a draft issue.
+    pub async fn post_publish_issue<Body>(&self, issue_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/issues/{}/publish",
+                &self.address, issue_id
+            ))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Send a draft as a test email to the given addresses.
+    pub async fn post_send_test_email(
+        &self,
+        issue_id: Uuid,
+        recipients: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/issues/{}/test", &self.address, issue_id))
+            .form(&serde_json::json!({ "recipients": recipients }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Send a get request to the login lockouts page.
+    pub async fn get_login_locks_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/login-locks", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Lift a login lock.
+    pub async fn post_clear_login_lock(&self, key: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/login-locks/clear", &self.address))
+            .form(&serde_json::json!({ "key": key }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Send a get request to the change email page.
+    pub async fn get_change_email_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/email", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Send a post request to change the email address of the logged in user.
+    pub async fn post_change_email<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/email", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Ask for a password reset link.
+    pub async fn post_forgot_password(&self, username: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/login/forgot-password", &self.address))
+            .form(&serde_json::json!({ "username": username }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Send a post request to set a new password with a reset token.
+    pub async fn post_reset_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login/reset-password", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Send a get request to the second step of logging in.
+    pub async fn get_two_factor_form_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/login/two-factor", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Send a code for the second step of logging in.
+    pub async fn post_two_factor_code(&self, code: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/login/two-factor", &self.address))
+            .form(&serde_json::json!({ "code": code }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Send a get request to the two-factor settings page.
+    pub async fn get_two_factor_settings_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/two-factor", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Start setting up two-factor authentication.
+    pub async fn post_start_two_factor(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/two-factor/setup", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Confirm two-factor authentication with a code from the new secret.
+    pub async fn post_confirm_two_factor(&self, code: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/two-factor/confirm", &self.address))
+            .form(&serde_json::json!({ "code": code }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Turn off two-factor authentication.
+    pub async fn post_disable_two_factor<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/two-factor/disable", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Send a get request to the user management page.
+    pub async fn get_users_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/users", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Invite a user to the admin section.
+    pub async fn post_invite_user<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/users/invitations", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Disable, enable or delete a user.
+    pub async fn post_manage_user(&self, user_id: Uuid, action: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/users/{}/{}",
+                &self.address, user_id, action
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Send a post request to accept an invitation.
+    pub async fn post_accept_invitation<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/invitations/accept", &self.address))
+            .form(body)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -300,7 +760,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/newsletters", &self.address))
+            .post(format!("{}/admin/newsletters", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .form(&body)
             .send()
@@ -308,10 +768,40 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    /// Post an event to the Postmark webhook with the configured credentials
+    pub async fn post_postmark_webhook(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/webhooks/postmark", &self.address))
+            .basic_auth(
+                &self.configuration.webhooks.postmark_username,
+                Some(
+                    self.configuration
+                        .webhooks
+                        .postmark_password
+                        .expose_secret(),
+                ),
+            )
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Send an RFC 8058 one-click post request to an unsubscribe link.
+    pub async fn post_unsubscribe(&self, unsubscribe_link: reqwest::Url) -> reqwest::Response {
+        self.api_client
+            .post(unsubscribe_link)
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body("List-Unsubscribe=One-Click")
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     /// Send a post request to the subscriptions endpoint.
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -320,6 +810,118 @@ impl TestApp {
     }
 }
 
+/// Answers Postmark batch requests with one result per message, like the real API.
+///
+/// The first `failed_messages` messages of every batch are rejected with `error_code`.
+pub struct PostmarkBatchResponder {
+    pub failed_messages: usize,
+    pub error_code: i64,
+    pub delay: std::time::Duration,
+}
+
+impl PostmarkBatchResponder {
+    pub fn all_succeed() -> Self {
+        Self {
+            failed_messages: 0,
+            // "Service is down for maintenance", which is worth retrying
+            error_code: 100,
+            delay: std::time::Duration::ZERO,
+        }
+    }
+
+    pub fn failing_first(failed_messages: usize) -> Self {
+        Self {
+            failed_messages,
+            ..Self::all_succeed()
+        }
+    }
+
+    pub fn with_delay(self, delay: std::time::Duration) -> Self {
+        Self { delay, ..self }
+    }
+
+    pub fn with_error_code(self, error_code: i64) -> Self {
+        Self { error_code, ..self }
+    }
+}
+
+impl Respond for PostmarkBatchResponder {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let messages: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+        let results: Vec<_> = messages
+            .iter()
+            .enumerate()
+            .map(|(i, message)| {
+                if i < self.failed_messages {
+                    serde_json::json!({
+                        "ErrorCode": self.error_code,
+                        "Message": "Rejected by the test server",
+                        "To": message["To"],
+                    })
+                } else {
+                    serde_json::json!({
+                        "ErrorCode": 0,
+                        "Message": "OK",
+                        "MessageID": Uuid::new_v4(),
+                        "To": message["To"],
+                    })
+                }
+            })
+            .collect();
+        ResponseTemplate::new(200)
+            .set_body_json(results)
+            .set_delay(self.delay)
+    }
+}
+
+/// Use the public API of the application under test to create
+/// an unconfirmed subscriber.
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    let name: String = Name().fake();
+    let email: String = SafeEmail().fake();
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "name":name,
+        "email":email,
+    }))
+    .unwrap();
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // We now inspect the requests received by the mock Postmark server
+    // to retrieve the confirmation link and return it
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+
+    app.get_confirmation_links(email_request)
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    // We can reuse the other helper to create an unconfirmed subscriber
+    // then in this one follow the link to confirm it.
+    let confirmation_link = create_unconfirmed_subscriber(app).await;
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
 pub struct ConfirmationLinks {
     pub html: reqwest::Url,
     pub plain_text: reqwest::Url,
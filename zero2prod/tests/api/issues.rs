@@ -0,0 +1,446 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::{
+    helpers::{create_confirmed_subscriber, spawn_app, PostmarkBatchResponder, TestApp},
+    login::assert_is_redirect_to,
+};
+
+fn draft() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    })
+}
+
+/// Save a draft and return its id.
+async fn create_draft(app: &TestApp) -> Uuid {
+    let response = app.post_create_issue(&draft()).await;
+    assert_eq!(response.status().as_u16(), 303);
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+async fn issue_status(app: &TestApp, issue_id: Uuid) -> String {
+    sqlx::query!(
+        "SELECT status FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .status
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_issues() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_issues().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn saving_a_draft_does_not_send_it() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_create_issue(&draft()).await;
+
+    // Assert
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}/edit", issue_id));
+    assert_eq!(issue_status(&app, issue_id).await, "draft");
+    app.dispatch_all_pending_emails().await;
+
+    let html_page = app.get_issues_html().await;
+    assert!(html_page.contains("Newsletter title"));
+    assert!(html_page.contains("draft"));
+}
+
+#[tokio::test]
+async fn drafts_can_be_edited() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = create_draft(&app).await;
+
+    // Act
+    let response = app
+        .post_update_issue(
+            issue_id,
+            &serde_json::json!({
+                "title": "A better title",
+                "text_content": "Newsletter body as plain text",
+                "html_content": "<p>Newsletter body as HTML</p>",
+            }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}/edit", issue_id));
+    let html_page = app.get_edit_issue_html(issue_id).await;
+    assert!(html_page.contains("The draft has been saved."));
+    assert!(html_page.contains(r#"value="A better title""#));
+    assert!(html_page.contains("&lt;p&gt;Newsletter body as HTML&lt;/p&gt;"));
+}
+
+#[tokio::test]
+async fn the_preview_shows_both_bodies() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = create_draft(&app).await;
+
+    // Act
+    let html_page = app.get_preview_issue_html(issue_id).await;
+
+    // Assert
+    assert!(html_page.contains(r#"srcdoc="&lt;p&gt;Newsletter body as HTML&lt;/p&gt;""#));
+    assert!(html_page.contains("<pre>Newsletter body as plain text</pre>"));
+    assert!(html_page.contains(&format!("/admin/issues/{}/publish", issue_id)));
+}
+
+#[tokio::test]
+async fn publishing_a_draft_delivers_it_to_confirmed_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let issue_id = create_draft(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::all_succeed())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_publish_issue(
+            issue_id,
+            &serde_json::json!({ "idempotency_key": Uuid::new_v4().to_string() }),
+        )
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/issues");
+    let html_page = app.get_issues_html().await;
+    assert!(html_page.contains("The newsletter issue has been accepted"));
+    assert_eq!(issue_status(&app, issue_id).await, "published");
+}
+
+#[tokio::test]
+async fn incomplete_drafts_can_not_be_published() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let response = app
+        .post_create_issue(&serde_json::json!({ "title": "Newsletter title" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_publish_issue(
+            issue_id,
+            &serde_json::json!({ "idempotency_key": Uuid::new_v4().to_string() }),
+        )
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}/preview", issue_id));
+    let html_page = app.get_preview_issue_html(issue_id).await;
+    assert!(html_page.contains("The issue needs both an html and a plain text body."));
+    assert_eq!(issue_status(&app, issue_id).await, "draft");
+}
+
+#[tokio::test]
+async fn publishing_a_draft_is_idempotent() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let issue_id = create_draft(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::all_succeed())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let body = serde_json::json!({ "idempotency_key": Uuid::new_v4().to_string() });
+    let response = app.post_publish_issue(issue_id, &body).await;
+    assert_is_redirect_to(&response, "/admin/issues");
+    let response = app.post_publish_issue(issue_id, &body).await;
+    assert_is_redirect_to(&response, "/admin/issues");
+
+    // Assert
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email **once**
+}
+
+#[tokio::test]
+async fn published_issues_can_not_be_edited_published_again_or_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = create_draft(&app).await;
+    app.post_publish_issue(
+        issue_id,
+        &serde_json::json!({ "idempotency_key": Uuid::new_v4().to_string() }),
+    )
+    .await;
+
+    // Act - Part 1 - Edit
+    let response = app
+        .post_update_issue(
+            issue_id,
+            &serde_json::json!({
+                "title": "A better title",
+                "text_content": "",
+                "html_content": "",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/issues");
+    let html_page = app.get_issues_html().await;
+    assert!(html_page.contains("A published issue can no longer be edited."));
+
+    // Act - Part 2 - Publish again
+    app.post_publish_issue(
+        issue_id,
+        &serde_json::json!({ "idempotency_key": Uuid::new_v4().to_string() }),
+    )
+    .await;
+    let html_page = app.get_issues_html().await;
+    assert!(html_page.contains("has already been published"));
+
+    // Act - Part 3 - Delete
+    app.post_delete_issue(issue_id).await;
+    let html_page = app.get_issues_html().await;
+    assert!(html_page.contains("Only draft issues can be deleted."));
+
+    // Assert
+    let issue = sqlx::query!("SELECT title, status FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.title, "Newsletter title");
+    assert_eq!(issue.status, "published");
+}
+
+#[tokio::test]
+async fn drafts_can_be_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = create_draft(&app).await;
+
+    // Act
+    let response = app.post_delete_issue(issue_id).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/issues");
+    let issues = sqlx::query!("SELECT COUNT(*) AS count FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, Some(0));
+}
+
+#[tokio::test]
+async fn test_emails_only_go_to_the_chosen_addresses() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let issue_id = create_draft(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email/batch"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_send_test_email(issue_id, "editor@example.com, reviewer@example.com")
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}/preview", issue_id));
+    let html_page = app.get_preview_issue_html(issue_id).await;
+    assert!(html_page
+        .contains("A test email has been sent to editor@example.com, reviewer@example.com."));
+    assert_eq!(issue_status(&app, issue_id).await, "draft");
+
+    let test_emails: Vec<serde_json::Value> = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| serde_json::from_slice(&request.body).unwrap())
+        .filter(|body: &serde_json::Value| body["Subject"] == "[TEST] Newsletter title")
+        .collect();
+    let recipients: Vec<_> = test_emails.iter().map(|body| &body["To"]).collect();
+    assert_eq!(recipients, ["editor@example.com", "reviewer@example.com"]);
+    assert_eq!(test_emails[0]["HtmlBody"], "<p>Newsletter body as HTML</p>");
+    let deliveries = sqlx::query!("SELECT COUNT(*) AS count FROM issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.count, Some(0));
+}
+
+#[tokio::test]
+async fn test_emails_need_valid_addresses() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = create_draft(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    for recipients in ["", "editor@example.com, not-an-email"] {
+        // Act
+        let response = app.post_send_test_email(issue_id, recipients).await;
+
+        // Assert
+        assert_is_redirect_to(&response, &format!("/admin/issues/{}/preview", issue_id));
+        let html_page = app.get_preview_issue_html(issue_id).await;
+        assert!(!html_page.contains("A test email has been sent"));
+        assert!(html_page.contains("<strong>Error</strong>"));
+    }
+}
+
+#[tokio::test]
+async fn flash_messages_escape_what_the_user_typed() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = create_draft(&app).await;
+
+    // Act
+    app.post_send_test_email(issue_id, "<script>alert(1)</script>")
+        .await;
+
+    // Assert
+    let html_page = app.get_preview_issue_html(issue_id).await;
+    assert!(!html_page.contains("<script>alert(1)</script>"));
+    assert!(html_page.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+}
+
+#[tokio::test]
+async fn drafts_can_be_written_in_markdown() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_create_issue(&serde_json::json!({
+        "title": "Newsletter title",
+        "markdown_content": "Hello *there*",
+        "text_content": "",
+        "html_content": "",
+    }))
+    .await;
+
+    // Assert
+    let issue = sqlx::query!(
+        "SELECT newsletter_issue_id, text_content, html_content FROM newsletter_issues"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(issue.text_content, "Hello there");
+    assert!(issue.html_content.contains("<p>Hello <em>there</em></p>"));
+    // Generated bodies are not shown as hand-written overrides
+    let html_page = app.get_edit_issue_html(issue.newsletter_issue_id).await;
+    assert!(html_page.contains(r#"name="markdown_content">Hello *there*</textarea>"#));
+    assert!(html_page.contains(r#"name="text_content"></textarea>"#));
+    assert!(html_page.contains(r#"name="html_content"></textarea>"#));
+}
+
+#[tokio::test]
+async fn generated_bodies_are_not_mistaken_for_overrides_after_a_layout_change() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_create_issue(&serde_json::json!({
+        "title": "Newsletter title",
+        "markdown_content": "Hello *there*",
+        "text_content": "Hand-written text",
+        "html_content": "",
+    }))
+    .await;
+    // The html was generated with a layout that has changed since
+    let issue_id = sqlx::query!(
+        "UPDATE newsletter_issues SET html_content = '<p>Old layout</p>' \
+        RETURNING newsletter_issue_id"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .newsletter_issue_id;
+
+    // Act
+    let html_page = app.get_edit_issue_html(issue_id).await;
+
+    // Assert
+    assert!(html_page.contains(r#"name="text_content">Hand-written text</textarea>"#));
+    assert!(html_page.contains(r#"name="html_content"></textarea>"#));
+}
@@ -1,3 +1,5 @@
+use argon2::{password_hash::SaltString, Algorithm, Argon2, Params, PasswordHasher, Version};
+
 use crate::helpers::spawn_app;
 
 pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
@@ -58,3 +60,82 @@ async fn redirect_to_admin_dashboard_after_login_success() {
 
     // Assert - Part 2
 }
+
+#[tokio::test]
+async fn outdated_password_hashes_are_upgraded_after_logging_in() {
+    // Arrange
+    let app = spawn_app().await;
+    let weak_hash = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(4096, 1, 1, None).unwrap(),
+    )
+    .hash_password(
+        app.test_user.password.as_bytes(),
+        &SaltString::generate(&mut rand::thread_rng()),
+    )
+    .unwrap()
+    .to_string();
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1 WHERE user_id = $2",
+        weak_hash,
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Assert - The hash is upgraded in the background
+    let mut password_hash = weak_hash.clone();
+    for _ in 0..50 {
+        password_hash = sqlx::query!(
+            "SELECT password_hash FROM users WHERE user_id = $1",
+            app.test_user.user_id
+        )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .password_hash;
+        if password_hash != weak_hash {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    let hashing = &app.configuration.password_hashing;
+    assert!(password_hash.contains(&format!(
+        "m={},t={},p={}",
+        hashing.memory_kib, hashing.iterations, hashing.parallelism
+    )));
+    app.post_logout().await;
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn current_password_hashes_are_left_alone() {
+    // Arrange
+    let app = spawn_app().await;
+    let get_hash = || async {
+        sqlx::query!(
+            "SELECT password_hash FROM users WHERE user_id = $1",
+            app.test_user.user_id
+        )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .password_hash
+    };
+    let stored_hash = get_hash().await;
+
+    // Act
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+
+    // Assert
+    assert_eq!(get_hash().await, stored_hash);
+}
@@ -0,0 +1,175 @@
+use uuid::Uuid;
+
+use crate::{
+    helpers::{spawn_app, TestApp, TestUser},
+    login::assert_is_redirect_to,
+};
+
+const LOCKED_OUT: &str = "Too many failed login attempts. Try again in 15 minutes.";
+
+async fn fail_login(app: &TestApp, username: &str) {
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": username,
+            "password": "not-the-password",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_username_is_locked_after_too_many_failed_logins() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..5 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+
+    // Act - Even the right password is refused now
+    let response = app.test_user.login(&app).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains(LOCKED_OUT));
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn an_address_is_locked_after_too_many_failed_logins_whatever_the_username() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..10 {
+        fail_login(&app, &Uuid::new_v4().to_string()).await;
+    }
+
+    // Act
+    let response = app.test_user.login(&app).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains(LOCKED_OUT));
+}
+
+#[tokio::test]
+async fn concurrent_guesses_can_not_get_past_the_limit() {
+    // Arrange
+    let app = spawn_app().await;
+    // Every guess comes from its own address, so only the username limit applies
+    let mut guesses = tokio::task::JoinSet::new();
+    for _ in 0..8 {
+        let mut default_headers = reqwest::header::HeaderMap::new();
+        let client_ip = format!("10.1.{}.{}", rand::random::<u8>(), rand::random::<u8>());
+        default_headers.insert("x-forwarded-for", client_ip.parse().unwrap());
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .cookie_store(true)
+            .default_headers(default_headers)
+            .build()
+            .unwrap();
+        let address = app.address.clone();
+        let username = app.test_user.username.clone();
+        guesses.spawn(async move {
+            client
+                .post(format!("{}/login", address))
+                .form(&serde_json::json!({
+                    "username": username,
+                    "password": "not-the-password",
+                }))
+                .send()
+                .await
+                .unwrap();
+            client
+                .get(format!("{}/login", address))
+                .send()
+                .await
+                .unwrap()
+                .text()
+                .await
+                .unwrap()
+        });
+    }
+
+    // Act
+    let mut html_pages = Vec::new();
+    while let Some(html_page) = guesses.join_next().await {
+        html_pages.push(html_page.unwrap());
+    }
+
+    // Assert
+    let locked_out = html_pages
+        .iter()
+        .filter(|html_page| html_page.contains(LOCKED_OUT))
+        .count();
+    assert_eq!(locked_out, 3);
+}
+
+#[tokio::test]
+async fn a_successful_login_forgets_the_failures_of_the_username() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..4 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    app.post_logout().await;
+
+    // Act
+    for _ in 0..4 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+    let response = app.test_user.login(&app).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn an_owner_can_lift_a_lock() {
+    // Arrange
+    let app = spawn_app().await;
+    let viewer = TestUser::generate_with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    for _ in 0..5 {
+        fail_login(&app, &viewer.username).await;
+    }
+    app.test_user.login(&app).await;
+    let key = format!("login_failures:username:{}", viewer.username);
+    let html_page = app.get_login_locks_html().await;
+    assert!(html_page.contains(&format!("<td>{}</td><td>5</td><td>locked</td>", key)));
+
+    // Act
+    let response = app.post_clear_login_lock(&key).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/login-locks");
+    let html_page = app.get_login_locks_html().await;
+    assert!(html_page.contains(&format!("The failed logins of {} were cleared.", key)));
+    assert!(!html_page.contains(&format!("<td>{}</td>", key)));
+    app.post_logout().await;
+    let response = viewer.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn only_owners_can_see_the_login_locks() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    editor.login(&app).await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/admin/login-locks", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
@@ -1,8 +1,22 @@
 mod admin_dashboard;
+mod archive;
 mod change_password;
+mod delivery_failures;
+mod delivery_status;
+mod feeds;
 mod health_check;
 mod helpers;
+mod issues;
 mod login;
+mod login_throttle;
 mod newsletters;
+mod password_reset;
+mod personalization;
+mod scheduled_newsletters;
+mod shutdown;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
+mod two_factor;
+mod users;
+mod webhooks;
@@ -1,74 +1,22 @@
 use std::time::Duration;
 
+use sqlx::postgres::PgListener;
 use wiremock::{
     matchers::{any, method, path},
     Mock, ResponseTemplate,
 };
-use zero2prod::routes::newsletters::PUBLISH_SUCCESS_INFO_MESSAGE;
-
-use crate::{helpers::spawn_app, login::assert_is_redirect_to};
-
-mod newsletter_helpers {
-    use fake::{
-        faker::{internet::en::SafeEmail, name::en::Name},
-        Fake,
-    };
-    use wiremock::{
-        matchers::{method, path},
-        Mock, ResponseTemplate,
-    };
-
-    use crate::helpers::{ConfirmationLinks, TestApp};
-    /// Use the public API of the applixation under test to create
-    /// and unconfirmed subscriber.
-    pub(crate) async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
-        let name: String = Name().fake();
-        let email: String = SafeEmail().fake();
-        let body = serde_urlencoded::to_string(serde_json::json!({
-            "name":name,
-            "email":email,
-        }))
-        .unwrap();
-
-        let _mock_guard = Mock::given(path("/email"))
-            .and(method("POST"))
-            .respond_with(ResponseTemplate::new(200))
-            .named("Create unconfirmed subscriber")
-            .expect(1)
-            .mount_as_scoped(&app.email_server)
-            .await;
-
-        app.post_subscriptions(body)
-            .await
-            .error_for_status()
-            .unwrap();
-
-        // We now inspect the requests received by the mock Postmark server
-        // to retrieve the confirmation link and return it
-        let email_request = &app
-            .email_server
-            .received_requests()
-            .await
-            .unwrap()
-            .pop()
-            .unwrap();
-
-        app.get_confirmation_links(email_request)
-    }
-
-    pub(crate) async fn create_confirmed_subscriber(app: &TestApp) {
-        // We can reuse the other helper to create an unconfirmed subscriber
-        // then in this one follow the link to confirm it.
-        let confirmation_link = create_unconfirmed_subscriber(app).await;
-        reqwest::get(confirmation_link.html)
-            .await
-            .unwrap()
-            .error_for_status()
-            .unwrap();
-    }
-}
+use zero2prod::{
+    issue_delivery_worker::DELIVERY_QUEUE_CHANNEL,
+    routes::newsletters::PUBLISH_SUCCESS_INFO_MESSAGE,
+};
 
-use newsletter_helpers::*;
+use crate::{
+    helpers::{
+        create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app,
+        PostmarkBatchResponder,
+    },
+    login::assert_is_redirect_to,
+};
 
 #[tokio::test]
 async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
@@ -113,9 +61,9 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     let app = spawn_app().await;
     create_confirmed_subscriber(&app).await;
 
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(PostmarkBatchResponder::all_succeed())
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -148,27 +96,35 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
 async fn newsletters_fails_for_invalid_data() {
     // Arrange
     let app = spawn_app().await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let missing_body = "fill in both the html and the plain text body";
     let test_cases = vec![
         (
             serde_json::json!({
                 "text_content": "Newsletter body as plain text",
                 "html_content": "<p>Newsletter body as HTML</p>",
+                "idempotency_key": idempotency_key,
             }),
             "missing title",
+            "Part of the form is not filled out",
         ),
         (
             serde_json::json!({
                 "title": "Newsletter title",
                 "html_content": "<p>Newsletter body as HTML</p>",
+                "idempotency_key": idempotency_key,
             }),
             "missing plaintext content",
+            missing_body,
         ),
         (
             serde_json::json!({
                 "title": "Newsletter title",
                 "text_content": "Newsletter body as plain text",
+                "idempotency_key": idempotency_key,
             }),
             "missing HTML content",
+            missing_body,
         ),
     ];
 
@@ -176,7 +132,7 @@ async fn newsletters_fails_for_invalid_data() {
     let response = app.test_user.login(&app).await;
     assert_is_redirect_to(&response, "/admin/dashboard");
 
-    for (invalid_body, error_message) in test_cases {
+    for (invalid_body, error_message, flash_message) in test_cases {
         // Act - Part 2 - Post the newsletter cases
         let _ = app
             .post_publish_newsletter(&invalid_body)
@@ -188,7 +144,7 @@ async fn newsletters_fails_for_invalid_data() {
         let html = app.get_publish_newsletter_html().await;
 
         assert!(
-            html.contains("Part of the form is not filled out"),
+            html.contains(flash_message),
             "The API did not fail when the payload was {}.",
             error_message
         );
@@ -221,9 +177,9 @@ async fn newsletter_create_is_idempotent() {
     create_confirmed_subscriber(&app).await;
     let response = app.test_user.login(&app).await;
     assert_is_redirect_to(&response, "/admin/dashboard");
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(PostmarkBatchResponder::all_succeed())
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -261,10 +217,10 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     let app = spawn_app().await;
     create_confirmed_subscriber(&app).await;
     app.test_user.login(&app).await;
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
         // Setting a long delay to ensure the second request arrives before the first one completes
-        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+        .respond_with(PostmarkBatchResponder::all_succeed().with_delay(Duration::from_secs(2)))
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -297,7 +253,7 @@ async fn transient_errors_get_retried() {
     let app = spawn_app().await;
     create_confirmed_subscriber(&app).await;
 
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(500))
         .up_to_n_times(1)
@@ -305,9 +261,9 @@ async fn transient_errors_get_retried() {
         .mount(&app.email_server)
         .await;
 
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(PostmarkBatchResponder::all_succeed())
         .up_to_n_times(1)
         .expect(1)
         .mount(&app.email_server)
@@ -332,6 +288,132 @@ async fn transient_errors_get_retried() {
     // and the second time should have been successful.
 }
 
+#[tokio::test]
+async fn only_the_rejected_messages_of_a_batch_get_retried() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    // Both subscribers go out in one batch, and Postmark rejects one of them
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::failing_first(1))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::all_succeed())
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let batches = app.email_server.received_requests().await.unwrap();
+    let batches: Vec<Vec<serde_json::Value>> = batches
+        .iter()
+        .filter(|r| r.url.path() == "/email/batch")
+        .map(|r| serde_json::from_slice(&r.body).unwrap())
+        .collect();
+    assert_eq!(batches.len(), 2);
+    assert_eq!(batches[0].len(), 2);
+    assert_eq!(batches[1].len(), 1);
+    // The retry goes to the subscriber whose message was rejected
+    assert_eq!(batches[1][0]["To"], batches[0][0]["To"]);
+    let remaining = sqlx::query!("SELECT COUNT(*) AS count FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining.count, Some(0));
+}
+
+#[tokio::test]
+async fn publishing_a_newsletter_wakes_up_the_delivery_workers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let mut listener = PgListener::connect_with(&app.db_pool).await.unwrap();
+    listener.listen(DELIVERY_QUEUE_CHANNEL).await.unwrap();
+
+    // Act
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    }))
+    .await;
+
+    // Assert
+    let notification = tokio::time::timeout(Duration::from_secs(5), listener.recv())
+        .await
+        .expect("No notification was sent for the new delivery tasks")
+        .unwrap();
+    assert_eq!(notification.channel(), DELIVERY_QUEUE_CHANNEL);
+}
+
+#[tokio::test]
+async fn concurrent_workers_deliver_each_email_exactly_once() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..3 {
+        create_confirmed_subscriber(&app).await;
+    }
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::all_succeed())
+        .mount(&app.email_server)
+        .await;
+
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    }))
+    .await;
+
+    // Act - Drain the queue with two workers at once
+    tokio::join!(
+        app.dispatch_all_pending_emails(),
+        app.dispatch_all_pending_emails()
+    );
+
+    // Assert
+    let mut recipients: Vec<String> = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|r| r.url.path() == "/email/batch")
+        .flat_map(|r| serde_json::from_slice::<Vec<serde_json::Value>>(&r.body).unwrap())
+        .map(|message| message["To"].as_str().unwrap().to_owned())
+        .collect();
+    assert_eq!(recipients.len(), 3);
+    recipients.sort();
+    recipients.dedup();
+    assert_eq!(recipients.len(), 3);
+}
+
 #[tokio::test]
 async fn old_idempotency_entries_are_cleaned_up() {
     // Arrange
@@ -400,3 +482,71 @@ async fn old_idempotency_entries_are_cleaned_up() {
 
     assert_eq!(1, count.value.unwrap());
 }
+
+#[tokio::test]
+async fn newsletters_written_in_markdown_are_delivered_as_html_and_text() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::all_succeed())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "markdown_content": "# Hello\n\nRead [the blog](https://example.com).\n\n<script>alert('hi')</script>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let requests = app.email_server.received_requests().await.unwrap();
+    let request = requests
+        .iter()
+        .find(|r| r.url.path() == "/email/batch")
+        .unwrap();
+    let messages: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+    let html_body = messages[0]["HtmlBody"].as_str().unwrap();
+    // The configured layout wraps the issue and fills in its own placeholders
+    assert!(html_body.starts_with("<!DOCTYPE html>"));
+    assert!(html_body.contains("<h1>Hello</h1>"));
+    assert!(html_body.contains("/subscriptions/unsubscribe?subscriber_id="));
+    assert!(!html_body.contains("<script>"));
+    assert_eq!(
+        messages[0]["TextBody"],
+        "Hello\n=====\n\nRead the blog (https://example.com)."
+    );
+}
+
+#[tokio::test]
+async fn hand_written_bodies_override_the_markdown() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "markdown_content": "Hello *there*",
+        "text_content": "Hand-written text",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    }))
+    .await;
+
+    // Assert
+    let issue = sqlx::query!("SELECT text_content, html_content FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.text_content, "Hand-written text");
+    assert!(issue.html_content.contains("<p>Hello <em>there</em></p>"));
+}
@@ -0,0 +1,298 @@
+use std::time::Duration;
+
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::{
+    helpers::{spawn_app, TestApp},
+    login::assert_is_redirect_to,
+};
+
+const RESET_REQUESTED: &str =
+    "If that account exists and has an email address, a link to reset its password has been sent to it.";
+
+async fn give_test_user_an_email(app: &TestApp) {
+    sqlx::query!(
+        "UPDATE users SET email = 'admin@example.com' WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+/// Wait for the reset email, which is sent in the background, and return its token.
+async fn reset_token(app: &TestApp) -> String {
+    for _ in 0..50 {
+        if let Some(email_request) = app.email_server.received_requests().await.unwrap().first() {
+            let link = app.get_confirmation_links(email_request).html;
+            return link
+                .query_pairs()
+                .find(|(key, _)| key == "reset_token")
+                .unwrap()
+                .1
+                .into_owned();
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("No password reset email was sent.");
+}
+
+fn new_password(reset_token: &str, password: &str) -> serde_json::Value {
+    serde_json::json!({
+        "reset_token": reset_token,
+        "new_password": password,
+        "new_password_check": password,
+    })
+}
+
+#[tokio::test]
+async fn unknown_and_known_usernames_get_the_same_answer() {
+    // Arrange
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    for username in [Uuid::new_v4().to_string(), app.test_user.username.clone()] {
+        // Act
+        let response = app.post_forgot_password(&username).await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/login");
+        let html_page = app.get_login_html().await;
+        assert!(html_page.contains(RESET_REQUESTED));
+    }
+    reset_token(&app).await;
+}
+
+#[tokio::test]
+async fn users_without_an_email_can_add_one_and_then_reset_their_password() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.test_user.login(&app).await;
+    let html_page = app.get_change_email_html().await;
+    assert!(html_page.contains("You have no email address yet"));
+
+    // Act - Part 1 - A wrong password changes nothing
+    let response = app
+        .post_change_email(&serde_json::json!({
+            "email": "admin@example.com",
+            "current_password": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/email");
+    let html_page = app.get_change_email_html().await;
+    assert!(html_page.contains("The current password is incorrect."));
+    assert!(html_page.contains("You have no email address yet"));
+
+    // Act - Part 2 - Set the email address
+    let response = app
+        .post_change_email(&serde_json::json!({
+            "email": "admin@example.com",
+            "current_password": &app.test_user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/email");
+    let html_page = app.get_change_email_html().await;
+    assert!(html_page.contains("Your email address has been changed."));
+    assert!(html_page.contains("admin@example.com"));
+
+    // Act - Part 3 - Ask for a reset link
+    app.post_forgot_password(&app.test_user.username).await;
+
+    // Assert
+    reset_token(&app).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "admin@example.com");
+}
+
+#[tokio::test]
+async fn a_reset_link_sets_a_new_password_once() {
+    // Arrange
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_forgot_password(&app.test_user.username).await;
+    let reset_token = reset_token(&app).await;
+
+    // Act - Part 1 - Reset the password
+    let form = app
+        .api_client
+        .get(format!(
+            "{}/login/reset-password?reset_token={}",
+            &app.address, reset_token
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(form.status().as_u16(), 200);
+    let response = app
+        .post_reset_password(&new_password(&reset_token, "a brand new password"))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 2 - Log in with the new password
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": "a brand new password",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Act - Part 3 - Use the link again
+    let response = app
+        .post_reset_password(&new_password(&reset_token, "yet another password"))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn resetting_the_password_ends_every_session() {
+    // Arrange
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.test_user.login(&app).await;
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act - Reset from another browser
+    let other_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    other_client
+        .post(format!("{}/login/forgot-password", &app.address))
+        .form(&serde_json::json!({ "username": &app.test_user.username }))
+        .send()
+        .await
+        .unwrap();
+    let reset_token = reset_token(&app).await;
+    let response = other_client
+        .post(format!("{}/login/reset-password", &app.address))
+        .form(&new_password(&reset_token, "a brand new password"))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+
+    // Assert
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_new_password_must_be_between_12_and_128_characters() {
+    // Arrange
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_forgot_password(&app.test_user.username).await;
+    let reset_token = reset_token(&app).await;
+
+    for password in ["too short".to_string(), "a".repeat(129)] {
+        // Act
+        let response = app
+            .post_reset_password(&new_password(&reset_token, &password))
+            .await;
+
+        // Assert
+        assert_is_redirect_to(
+            &response,
+            &format!("/login/reset-password?reset_token={}", reset_token),
+        );
+    }
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn reset_requests_for_a_username_are_rate_limited() {
+    // Arrange
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+    let max_requests = app.configuration.login_throttle.max_failures_per_username;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    for _ in 0..max_requests + 2 {
+        let response = app.post_forgot_password(&app.test_user.username).await;
+        assert_is_redirect_to(&response, "/login");
+    }
+
+    // Assert - the answer never changes, but only the allowed emails go out
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains(RESET_REQUESTED));
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let sent = app.email_server.received_requests().await.unwrap().len();
+    assert_eq!(sent, max_requests as usize);
+}
+
+#[tokio::test]
+async fn a_password_reset_lifts_the_lock_on_the_username() {
+    // Arrange
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    for _ in 0..app.configuration.login_throttle.max_failures_per_username {
+        app.post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": "not-the-password",
+        }))
+        .await;
+    }
+    app.post_forgot_password(&app.test_user.username).await;
+    let reset_token = reset_token(&app).await;
+
+    // Act
+    app.post_reset_password(&new_password(&reset_token, "a brand new password"))
+        .await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": "a brand new password",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
@@ -0,0 +1,176 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::{
+    helpers::{create_confirmed_subscriber, spawn_app, PostmarkBatchResponder, TestApp},
+    login::assert_is_redirect_to,
+};
+
+fn personalized_newsletter() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Hi {{ name | reader }}! Unsubscribe: {{ unsubscribe_url }}",
+        "html_content": r#"<p>Hi {{ name | reader }}!</p><a href="{{ web_view_url }}">View online</a>"#,
+        "idempotency_key": Uuid::new_v4().to_string(),
+    })
+}
+
+async fn published_issue_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+#[tokio::test]
+async fn placeholders_are_filled_in_for_every_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscriptions SET name = 'Ursula <Le Guin>'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::all_succeed())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_publish_newsletter(&personalized_newsletter())
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let issue_id = published_issue_id(&app).await;
+    let requests = app.email_server.received_requests().await.unwrap();
+    let request = requests
+        .iter()
+        .find(|r| r.url.path() == "/email/batch")
+        .unwrap();
+    let messages: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+    let text_body = messages[0]["TextBody"].as_str().unwrap();
+    assert!(text_body.starts_with("Hi Ursula <Le Guin>! Unsubscribe: "));
+    assert!(text_body.contains("/subscriptions/unsubscribe?subscriber_id="));
+    assert_eq!(
+        messages[0]["HtmlBody"],
+        format!(
+            r#"<p>Hi Ursula &lt;Le Guin&gt;!</p><a href="{}/issues/{}">View online</a>"#,
+            app.base_url, issue_id
+        )
+    );
+}
+
+#[tokio::test]
+async fn newsletters_with_unknown_placeholders_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Hi {{ first_name }}!",
+            "html_content": "<p>Hi!</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("is not a known placeholder"));
+    let issues = sqlx::query!("SELECT COUNT(*) AS count FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, Some(0));
+}
+
+#[tokio::test]
+async fn drafts_with_unknown_placeholders_can_not_be_published() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_create_issue(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Hi!",
+        "html_content": "<p>Hi {{ name</p>",
+    }))
+    .await;
+    let issue_id = published_issue_id(&app).await;
+
+    // Act
+    let response = app
+        .post_publish_issue(
+            issue_id,
+            &serde_json::json!({ "idempotency_key": Uuid::new_v4().to_string() }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}/preview", issue_id));
+    let html_page = app.get_preview_issue_html(issue_id).await;
+    assert!(html_page.contains("never closed"));
+    let status = sqlx::query!("SELECT status FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status;
+    assert_eq!(status, "draft");
+}
+
+#[tokio::test]
+async fn the_web_view_uses_fallbacks_for_subscriber_details() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter(&personalized_newsletter())
+        .await;
+    let issue_id = published_issue_id(&app).await;
+
+    // Act
+    let response = app.get_web_view(issue_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("&lt;p&gt;Hi reader!&lt;/p&gt;"));
+    assert!(html_page.contains(&format!("{}/issues/{}", app.base_url, issue_id)));
+}
+
+#[tokio::test]
+async fn drafts_have_no_web_view() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_create_issue(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Hi!",
+        "html_content": "<p>Hi!</p>",
+    }))
+    .await;
+    let issue_id = published_issue_id(&app).await;
+
+    // Act
+    let response = app.get_web_view(issue_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,245 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::{
+    helpers::{create_confirmed_subscriber, spawn_app, PostmarkBatchResponder, TestApp},
+    login::assert_is_redirect_to,
+};
+
+fn scheduled_newsletter(send_at: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "send_at": send_at,
+    })
+}
+
+fn tomorrow() -> String {
+    (chrono::Utc::now() + chrono::Duration::days(1)).to_rfc3339()
+}
+
+/// Pretend the clock has moved past the send time of every scheduled issue.
+async fn make_scheduled_issues_due(app: &TestApp) {
+    sqlx::query!(
+        "UPDATE newsletter_issues SET scheduled_for = now() - interval '1 minute' WHERE status = 'scheduled'"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+async fn queued_deliveries(app: &TestApp) -> Option<i64> {
+    sqlx::query!("SELECT COUNT(*) AS count FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn scheduled_newsletters_are_not_delivered_before_their_send_time() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&scheduled_newsletter(&tomorrow()))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert_eq!(app.publish_due_issues().await, 0);
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("The newsletter issue has been scheduled for"));
+    let issue = sqlx::query!("SELECT status, published_at FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "scheduled");
+    assert!(issue.published_at.is_none());
+    assert_eq!(queued_deliveries(&app).await, Some(0));
+}
+
+#[tokio::test]
+async fn scheduled_newsletters_are_delivered_once_due() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::all_succeed())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_publish_newsletter(&scheduled_newsletter(&tomorrow()))
+        .await;
+    make_scheduled_issues_due(&app).await;
+
+    // Act
+    let published = app.publish_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(published, 1);
+    let issue = sqlx::query!("SELECT status, published_at FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "published");
+    assert!(issue.published_at.is_some());
+    // A second pass finds nothing left to publish
+    assert_eq!(app.publish_due_issues().await, 0);
+    // Mock verifies on Drop that we have sent the newsletter email **once**
+}
+
+#[tokio::test]
+async fn concurrent_scheduler_runs_publish_a_due_issue_only_once() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter(&scheduled_newsletter(&tomorrow()))
+        .await;
+    make_scheduled_issues_due(&app).await;
+
+    // Act
+    let (first, second) = tokio::join!(app.publish_due_issues(), app.publish_due_issues());
+
+    // Assert
+    assert_eq!(first + second, 1);
+    assert_eq!(queued_deliveries(&app).await, Some(1));
+}
+
+#[tokio::test]
+async fn invalid_send_times_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let yesterday = (chrono::Utc::now() - chrono::Duration::days(1)).to_rfc3339();
+    let test_cases = vec![
+        ("2030-10-30T09:00:00", "must include a time zone"),
+        ("next tuesday", "must include a time zone"),
+        (yesterday.as_str(), "must be in the future"),
+    ];
+
+    for (send_at, error_message) in test_cases {
+        // Act
+        let response = app
+            .post_publish_newsletter(&scheduled_newsletter(send_at))
+            .await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/admin/newsletters");
+        let html_page = app.get_publish_newsletter_html().await;
+        assert!(
+            html_page.contains(error_message),
+            "The send time {} was not rejected with '{}'.",
+            send_at,
+            error_message
+        );
+    }
+    let issues = sqlx::query!("SELECT COUNT(*) AS count FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, Some(0));
+}
+
+#[tokio::test]
+async fn scheduled_issues_can_not_be_saved_with_unknown_placeholders() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter(&scheduled_newsletter(&tomorrow()))
+        .await;
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+
+    // Act
+    let response = app
+        .post_update_issue(
+            issue_id,
+            &serde_json::json!({
+                "title": "Newsletter title",
+                "text_content": "Hi {{ nmae }}",
+                "html_content": "<p>Hi</p>",
+            }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}/edit", issue_id));
+    let html_page = app.get_edit_issue_html(issue_id).await;
+    assert!(html_page.contains("is not a known placeholder"));
+    let issue = sqlx::query!("SELECT text_content FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.text_content, "Newsletter body as plain text");
+}
+
+#[tokio::test]
+async fn due_issues_that_can_not_be_published_stay_scheduled() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::all_succeed())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_publish_newsletter(&scheduled_newsletter(&tomorrow()))
+        .await;
+    app.post_publish_newsletter(&scheduled_newsletter(&tomorrow()))
+        .await;
+    // Saved before placeholders were checked on every edit
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues SET html_content = '<p>Hi {{ name</p>'
+        WHERE newsletter_issue_id = (SELECT newsletter_issue_id FROM newsletter_issues LIMIT 1)
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    make_scheduled_issues_due(&app).await;
+
+    // Act
+    let published = app.publish_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(published, 1);
+    let statuses: Vec<_> = sqlx::query!("SELECT status FROM newsletter_issues ORDER BY status")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.status)
+        .collect();
+    assert_eq!(statuses, ["published", "scheduled"]);
+}
@@ -0,0 +1,212 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use wiremock::{
+    matchers::{method, path},
+    Mock,
+};
+use zero2prod::{
+    domain::SubscriberEmail,
+    email_client::{EmailError, EmailHeader, EmailSender, SentEmail},
+    issue_delivery_worker::{self, try_execute_task},
+    shutdown::{self, ShutdownTrigger},
+    startup::{ApplicationBaseUrl, HmacSecret},
+};
+
+use crate::helpers::{create_confirmed_subscriber, spawn_app, PostmarkBatchResponder};
+
+/// A transport without a batch API that requests a shutdown while sending its first email.
+#[derive(Debug)]
+struct ShutdownOnFirstEmail {
+    trigger: Mutex<Option<ShutdownTrigger>>,
+    sent: AtomicUsize,
+}
+
+#[async_trait::async_trait]
+impl EmailSender for ShutdownOnFirstEmail {
+    async fn send_email_with_headers(
+        &self,
+        _recipient: &SubscriberEmail,
+        _subject: &str,
+        _html_content: &str,
+        _text_content: &str,
+        _headers: &[EmailHeader],
+    ) -> Result<SentEmail, EmailError> {
+        if let Some(trigger) = self.trigger.lock().unwrap().take() {
+            trigger.trigger();
+        }
+        self.sent.fetch_add(1, Ordering::SeqCst);
+        Ok(SentEmail::default())
+    }
+}
+
+#[tokio::test]
+async fn the_server_stops_once_a_shutdown_is_requested() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    app.shutdown.trigger();
+
+    // Assert
+    let outcome = tokio::time::timeout(Duration::from_secs(5), app.server)
+        .await
+        .expect("The server did not stop in time")
+        .unwrap();
+    assert!(outcome.is_ok());
+    let response = reqwest::get(format!("{}/health_check", app.address)).await;
+    assert!(response.is_err());
+}
+
+#[tokio::test]
+async fn delivery_workers_finish_the_batch_in_flight_before_stopping() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::all_succeed().with_delay(Duration::from_secs(1)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    }))
+    .await;
+
+    let (trigger, shutdown) = shutdown::channel();
+    let workers = tokio::spawn(issue_delivery_worker::run_worker_until_stopped(
+        app.configuration.clone(),
+        shutdown,
+    ));
+
+    // Wait until the batch is on its way to Postmark
+    while !app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .any(|r| r.url.path() == "/email/batch")
+    {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // Act
+    trigger.trigger();
+
+    // Assert
+    let outcome = tokio::time::timeout(Duration::from_secs(10), workers)
+        .await
+        .expect("The delivery workers did not stop in time")
+        .unwrap();
+    assert!(outcome.is_ok());
+    let queued = sqlx::query!("SELECT COUNT(*) AS count FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, Some(0));
+}
+
+#[tokio::test]
+async fn a_batch_stops_at_shutdown_and_keeps_the_rest_queued() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..3 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    }))
+    .await;
+    let (trigger, shutdown) = shutdown::channel();
+    let email_client = ShutdownOnFirstEmail {
+        trigger: Mutex::new(Some(trigger)),
+        sent: AtomicUsize::new(0),
+    };
+
+    // Act
+    try_execute_task(
+        &app.db_pool,
+        &email_client,
+        &ApplicationBaseUrl(app.base_url.clone()),
+        &HmacSecret(app.hmac_secret.clone()),
+        &app.configuration.delivery_worker,
+        &shutdown,
+    )
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(email_client.sent.load(Ordering::SeqCst), 1);
+    let queued = sqlx::query!("SELECT COUNT(*) AS count FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, Some(2));
+    let deliveries = sqlx::query!("SELECT COUNT(*) AS count FROM issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.count, Some(1));
+}
+
+#[tokio::test]
+async fn delivery_workers_stop_listening_for_new_tasks_on_shutdown() {
+    // Arrange
+    let app = spawn_app().await;
+    let (trigger, shutdown) = shutdown::channel();
+    let workers = tokio::spawn(issue_delivery_worker::run_worker_until_stopped(
+        app.configuration.clone(),
+        shutdown,
+    ));
+    while listening_connections(&app).await == 0 {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // Act
+    trigger.trigger();
+
+    // Assert
+    let outcome = tokio::time::timeout(Duration::from_secs(5), workers)
+        .await
+        .expect("The delivery workers did not stop in time")
+        .unwrap();
+    assert!(outcome.is_ok());
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while listening_connections(&app).await > 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("The queue listener is still running");
+}
+
+async fn listening_connections(app: &crate::helpers::TestApp) -> i64 {
+    sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM pg_stat_activity
+        WHERE datname = current_database() AND query LIKE 'LISTEN %'
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count
+}
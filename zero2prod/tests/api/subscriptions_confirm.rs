@@ -3,7 +3,7 @@ use wiremock::{
     Mock, ResponseTemplate,
 };
 
-use crate::helpers::spawn_app;
+use crate::helpers::{create_unconfirmed_subscriber, spawn_app};
 
 #[tokio::test]
 async fn confirmations_without_token_are_rejected_with_a_400() {
@@ -11,7 +11,7 @@ async fn confirmations_without_token_are_rejected_with_a_400() {
     let app = spawn_app().await;
 
     // Act
-    let response = reqwest::get(&format!("{}/subscriptions/confirm", app.address))
+    let response = reqwest::get(format!("{}/subscriptions/confirm", app.address))
         .await
         .unwrap();
 
@@ -103,3 +103,141 @@ async fn confirm_fails_if_there_is_a_fatal_database_error() {
     // Assert
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn confirmation_links_can_only_be_used_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+
+    // Act
+    let first = reqwest::get(confirmation_links.html.clone()).await.unwrap();
+    let second = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Assert
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 401);
+    let tokens = sqlx::query!(r#"SELECT COUNT(*) as "value!" FROM subscription_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(tokens.value, 0);
+}
+
+#[tokio::test]
+async fn expired_confirmation_links_are_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    sqlx::query!(
+        "UPDATE subscription_tokens SET created_at = $1",
+        chrono::Utc::now() - app.subscription_token_ttl - chrono::Duration::minutes(1)
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn expired_tokens_and_stale_pending_subscribers_are_cleaned_up() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    create_unconfirmed_subscriber(&app).await;
+    let stale = chrono::Utc::now() - app.subscription_token_ttl - chrono::Duration::minutes(1);
+    // Age only one of the two subscribers
+    let stale_subscriber = sqlx::query!(
+        r#"
+        UPDATE subscriptions SET subscribed_at = $1
+        WHERE id = (SELECT id FROM subscriptions LIMIT 1)
+        RETURNING id
+        "#,
+        stale
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "UPDATE subscription_tokens SET created_at = $1 WHERE subscriber_id = $2",
+        stale,
+        stale_subscriber.id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    app.clean_up_subscription_tokens().await;
+
+    // Assert
+    let subscribers = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers.len(), 1);
+    assert_ne!(subscribers[0].id, stale_subscriber.id);
+    let tokens = sqlx::query!(r#"SELECT COUNT(*) as "value!" FROM subscription_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(tokens.value, 1);
+}
+
+#[tokio::test]
+async fn stale_pending_subscribers_with_email_events_are_cleaned_up() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let response = app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "Delivery",
+            "ServerID": 23,
+            "MessageStream": "outbound",
+            "MessageID": uuid::Uuid::new_v4(),
+            "Recipient": subscriber.email,
+            "DeliveredAt": "2023-10-23T16:33:54.9070259Z",
+            "Details": "Test delivery webhook details",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let stale = chrono::Utc::now() - app.subscription_token_ttl - chrono::Duration::minutes(1);
+    sqlx::query!("UPDATE subscriptions SET subscribed_at = $1", stale)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!("UPDATE subscription_tokens SET created_at = $1", stale)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    app.clean_up_subscription_tokens().await;
+
+    // Assert
+    let subscribers = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers.count, 0);
+    let event = sqlx::query!("SELECT subscriber_id, recipient FROM email_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(event.subscriber_id.is_none());
+    assert_eq!(event.recipient, subscriber.email);
+}
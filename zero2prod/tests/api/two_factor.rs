@@ -0,0 +1,157 @@
+use chrono::Utc;
+use zero2prod::authentication::TotpSecret;
+
+use crate::{
+    helpers::{spawn_app, TestApp},
+    login::assert_is_redirect_to,
+};
+
+struct Enrollment {
+    secret: TotpSecret,
+    /// The code that turned two-factor authentication on
+    code: String,
+    recovery_codes: Vec<String>,
+}
+
+/// Turn on two-factor authentication for the test user. Leaves them logged out.
+async fn enroll(app: &TestApp) -> Enrollment {
+    app.test_user.login(app).await;
+    let response = app.post_start_two_factor().await;
+    assert_is_redirect_to(&response, "/admin/two-factor");
+    let secret = sqlx::query!(
+        r#"SELECT totp_secret AS "totp_secret!" FROM users WHERE user_id = $1"#,
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .totp_secret;
+    let html_page = app.get_two_factor_settings_html().await;
+    assert!(html_page.contains(&secret));
+    let secret = TotpSecret::parse(secret).unwrap();
+
+    let code = secret.code_at_time(Utc::now().timestamp());
+    let response = app.post_confirm_two_factor(&code).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let recovery_codes: Vec<String> = regex::Regex::new(r"<code>([a-z0-9]{5}-[a-z0-9]{5})</code>")
+        .unwrap()
+        .captures_iter(&response.text().await.unwrap())
+        .map(|captures| captures[1].to_string())
+        .collect();
+    assert_eq!(recovery_codes.len(), 10);
+
+    app.post_logout().await;
+    Enrollment {
+        secret,
+        code,
+        recovery_codes,
+    }
+}
+
+#[tokio::test]
+async fn a_password_alone_does_not_log_in_a_user_with_two_factor() {
+    // Arrange
+    let app = spawn_app().await;
+    enroll(&app).await;
+
+    // Act
+    let response = app.test_user.login(&app).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login/two-factor");
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_valid_code_completes_the_login() {
+    // Arrange
+    let app = spawn_app().await;
+    let Enrollment { secret, .. } = enroll(&app).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    // The code used to enroll can't be used again, so take the one for the next step
+    let code = secret.code_at_time(Utc::now().timestamp() + 30);
+    let response = app.post_two_factor_code(&code).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn an_invalid_or_replayed_code_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let Enrollment {
+        code: enrollment_code,
+        ..
+    } = enroll(&app).await;
+    app.test_user.login(&app).await;
+    let wrong_code = if enrollment_code == "123456" {
+        "654321"
+    } else {
+        "123456"
+    };
+
+    for code in [wrong_code, enrollment_code.as_str()] {
+        // Act
+        let response = app.post_two_factor_code(code).await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/login/two-factor");
+        let html_page = app.get_two_factor_form_html().await;
+        assert!(html_page.contains("The code is invalid or was already used."));
+        let response = app.get_admin_dashboard().await;
+        assert_is_redirect_to(&response, "/login");
+    }
+}
+
+#[tokio::test]
+async fn a_recovery_code_logs_in_only_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let Enrollment { recovery_codes, .. } = enroll(&app).await;
+
+    // Act - Part 1 - Use a recovery code
+    app.test_user.login(&app).await;
+    let response = app
+        .post_two_factor_code(&recovery_codes[0].to_uppercase())
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_two_factor_settings_html().await;
+    assert!(html_page.contains("You have 9 unused recovery codes left."));
+    app.post_logout().await;
+
+    // Act - Part 2 - Use it again
+    app.test_user.login(&app).await;
+    let response = app.post_two_factor_code(&recovery_codes[0]).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login/two-factor");
+}
+
+#[tokio::test]
+async fn two_factor_can_be_turned_off_with_the_password() {
+    // Arrange
+    let app = spawn_app().await;
+    let Enrollment { secret, .. } = enroll(&app).await;
+    app.test_user.login(&app).await;
+    app.post_two_factor_code(&secret.code_at_time(Utc::now().timestamp() + 30))
+        .await;
+
+    // Act
+    let response = app
+        .post_disable_two_factor(&serde_json::json!({
+            "current_password": &app.test_user.password,
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/two-factor");
+    app.post_logout().await;
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
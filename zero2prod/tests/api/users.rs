@@ -0,0 +1,297 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::{
+    helpers::{spawn_app, TestUser},
+    login::assert_is_redirect_to,
+};
+
+#[tokio::test]
+async fn viewers_cannot_write_or_send_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    let viewer = TestUser::generate_with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    viewer.login(&app).await;
+
+    // Act
+    let response = app
+        .post_create_issue(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    let issues = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(issues.is_empty());
+    // Reading is still allowed
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("You are logged in as viewer."));
+    assert!(!html_page.contains("/admin/newsletters"));
+}
+
+#[tokio::test]
+async fn only_owners_can_manage_users() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    editor.login(&app).await;
+
+    // Act
+    let page = app
+        .api_client
+        .get(format!("{}/admin/users", &app.address))
+        .send()
+        .await
+        .unwrap();
+    let disable = app.post_manage_user(app.test_user.user_id, "disable").await;
+
+    // Assert
+    assert_eq!(page.status().as_u16(), 403);
+    assert_eq!(disable.status().as_u16(), 403);
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(!html_page.contains("Manage users"));
+}
+
+#[tokio::test]
+async fn an_invited_user_can_choose_a_password_and_log_in() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Invite
+    let response = app
+        .post_invite_user(&serde_json::json!({
+            "username": "ursula",
+            "email": "ursula@example.com",
+            "role": "editor",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("An invitation has been sent to ursula@example.com."));
+
+    // Act - Part 2 - Accept
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let invitation_link = app.get_confirmation_links(email_request).html;
+    let invitation_token = invitation_link
+        .query_pairs()
+        .find(|(key, _)| key == "invitation_token")
+        .unwrap()
+        .1
+        .into_owned();
+    app.post_logout().await;
+    let form = reqwest::get(invitation_link).await.unwrap();
+    assert_eq!(form.status().as_u16(), 200);
+    let response = app
+        .post_accept_invitation(&serde_json::json!({
+            "invitation_token": invitation_token,
+            "password": "a very long password",
+            "password_check": "a very long password",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 3 - Log in
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "ursula",
+            "password": "a very long password",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("You are logged in as editor."));
+    // The invitation can only be used once
+    let response = app
+        .post_accept_invitation(&serde_json::json!({
+            "invitation_token": invitation_token,
+            "password": "another long password",
+            "password_check": "another long password",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn disabling_a_user_ends_their_session() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    editor.login(&app).await;
+    // The editor's session, kept aside while the owner logs in
+    let editor_client = app.api_client.clone();
+
+    // Act - Part 1 - The owner disables the editor
+    let owner_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    owner_client
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+    let response = owner_client
+        .post(format!(
+            "{}/admin/users/{}/disable",
+            &app.address, editor.user_id
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/users");
+
+    // Act - Part 2 - The editor comes back
+    let response = editor_client
+        .get(format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let response = editor.login(&app).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn owners_cannot_disable_or_delete_themselves() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let disable = app.post_manage_user(app.test_user.user_id, "disable").await;
+    let delete = app.post_manage_user(app.test_user.user_id, "delete").await;
+
+    // Assert
+    assert_is_redirect_to(&disable, "/admin/users");
+    assert_is_redirect_to(&delete, "/admin/users");
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("You can't disable or delete your own account."));
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_deleted_user_can_no_longer_log_in() {
+    // Arrange
+    let app = spawn_app().await;
+    let viewer = TestUser::generate_with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.post_manage_user(viewer.user_id, "delete").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/users");
+    app.post_logout().await;
+    let response = viewer.login(&app).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_invitation_email_escapes_the_username() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_invite_user(&serde_json::json!({
+        "username": "<b>ursula</b>",
+        "email": "ursula@example.com",
+        "role": "editor",
+    }))
+    .await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains("&lt;b&gt;ursula&lt;/b&gt;"));
+    assert!(!html_body.contains("<b>"));
+}
+
+#[tokio::test]
+async fn two_owners_cannot_disable_each_other_at_the_same_time() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let other_owner = TestUser::generate_with_role("owner");
+    other_owner.store(&app.db_pool).await;
+    // Leave the two of them as the only active owners
+    sqlx::query!(
+        "UPDATE users SET disabled_at = now() WHERE user_id <> $1 AND user_id <> $2",
+        app.test_user.user_id,
+        other_owner.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let other_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    other_client
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &other_owner.username,
+            "password": &other_owner.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Act
+    let other_disables_us = other_client
+        .post(format!(
+            "{}/admin/users/{}/disable",
+            &app.address, app.test_user.user_id
+        ))
+        .send();
+    let we_disable_other = app.post_manage_user(other_owner.user_id, "disable");
+    let (first, second) = tokio::join!(other_disables_us, we_disable_other);
+
+    // Assert - whoever lost may also be sent to the login page, but one owner stays active
+    assert_eq!(first.unwrap().status().as_u16(), 303);
+    assert_eq!(second.status().as_u16(), 303);
+    let active_owners = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM users WHERE role = 'owner' AND disabled_at IS NULL"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(active_owners.count, 1);
+}
@@ -0,0 +1,283 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock,
+};
+
+use secrecy::Secret;
+use zero2prod::configuration::WebhookSettings;
+
+use crate::helpers::{
+    create_confirmed_subscriber, spawn_app, spawn_app_with, PostmarkBatchResponder, TestApp,
+};
+
+/// Return the email of the only subscriber.
+async fn subscriber_email(app: &TestApp) -> String {
+    sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email
+}
+
+async fn subscriber_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status
+}
+
+fn bounce(email: &str, bounce_type: &str) -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "Bounce",
+        "ID": 4323372036854775807_i64,
+        "Type": bounce_type,
+        "TypeCode": 1,
+        "MessageID": uuid::Uuid::new_v4(),
+        "Email": email,
+        "Description": "The server was unable to deliver your message.",
+        "Details": "Test bounce details",
+        "BouncedAt": "2023-10-23T16:33:54.9070259Z",
+        "Inactive": false,
+    })
+}
+
+#[tokio::test]
+async fn requests_without_credentials_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/webhooks/postmark", &app.address))
+        .json(&bounce("ursula@example.com", "HardBounce"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        response.headers()["WWW-Authenticate"],
+        r#"Basic realm="webhooks""#
+    );
+}
+
+#[tokio::test]
+async fn requests_with_the_wrong_password_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/webhooks/postmark", &app.address))
+        .basic_auth(
+            &app.configuration.webhooks.postmark_username,
+            Some(uuid::Uuid::new_v4().to_string()),
+        )
+        .json(&bounce("ursula@example.com", "HardBounce"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn requests_are_rejected_while_the_password_is_the_placeholder() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.webhooks.postmark_password = Secret::new(WebhookSettings::PLACEHOLDER_PASSWORD.into());
+    })
+    .await;
+
+    // Act
+    let response = app
+        .post_postmark_webhook(&bounce("ursula@example.com", "HardBounce"))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn invalid_payloads_are_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "Bounce",
+            "Email": "ursula@example.com",
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn unsupported_events_are_acknowledged() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "Open",
+            "MessageID": uuid::Uuid::new_v4(),
+            "Recipient": "ursula@example.com",
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_hard_bounce_suppresses_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+
+    // Act
+    let response = app
+        .post_postmark_webhook(&bounce(&email, "HardBounce"))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "suppressed");
+    let event = sqlx::query!("SELECT event_type, subscriber_id FROM email_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(event.event_type, "bounce");
+    assert!(event.subscriber_id.is_some());
+}
+
+#[tokio::test]
+async fn a_spam_complaint_suppresses_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+
+    // Act
+    let response = app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "SpamComplaint",
+            "ID": 42,
+            "Type": "SpamComplaint",
+            "TypeCode": 512,
+            "MessageID": uuid::Uuid::new_v4(),
+            "Email": email,
+            "BouncedAt": "2023-10-23T16:33:54.9070259Z",
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "suppressed");
+}
+
+#[tokio::test]
+async fn a_soft_bounce_is_recorded_without_suppressing_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+
+    // Act
+    let response = app
+        .post_postmark_webhook(&bounce(&email, "SoftBounce"))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+    let events = sqlx::query!("SELECT COUNT(*) AS count FROM email_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(events.count, Some(1));
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_suppressed_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    app.post_postmark_webhook(&bounce(&email, "HardBounce"))
+        .await;
+
+    // Act
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    }))
+    .await;
+
+    // Assert
+    let queued = sqlx::query!("SELECT COUNT(*) AS count FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, Some(0));
+}
+
+#[tokio::test]
+async fn delivery_events_update_the_delivery_log() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::all_succeed())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+    let delivery =
+        sqlx::query!("SELECT subscriber_email, provider_message_id FROM issue_deliveries")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+
+    // Act
+    let response = app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "Delivery",
+            "ServerID": 23,
+            "MessageStream": "outbound",
+            "MessageID": delivery.provider_message_id,
+            "Recipient": delivery.subscriber_email,
+            "DeliveredAt": "2023-10-23T16:33:54.9070259Z",
+            "Details": "Test delivery webhook details",
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let delivery = sqlx::query!("SELECT provider_status, provider_status_at FROM issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(delivery.provider_status.as_deref(), Some("delivery"));
+    assert!(delivery.provider_status_at.is_some());
+}